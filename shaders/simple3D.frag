@@ -58,4 +58,6 @@ void main(void)
 	fog_factor = clamp(fog_factor, 0.2, 1.0);
 
 	gl_FragColor = mix(FOG_COLOR, final_color, fog_factor);
+	// The lights add up their alpha, so the material decides how see through the fragment is
+	gl_FragColor.a = diffuse_material.a;
 }
\ No newline at end of file
@@ -1,13 +1,13 @@
 pub const W_WIDTH: u32 = 1920;
 pub const W_HEIGHT: u32 = 1080;
 pub const MODEL_LOCATION: &str = "./models";
-
-pub const FINISH_LINE_Z: f32 = 130.0;
-pub const FINISH_LINE_X_START: f32 = -10.0;
-pub const FINISH_LINE_X_STOP: f32 = 20.0;
-
-pub const HALF_RING_LINE_Z: f32 = 158.0;
-pub const HALF_RING_X_START: f32 = 230.0;
-pub const HALF_RING_X_STOP: f32 = 270.0;
+pub const DEFAULT_TRACK: &str = "./tracks/default.ron";
+pub const WINDOW_TITLE: &str = "OpenGL";
+// Folder in the user data folder of the system, where lap records and ghosts are saved
+pub const USER_DATA_FOLDER: &str = "cg-assignment5";
+// Folder in the user data folder with the best laps of the time trial, one file per track
+pub const GHOST_FOLDER: &str = "ghosts";
+// Length of a random track when none is given
+pub const DEFAULT_RANDOM_TRACK_LENGTH: f32 = 1200.0;
 
 pub const SUNLIGHT_ID: &str = "SUN";
\ No newline at end of file
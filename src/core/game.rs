@@ -1,4 +1,4 @@
-use std::{cell::RefCell, collections::VecDeque, path::Path, rc::Rc, time::Instant};
+use std::{cell::RefCell, collections::{HashMap, VecDeque}, path::{Path, PathBuf}, rc::Rc, time::Instant};
 
 use crate::{
     core::{
@@ -6,8 +6,10 @@ use crate::{
         shader::Shader3D,
     },
     game_objects::{
-        cars::network_car::NetworkCar, cars::player_car::PlayerCar, environment::{skybox::Skybox, cactus::{Cactus, CactusType}},
-        track::track::Track,
+        cars::network_car::NetworkCar, cars::bot_car::BotCar, cars::bot_driver::BotDifficulty, development::{freecam_controller::FreecamController, track_editor::TrackEditor}, cars::player_car::PlayerCar, cars::player_input::PlayerInput, cars::slipstream::CarPose, environment::{skybox::Skybox, cactus::{Cactus, CactusModels}},
+        race::{lap_records::{lap_records_path, LapRecords}, race_controller::RaceController},
+        replay::{replay_file::{Replay, ReplayEvent, ReplayRecorder}, replay_player::ReplayPlayer},
+        track::{racing_line::RacingLine, track::{RespawnPoint, Track}, track_file::{track_position, Prop, TrackFile}},
     },
     network::server_connection::{NetworkEvent, ServerConnection},
     objects::{cube::Cube, mesh_model::MeshModel},
@@ -20,22 +22,28 @@ use sdl2::{
 };
 
 use super::{
-    constants::{W_HEIGHT, W_WIDTH, MODEL_LOCATION, SUNLIGHT_ID},
-    game_object::GameObject,
+    constants::{W_HEIGHT, W_WIDTH, MODEL_LOCATION, SUNLIGHT_ID, WINDOW_TITLE},
+    game_object::{Collider, GameObject},
     matrices,
     obj_loader::load_obj_file, lights::Lights, color::Color,
+    raycast::RaycastHit,
+    split_screen::split_screen_viewports,
 };
 
 const SHOW_FPS: bool = false;
+// Units per second the free camera flies in the track editor
+const EDITOR_CAMERA_SPEED: f32 = 80.0;
 
 pub struct Game<'a> {
     gl: &'a Context,
-    window: &'a Window,
+    window: &'a mut Window,
     events_loop: &'a mut EventPump,
     joystick_subsystem: &'a JoystickSubsystem,
-    pub joystick: Option<Joystick>,
+    // The joysticks of the players that are connected, by their index
+    joysticks: HashMap<u32, Joystick>,
     pub shader: Shader3D<'a>,
     pub model_matrix: RefCell<ModelMatrix>,
+    // The camera the scene is drawn with, in split screen it is set to each player's camera in turn
     pub view_matrix: RefCell<ViewMatrix>,
     pub projection_matrix: RefCell<ProjectionMatrix>,
     pub cube: Cube<'a>,
@@ -49,16 +57,80 @@ pub struct Game<'a> {
     pub car_model: Rc<MeshModel<'a>>,
     pub wheel_model: Rc<MeshModel<'a>>,
     pub lights: RefCell<Lights>,
+    pub respawn_points: RefCell<Vec<RespawnPoint>>,
+    pub car_poses: RefCell<HashMap<*const usize, CarPose>>,
+    pub arcade_mode: bool,
+    pub telemetry_path: Option<PathBuf>,
+    pub track_file: TrackFile,
+    // Where the track file was loaded from, the editor saves to it
+    pub track_path: PathBuf,
+    pub edit_mode: bool,
+    // Computer driven cars in single player
+    pub bot_count: usize,
+    pub bot_difficulty: BotDifficulty,
+    // Race against the best lap on the track
+    pub time_trial: bool,
+    // Records the race when set, the cars record themselves when they are updated
+    pub replay_recorder: RefCell<Option<ReplayRecorder>>,
+    // Played back instead of racing
+    replay: Option<Replay>,
+    pub race: RefCell<RaceController>,
+    // The laps of every player are added to the same records, saved after each lap that made the leaderboard
+    pub lap_records: RefCell<LapRecords>,
+    // None when there is nowhere to save the records, they are still kept until the game is closed
+    lap_records_path: Option<PathBuf>,
+    // How each player at this computer drives, more than one plays split screen
+    pub players: Vec<PlayerInput>,
+    // The cameras of the players, the window is split between them
+    pub player_cameras: RefCell<Vec<ViewMatrix>>,
+    // Shown in the window title for each player, like the lap time
+    pub status_texts: RefCell<Vec<String>>,
+    shown_status_text: String,
+}
+
+/// What the game is started with, from the command line
+pub struct GameOptions {
+    // None plays single player
+    pub server_address: Option<String>,
+    pub arcade_mode: bool,
+    pub telemetry_path: Option<PathBuf>,
+    pub track_file: TrackFile,
+    pub track_path: PathBuf,
+    pub edit_mode: bool,
+    pub bot_count: usize,
+    pub bot_difficulty: BotDifficulty,
+    pub time_trial: bool,
+    pub replay_recorder: Option<ReplayRecorder>,
+    pub replay: Option<Replay>,
+    // None races forever
+    pub lap_count: Option<u32>,
+    pub players: Vec<PlayerInput>,
 }
 
 impl<'a> Game<'a> {
     pub fn new(
         gl: &'a Context,
-        window: &'a Window,
+        window: &'a mut Window,
         events_loop: &'a mut EventPump,
         joystick_subsystem: &'a JoystickSubsystem,
-        server_address: Option<&str>,
+        options: GameOptions,
     ) -> Game<'a> {
+        let GameOptions {
+            server_address,
+            arcade_mode,
+            telemetry_path,
+            track_file,
+            track_path,
+            edit_mode,
+            bot_count,
+            bot_difficulty,
+            time_trial,
+            replay_recorder,
+            replay,
+            lap_count,
+            players,
+        } = options;
+
         let shader = Shader3D::new(&gl);
         let cube = Cube::new(&gl);
 
@@ -73,17 +145,33 @@ impl<'a> Game<'a> {
 
         let mut server_connection = ServerConnection::new();
 
-        match server_address {
+        match &server_address {
             Some(a) => server_connection.connect(a),
             None => (),
         }
 
+        let mut players = players;
+        if players.len() > 1 && server_connection.is_multiplayer() {
+            log::warn!("Split screen is only for single player, only the first player joins the server");
+            players.truncate(1);
+        }
+        let race = RaceController::new(lap_count, server_connection.is_multiplayer());
+
+        let lap_records_path = lap_records_path();
+        let lap_records = match &lap_records_path {
+            Some(path) => LapRecords::load(path).unwrap_or_else(|e| {
+                log::error!("{e:#}");
+                LapRecords::default()
+            }),
+            None => LapRecords::default(),
+        };
+
         Game {
             gl,
             window,
             events_loop,
             joystick_subsystem,
-            joystick: None,
+            joysticks: HashMap::new(),
             shader,
             model_matrix: RefCell::new(model_matrix),
             view_matrix: RefCell::new(view_matrix),
@@ -100,6 +188,25 @@ impl<'a> Game<'a> {
             car_model: Rc::new(MeshModel::new(gl)),
             wheel_model: Rc::new(MeshModel::new(gl)),
             lights: RefCell::new(Lights::new()),
+            respawn_points: RefCell::new(Vec::new()),
+            car_poses: RefCell::new(HashMap::new()),
+            arcade_mode,
+            telemetry_path,
+            track_file,
+            track_path,
+            edit_mode,
+            bot_count,
+            bot_difficulty,
+            time_trial,
+            replay_recorder: RefCell::new(replay_recorder),
+            replay,
+            race: RefCell::new(race),
+            lap_records: RefCell::new(lap_records),
+            lap_records_path,
+            player_cameras: RefCell::new(Vec::new()),
+            status_texts: RefCell::new(vec![String::new(); players.len()]),
+            shown_status_text: String::new(),
+            players,
         }
     }
 
@@ -125,34 +232,89 @@ impl<'a> Game<'a> {
         drop(lights);
 
         // Create the level
-        self.add_game_object(Skybox::new(self.gl, self));
-        self.add_game_object(Track::new(self.gl, self));
-        self.add_game_object(PlayerCar::new(
-            self.car_model.clone(),
-            self.wheel_model.clone(),
-            self.gl,
-            self,
-        ));
-
-        // Create cactuses
-        self.add_game_object(Cactus::new(Vector3::new(20.0, 30.0, 200.0), 0.0, CactusType::Small, self.gl, self));
-        self.add_game_object(Cactus::new(Vector3::new(40.0, 30.0, 220.0), 100f32.to_radians(), CactusType::Large, self.gl, self));
-        self.add_game_object(Cactus::new(Vector3::new(50.0, 30.0, 250.0), 0.0, CactusType::Small, self.gl, self));
-        self.add_game_object(Cactus::new(Vector3::new(75.0, 30.0, 250.0), 0.0, CactusType::Small, self.gl, self));
-        self.add_game_object(Cactus::new(Vector3::new(295.0, 30.0, 232.0), 0.0, CactusType::Large, self.gl, self));
-        self.add_game_object(Cactus::new(Vector3::new(234.0, 30.0, 188.0), 75f32.to_radians(), CactusType::Small, self.gl, self));
-        self.add_game_object(Cactus::new(Vector3::new(297.0, 30.0, 82.0), 0.0, CactusType::Large, self.gl, self));
-        self.add_game_object(Cactus::new(Vector3::new(68.0, 30.0, 51.0), 0.0, CactusType::Small, self.gl, self));
-        self.add_game_object(Cactus::new(Vector3::new(64.0, 30.0, 11.0), 45f32.to_radians(), CactusType::Small, self.gl, self));
-        self.add_game_object(Cactus::new(Vector3::new(58.0, 30.0, -19.0), 0.0, CactusType::Large, self.gl, self));
-        self.add_game_object(Cactus::new(Vector3::new(-11.0, 30.0, 283.0), 0.0, CactusType::Large, self.gl, self));
-        self.add_game_object(Cactus::new(Vector3::new(332.0, 30.0, 265.0), 45f32.to_radians(), CactusType::Small, self.gl, self));
-        self.add_game_object(Cactus::new(Vector3::new(346.0, 30.0, 97.0), 0.0, CactusType::Small, self.gl, self));
-        self.add_game_object(Cactus::new(Vector3::new(147.0, 30.0, -32.0), 75f32.to_radians(), CactusType::Large, self.gl, self));
-        self.add_game_object(Cactus::new(Vector3::new(64.0, 30.0, -96.0), 0.0, CactusType::Small, self.gl, self));
-        self.add_game_object(Cactus::new(Vector3::new(-1.0, 30.0, -104.0), 0.0, CactusType::Small, self.gl, self));
-        self.add_game_object(Cactus::new(Vector3::new(101.0, 30.0, -23.0), 45f32.to_radians(), CactusType::Large, self.gl, self));
-        self.add_game_object(Cactus::new(Vector3::new(125.0, 30.0, 11.0), 0.0, CactusType::Large, self.gl, self));
+        self.add_game_object(Skybox::new(&self.track_file.skybox, self.gl, self));
+
+        if self.edit_mode {
+            self.add_game_object(TrackEditor::new(self.track_file.clone(), self.gl, self));
+            self.add_game_object(FreecamController::new(self.gl).with_speed(EDITOR_CAMERA_SPEED));
+            return;
+        }
+
+        self.add_game_object(Track::new(&self.track_file, self.gl, self));
+        match self.replay.take() {
+            Some(replay) => self.add_game_object(ReplayPlayer::new(
+                replay,
+                self.car_model.clone(),
+                self.wheel_model.clone(),
+                self.gl,
+                self,
+            )),
+            None => {
+                for (player, input) in self.players.clone().into_iter().enumerate() {
+                    self.player_cameras.get_mut().push(ViewMatrix::new());
+                    self.add_game_object(PlayerCar::new(
+                        player,
+                        input,
+                        self.car_model.clone(),
+                        self.wheel_model.clone(),
+                        self.gl,
+                        self,
+                    ));
+                }
+                self.add_bots();
+            }
+        }
+
+        // Create the props
+        let cactus_models = CactusModels::load(self.gl, self);
+        for prop in self.track_file.props.clone() {
+            match prop {
+                Prop::Cactus { position, rotation, kind } => self.add_game_object(Cactus::new(
+                    track_position(position),
+                    rotation.to_radians(),
+                    kind,
+                    &cactus_models,
+                )),
+            }
+        }
+    }
+
+    /// Bots start behind the players on the grid and drive the racing line of the track
+    fn add_bots(&mut self) {
+        if self.bot_count == 0 {
+            return;
+        }
+        if self.server_connection.is_multiplayer() {
+            log::warn!("Bots can only race in single player, starting without them");
+            return;
+        }
+
+        let centre_line = self.respawn_points.borrow().iter().map(|p| p.position).collect::<Vec<_>>();
+        let racing_line = Rc::new(RacingLine::new(&centre_line));
+
+        let players = self.players.len();
+        let grid_spots = self.track_file.start_grid.len().saturating_sub(players);
+        if self.bot_count > grid_spots {
+            log::warn!("The start grid only has room for {grid_spots} bots");
+        }
+
+        for bot_id in 1..=self.bot_count.min(grid_spots) {
+            let (position, angle) = match self.track_file.grid_position(players + bot_id - 1) {
+                Some(p) => p,
+                None => break,
+            };
+            self.add_game_object(BotCar::new(
+                bot_id,
+                position,
+                angle,
+                racing_line.clone(),
+                self.bot_difficulty,
+                self.car_model.clone(),
+                self.wheel_model.clone(),
+                self.gl,
+                self,
+            ));
+        }
     }
 
     #[inline(always)]
@@ -161,6 +323,87 @@ impl<'a> Game<'a> {
             .push(Box::new(RefCell::new(object)) as Box<RefCell<dyn GameObject<'a>>>);
     }
 
+    /// The joystick with the index if it is connected
+    pub fn joystick(&self, index: u32) -> Option<&Joystick> {
+        self.joysticks.get(&index).filter(|j| j.attached())
+    }
+
+    /// Size of the window in pixels
+    pub fn window_size(&self) -> (u32, u32) {
+        self.window.size()
+    }
+
+    /// Add an event that happened to the car to the replay, if the race is recorded
+    pub fn record_replay_event(&self, car: *const usize, event: ReplayEvent) {
+        if let Some(recorder) = self.replay_recorder.borrow_mut().as_mut() {
+            recorder.record_event(car, event);
+        }
+    }
+
+    pub fn save_lap_records(&self) {
+        if let Some(path) = &self.lap_records_path {
+            if let Err(e) = self.lap_records.borrow().save(path) {
+                log::error!("{e:#}");
+            }
+        }
+    }
+
+    /// Cast a ray against the colliders of all game objects except the one given in ignore
+    pub fn raycast(
+        &self,
+        origin: &Vector3<f32>,
+        dir: &Vector3<f32>,
+        max_dist: f32,
+        ignore: *const usize,
+    ) -> Option<RaycastHit> {
+        self.query_colliders(ignore, |collider| collider.raycast(origin, dir, max_dist))
+    }
+
+    /// Sweep a sphere against the colliders of all game objects except the one given in ignore
+    pub fn sphere_cast(
+        &self,
+        origin: &Vector3<f32>,
+        dir: &Vector3<f32>,
+        radius: f32,
+        max_dist: f32,
+        ignore: *const usize,
+    ) -> Option<RaycastHit> {
+        self.query_colliders(ignore, |collider| {
+            collider.sphere_cast(origin, dir, radius, max_dist)
+        })
+    }
+
+    fn query_colliders(
+        &self,
+        ignore: *const usize,
+        query: impl Fn(&Collider) -> Option<RaycastHit>,
+    ) -> Option<RaycastHit> {
+        let mut closest = None;
+
+        for object in &self.game_objects {
+            let object_ptr = object.as_ptr() as *const _ as *const usize;
+            if object_ptr == ignore {
+                continue;
+            }
+
+            let object = match object.try_borrow() {
+                Ok(o) => o,
+                Err(e) => {
+                    log::error!("Failed to query the collider of a game object. {e}");
+                    continue;
+                }
+            };
+
+            let hit = query(&object.collision_info()).map(|h| RaycastHit {
+                object: object_ptr,
+                ..h
+            });
+            closest = RaycastHit::closest(closest, hit);
+        }
+
+        closest
+    }
+
     pub fn load_texture(&self, path_string: &str, repeat: bool) -> NativeTexture {
         let loader = sdl2::rwops::RWops::from_file(Path::new(path_string), "r")
             .expect("Failed to load texture");
@@ -267,24 +510,15 @@ impl<'a> Game<'a> {
             self.frame_sum += 1;
         }
 
-        // Joystick was just connected
-        if self
-            .joystick_subsystem
-            .num_joysticks()
-            .map(|v| v > 0)
-            .unwrap_or(false)
-            && self.joystick.is_none()
-        {
-            self.joystick = self.joystick_subsystem.open(0).ok();
-        }
-        // Joystick was disconnected
-        else if self
-            .joystick
-            .as_ref()
-            .map(|j| !j.attached())
-            .unwrap_or(false)
-        {
-            self.joystick = None;
+        // Open the joysticks of the players when they are connected and forget them when they are disconnected
+        self.joysticks.retain(|_, j| j.attached());
+        let connected = self.joystick_subsystem.num_joysticks().unwrap_or(0);
+        for index in self.players.iter().filter_map(|p| p.joystick) {
+            if index < connected && !self.joysticks.contains_key(&index) {
+                if let Ok(joystick) = self.joystick_subsystem.open(index) {
+                    self.joysticks.insert(index, joystick);
+                }
+            }
         }
 
         // Recieve and handle packages for multiplayer
@@ -327,10 +561,15 @@ impl<'a> Game<'a> {
                     ));
                     self.server_connection.game_events.get_mut().pop_front();
                 }
-                Some(PlayerDisconnected { .. } | MoveToStartPos) | None => break,
+                Some(PlayerDisconnected { .. } | LapCompleted { .. } | MoveToStartPos) | None => break,
             }
         }
 
+        if let Some(recorder) = self.replay_recorder.get_mut() {
+            recorder.start_frame(self.delta_time);
+        }
+        self.race.get_mut().update(self.delta_time);
+
         for object in &self.game_objects {
             object.borrow_mut().update(self, self.gl);
         }
@@ -344,32 +583,75 @@ impl<'a> Game<'a> {
             self.gl.clear_color(0.03, 0.04, 0.13, 1.0);
             self.gl
                 .clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT);
-            self.gl.viewport(
-                0,
-                0,
-                self.window.size().0 as i32,
-                self.window.size().1 as i32,
-            );
         }
 
-        let view_matrix = self.view_matrix.get_mut();
+        self.lights.get_mut().update_lights(&self.shader);
 
-        self.shader
-            .set_view_matrix(view_matrix.get_matrix().as_slice());
-        self.shader
-            .set_projection_matrix(self.projection_matrix.get_mut().get_matrix().as_slice());
+        // Without player cameras, like in the editor, the whole window is drawn with the view matrix
+        let (width, height) = self.window.size();
+        let viewports = split_screen_viewports(width, height, self.player_cameras.get_mut().len());
+        for (player, viewport) in viewports.iter().enumerate() {
+            if let Some(camera) = self.player_cameras.get_mut().get(player) {
+                *self.view_matrix.get_mut() = camera.clone();
+            }
+            unsafe {
+                self.gl.viewport(viewport.x, viewport.y, viewport.width, viewport.height);
+            }
+            self.projection_matrix
+                .get_mut()
+                .set_perspective(60.0, viewport.aspect_ratio(), 0.5, 500.0);
 
-        self.model_matrix.get_mut().load_identity();
+            let view_matrix = self.view_matrix.get_mut();
 
-        self.lights.get_mut().update_lights(&self.shader);
-        self.shader
-            .set_eye_position(view_matrix.eye.x, view_matrix.eye.y, view_matrix.eye.z);
+            self.shader
+                .set_view_matrix(view_matrix.get_matrix().as_slice());
+            self.shader
+                .set_projection_matrix(self.projection_matrix.get_mut().get_matrix().as_slice());
 
-        for object in &self.game_objects {
-            object.borrow().display(self, self.gl);
+            self.model_matrix.get_mut().load_identity();
+
+            self.shader
+                .set_eye_position(view_matrix.eye.x, view_matrix.eye.y, view_matrix.eye.z);
+
+            for object in &self.game_objects {
+                object.borrow().display(self, self.gl);
+            }
+
+            // See through objects are blended over everything else and don't hide each other
+            unsafe {
+                self.gl.depth_mask(false);
+            }
+            for object in &self.game_objects {
+                object.borrow().display_translucent(self, self.gl);
+            }
+            unsafe {
+                self.gl.depth_mask(true);
+            }
         }
 
         self.window.gl_swap_window();
+
+        let status_texts = self.status_texts.get_mut();
+        let status_text = match status_texts.len() {
+            1 => status_texts[0].clone(),
+            _ => status_texts
+                .iter()
+                .enumerate()
+                .filter(|(_, text)| !text.is_empty())
+                .map(|(player, text)| format!("P{} {text}", player + 1))
+                .collect::<Vec<_>>()
+                .join(" || "),
+        };
+        if status_text != self.shown_status_text {
+            let title = match status_text.is_empty() {
+                true => WINDOW_TITLE.to_string(),
+                false => format!("{WINDOW_TITLE} | {status_text}"),
+            };
+            self.shown_status_text = status_text;
+            if let Err(e) = self.window.set_title(&title) {
+                log::error!("Failed to set the window title. {e}");
+            }
+        }
     }
 
     pub fn main(&mut self) {
@@ -2,16 +2,18 @@ use glow::*;
 use nalgebra::Vector3;
 use sdl2::event::Event;
 
-use super::game::Game;
+use super::{game::Game, surface::Surface};
 
 #[derive(Clone)]
 pub enum Collider {
     NoCollision,
-    HeightCollider(f32),
+    HeightCollider(f32, Surface),
     // minX, minY, minZ, maxX, maxY, maxZ
     BoxCollider(f32, f32, f32, f32, f32, f32),
     MultiCollider(Vec<Collider>),
     InfiniteYPlaneCollider(Vector3<f32>, Vector3<f32>),
+    // Triangles of a drivable surface, like the road
+    SurfaceCollider(Vec<[Vector3<f32>; 3]>, Surface),
 }
 
 pub trait GameObject<'a> {
@@ -22,4 +24,7 @@ pub trait GameObject<'a> {
     fn on_event(&mut self, game: &Game, event: &Event);
     fn update(&mut self, game: &Game, gl: &'a Context);
     fn display(&self, game: &Game, gl: &'a Context);
+
+    /// Drawn after every object has been displayed, for things that are see through
+    fn display_translucent(&self, _game: &Game, _gl: &'a Context) {}
 }
\ No newline at end of file
@@ -84,6 +84,7 @@ impl ModelMatrix {
     }
 }
 
+#[derive(Clone)]
 pub struct ViewMatrix {
     pub eye: Vector3<f32>,
     pub u: Vector3<f32>,
@@ -6,4 +6,7 @@ pub mod lights;
 pub mod material;
 pub mod matrices;
 pub mod obj_loader;
+pub mod raycast;
 pub mod shader;
+pub mod split_screen;
+pub mod surface;
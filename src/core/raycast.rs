@@ -0,0 +1,436 @@
+use std::ptr;
+
+use nalgebra::{Vector2, Vector3};
+
+use super::{game_object::Collider, surface::Surface};
+
+#[derive(Clone, Copy, Debug)]
+pub struct RaycastHit {
+    pub point: Vector3<f32>,
+    pub normal: Vector3<f32>,
+    // Distance the ray (or the center of the sphere) travelled before the hit
+    pub distance: f32,
+    pub surface: Surface,
+    // The game object that owns the collider, null when a collider is queried directly
+    pub object: *const usize,
+}
+
+impl RaycastHit {
+    fn new(point: Vector3<f32>, normal: Vector3<f32>, distance: f32) -> RaycastHit {
+        RaycastHit {
+            point,
+            normal,
+            distance,
+            surface: Surface::Asphalt,
+            object: ptr::null(),
+        }
+    }
+
+    pub fn closest(a: Option<RaycastHit>, b: Option<RaycastHit>) -> Option<RaycastHit> {
+        match (a, b) {
+            (Some(a), Some(b)) => Some(if a.distance <= b.distance { a } else { b }),
+            (a, None) => a,
+            (None, b) => b,
+        }
+    }
+}
+
+impl Collider {
+    /// Cast a ray from origin along dir and return the closest hit within max_dist
+    pub fn raycast(
+        &self,
+        origin: &Vector3<f32>,
+        dir: &Vector3<f32>,
+        max_dist: f32,
+    ) -> Option<RaycastHit> {
+        let dir = dir.try_normalize(f32::EPSILON)?;
+
+        use Collider::*;
+        match self {
+            &HeightCollider(y, surface) => {
+                raycast_height(origin, &dir, max_dist, y).map(|h| RaycastHit { surface, ..h })
+            }
+            &BoxCollider(min_x, min_y, min_z, max_x, max_y, max_z) => raycast_box(
+                origin,
+                &dir,
+                max_dist,
+                &Vector3::new(min_x, min_y, min_z),
+                &Vector3::new(max_x, max_y, max_z),
+            ),
+            InfiniteYPlaneCollider(p0, p1) => raycast_y_plane(origin, &dir, max_dist, p0, p1),
+            SurfaceCollider(triangles, surface) => triangles
+                .iter()
+                .fold(None, |closest, triangle| {
+                    RaycastHit::closest(
+                        closest,
+                        raycast_triangle(origin, &dir, max_dist, triangle, 0.0),
+                    )
+                })
+                .map(|h| RaycastHit {
+                    surface: *surface,
+                    ..h
+                }),
+            MultiCollider(c) => c.iter().fold(None, |closest, collider| {
+                RaycastHit::closest(closest, collider.raycast(origin, &dir, max_dist))
+            }),
+            NoCollision => None,
+        }
+    }
+
+    /// Sweep a sphere from origin along dir and return the first surface it touches within max_dist
+    pub fn sphere_cast(
+        &self,
+        origin: &Vector3<f32>,
+        dir: &Vector3<f32>,
+        radius: f32,
+        max_dist: f32,
+    ) -> Option<RaycastHit> {
+        let dir = dir.try_normalize(f32::EPSILON)?;
+
+        use Collider::*;
+        let hit = match self {
+            &HeightCollider(y, surface) => raycast_height(origin, &dir, max_dist, y + radius)
+                .map(|h| RaycastHit { surface, ..h }),
+            // The box is expanded by the radius, which treats the edges of the box as square instead of rounded
+            &BoxCollider(min_x, min_y, min_z, max_x, max_y, max_z) => raycast_box(
+                origin,
+                &dir,
+                max_dist,
+                &Vector3::new(min_x - radius, min_y - radius, min_z - radius),
+                &Vector3::new(max_x + radius, max_y + radius, max_z + radius),
+            ),
+            InfiniteYPlaneCollider(p0, p1) => {
+                let line = (p1 - p0).xz().try_normalize(f32::EPSILON)?;
+                let mut n = Vector2::new(-line.y, line.x);
+                if n.dot(&(origin.xz() - p0.xz())) < 0.0 {
+                    n = -n;
+                }
+
+                // Move the wall towards the sphere and extend it past its ends
+                let offset = Vector3::new(n.x, 0.0, n.y) * radius;
+                let extend = Vector3::new(line.x, 0.0, line.y) * radius;
+                raycast_y_plane(
+                    origin,
+                    &dir,
+                    max_dist,
+                    &(p0 + offset - extend),
+                    &(p1 + offset + extend),
+                )
+            }
+            // The triangles are moved towards the sphere, so the edges are not rounded
+            SurfaceCollider(triangles, surface) => triangles
+                .iter()
+                .fold(None, |closest, triangle| {
+                    RaycastHit::closest(
+                        closest,
+                        raycast_triangle(origin, &dir, max_dist, triangle, radius),
+                    )
+                })
+                .map(|h| RaycastHit {
+                    surface: *surface,
+                    ..h
+                }),
+            MultiCollider(c) => {
+                return c.iter().fold(None, |closest, collider| {
+                    RaycastHit::closest(
+                        closest,
+                        collider.sphere_cast(origin, &dir, radius, max_dist),
+                    )
+                })
+            }
+            NoCollision => None,
+        };
+
+        // The ray hit the surface offset by the radius, move the hit point back onto the actual surface
+        hit.map(|h| RaycastHit {
+            point: h.point - h.normal * radius,
+            ..h
+        })
+    }
+}
+
+fn raycast_height(
+    origin: &Vector3<f32>,
+    dir: &Vector3<f32>,
+    max_dist: f32,
+    y: f32,
+) -> Option<RaycastHit> {
+    let up = Vector3::new(0.0, 1.0, 0.0);
+
+    // Everything below the height collider is solid
+    if origin.y <= y {
+        return Some(RaycastHit::new(*origin, up, 0.0));
+    }
+
+    if dir.y >= 0.0 {
+        return None;
+    }
+
+    let t = (y - origin.y) / dir.y;
+    if t > max_dist {
+        return None;
+    }
+
+    Some(RaycastHit::new(origin + dir * t, up, t))
+}
+
+fn raycast_box(
+    origin: &Vector3<f32>,
+    dir: &Vector3<f32>,
+    max_dist: f32,
+    min: &Vector3<f32>,
+    max: &Vector3<f32>,
+) -> Option<RaycastHit> {
+    let mut t_enter = 0.0f32;
+    let mut t_exit = max_dist;
+    let mut normal = Vector3::zeros();
+
+    // Slab test, one axis at a time
+    for axis in 0..3 {
+        if dir[axis].abs() < f32::EPSILON {
+            if origin[axis] < min[axis] || origin[axis] > max[axis] {
+                return None;
+            }
+            continue;
+        }
+
+        let t1 = (min[axis] - origin[axis]) / dir[axis];
+        let t2 = (max[axis] - origin[axis]) / dir[axis];
+        let (t_near, t_far) = if t1 < t2 { (t1, t2) } else { (t2, t1) };
+
+        if t_near > t_enter {
+            t_enter = t_near;
+            normal = Vector3::zeros();
+            normal[axis] = -dir[axis].signum();
+        }
+        t_exit = t_exit.min(t_far);
+
+        if t_enter > t_exit {
+            return None;
+        }
+    }
+
+    // The ray started inside the box
+    if normal == Vector3::zeros() {
+        return Some(RaycastHit::new(*origin, -dir, 0.0));
+    }
+
+    Some(RaycastHit::new(origin + dir * t_enter, normal, t_enter))
+}
+
+fn raycast_y_plane(
+    origin: &Vector3<f32>,
+    dir: &Vector3<f32>,
+    max_dist: f32,
+    p0: &Vector3<f32>,
+    p1: &Vector3<f32>,
+) -> Option<RaycastHit> {
+    let cross = |a: &Vector2<f32>, b: &Vector2<f32>| a.x * b.y - a.y * b.x;
+
+    let o = origin.xz();
+    let d = dir.xz();
+    let s = (p1 - p0).xz();
+
+    let denominator = cross(&d, &s);
+    if denominator.abs() < f32::EPSILON {
+        return None;
+    }
+
+    let to_line = p0.xz() - o;
+    let t = cross(&to_line, &s) / denominator;
+    let u = cross(&to_line, &d) / denominator;
+
+    if t < 0.0 || t > max_dist || !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let mut n = Vector2::new(-s.y, s.x).normalize();
+    if n.dot(&d) > 0.0 {
+        n = -n;
+    }
+
+    Some(RaycastHit::new(
+        origin + dir * t,
+        Vector3::new(n.x, 0.0, n.y),
+        t,
+    ))
+}
+
+fn raycast_triangle(
+    origin: &Vector3<f32>,
+    dir: &Vector3<f32>,
+    max_dist: f32,
+    triangle: &[Vector3<f32>; 3],
+    offset: f32,
+) -> Option<RaycastHit> {
+    let e1 = triangle[1] - triangle[0];
+    let e2 = triangle[2] - triangle[0];
+
+    let mut normal = e1.cross(&e2).try_normalize(f32::EPSILON)?;
+    if normal.dot(dir) > 0.0 {
+        normal = -normal;
+    }
+    let p0 = triangle[0] + normal * offset;
+
+    // Möller–Trumbore intersection
+    let p = dir.cross(&e2);
+    let det = e1.dot(&p);
+    if det.abs() < f32::EPSILON {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    let to_origin = origin - p0;
+    let u = to_origin.dot(&p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = to_origin.cross(&e1);
+    let v = dir.dot(&q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = e2.dot(&q) * inv_det;
+    if t < 0.0 || t > max_dist {
+        return None;
+    }
+
+    Some(RaycastHit::new(origin + dir * t, normal, t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: Vector3<f32>, b: Vector3<f32>) {
+        assert!((a - b).norm() < 1e-4, "{a:?} is not {b:?}");
+    }
+
+    fn down() -> Vector3<f32> {
+        Vector3::new(0.0, -1.0, 0.0)
+    }
+
+    #[test]
+    fn height_collider() {
+        let ground = Collider::HeightCollider(30.0, Surface::Sand);
+        let origin = Vector3::new(1.0, 40.0, 2.0);
+
+        let hit = ground.raycast(&origin, &down(), 20.0).unwrap();
+        assert_near(hit.point, Vector3::new(1.0, 30.0, 2.0));
+        assert_near(hit.normal, Vector3::new(0.0, 1.0, 0.0));
+        assert!((hit.distance - 10.0).abs() < 1e-4);
+        assert_eq!(hit.surface, Surface::Sand);
+
+        let hit = ground.sphere_cast(&origin, &down(), 2.0, 20.0).unwrap();
+        assert_near(hit.point, Vector3::new(1.0, 30.0, 2.0));
+        assert!((hit.distance - 8.0).abs() < 1e-4);
+
+        assert!(ground.raycast(&origin, &down(), 9.0).is_none());
+        assert!(ground.sphere_cast(&origin, &down(), 2.0, 7.0).is_none());
+        // Parallel to the ground
+        assert!(ground.raycast(&origin, &Vector3::new(1.0, 0.0, 0.0), 100.0).is_none());
+        assert!(ground.sphere_cast(&origin, &Vector3::new(1.0, 0.0, 0.0), 2.0, 100.0).is_none());
+    }
+
+    #[test]
+    fn box_collider() {
+        let cube = Collider::BoxCollider(-1.0, -1.0, -1.0, 1.0, 1.0, 1.0);
+        let origin = Vector3::new(-5.0, 0.5, 0.0);
+        let right = Vector3::new(1.0, 0.0, 0.0);
+
+        let hit = cube.raycast(&origin, &right, 10.0).unwrap();
+        assert_near(hit.point, Vector3::new(-1.0, 0.5, 0.0));
+        assert_near(hit.normal, Vector3::new(-1.0, 0.0, 0.0));
+        assert!((hit.distance - 4.0).abs() < 1e-4);
+
+        let hit = cube.sphere_cast(&origin, &right, 0.5, 10.0).unwrap();
+        assert_near(hit.point, Vector3::new(-1.0, 0.5, 0.0));
+        assert!((hit.distance - 3.5).abs() < 1e-4);
+
+        assert!(cube.raycast(&origin, &right, 3.9).is_none());
+        assert!(cube.sphere_cast(&origin, &right, 0.5, 3.4).is_none());
+        // Parallel to the top face, above the box
+        let above = Vector3::new(-5.0, 2.0, 0.0);
+        assert!(cube.raycast(&above, &right, 10.0).is_none());
+        assert!(cube.sphere_cast(&above, &right, 0.5, 10.0).is_none());
+    }
+
+    #[test]
+    fn y_plane_collider() {
+        let wall = Collider::InfiniteYPlaneCollider(Vector3::new(0.0, 0.0, -5.0), Vector3::new(0.0, 0.0, 5.0));
+        let origin = Vector3::new(-4.0, 1.0, 1.0);
+        let right = Vector3::new(1.0, 0.0, 0.0);
+
+        let hit = wall.raycast(&origin, &right, 10.0).unwrap();
+        assert_near(hit.point, Vector3::new(0.0, 1.0, 1.0));
+        assert_near(hit.normal, Vector3::new(-1.0, 0.0, 0.0));
+        assert!((hit.distance - 4.0).abs() < 1e-4);
+
+        let hit = wall.sphere_cast(&origin, &right, 1.0, 10.0).unwrap();
+        assert_near(hit.point, Vector3::new(0.0, 1.0, 1.0));
+        assert!((hit.distance - 3.0).abs() < 1e-4);
+
+        assert!(wall.raycast(&origin, &right, 3.9).is_none());
+        assert!(wall.sphere_cast(&origin, &right, 1.0, 2.9).is_none());
+        // Along the wall
+        let along = Vector3::new(0.0, 0.0, 1.0);
+        assert!(wall.raycast(&origin, &along, 100.0).is_none());
+        assert!(wall.sphere_cast(&origin, &along, 1.0, 100.0).is_none());
+    }
+
+    #[test]
+    fn surface_collider() {
+        let road = Collider::SurfaceCollider(
+            vec![[Vector3::new(-10.0, 30.0, -10.0), Vector3::new(10.0, 30.0, -10.0), Vector3::new(0.0, 30.0, 10.0)]],
+            Surface::Kerb,
+        );
+        let origin = Vector3::new(0.0, 35.0, 0.0);
+
+        let hit = road.raycast(&origin, &down(), 10.0).unwrap();
+        assert_near(hit.point, Vector3::new(0.0, 30.0, 0.0));
+        assert_near(hit.normal, Vector3::new(0.0, 1.0, 0.0));
+        assert!((hit.distance - 5.0).abs() < 1e-4);
+        assert_eq!(hit.surface, Surface::Kerb);
+
+        let hit = road.sphere_cast(&origin, &down(), 1.0, 10.0).unwrap();
+        assert_near(hit.point, Vector3::new(0.0, 30.0, 0.0));
+        assert!((hit.distance - 4.0).abs() < 1e-4);
+
+        assert!(road.raycast(&origin, &down(), 4.9).is_none());
+        assert!(road.sphere_cast(&origin, &down(), 1.0, 3.9).is_none());
+        // Past the edge of the triangle
+        assert!(road.raycast(&Vector3::new(20.0, 35.0, 0.0), &down(), 10.0).is_none());
+        // Parallel to the road
+        let forward = Vector3::new(0.0, 0.0, 1.0);
+        assert!(road.raycast(&origin, &forward, 100.0).is_none());
+        assert!(road.sphere_cast(&origin, &forward, 1.0, 100.0).is_none());
+    }
+
+    #[test]
+    fn multi_collider_returns_the_nearest_hit() {
+        let colliders = Collider::MultiCollider(vec![
+            Collider::HeightCollider(0.0, Surface::Sand),
+            Collider::BoxCollider(-1.0, 0.0, -1.0, 1.0, 2.0, 1.0),
+            Collider::NoCollision,
+        ]);
+        let origin = Vector3::new(0.0, 10.0, 0.0);
+
+        let hit = colliders.raycast(&origin, &down(), 20.0).unwrap();
+        assert_near(hit.point, Vector3::new(0.0, 2.0, 0.0));
+        assert_near(hit.normal, Vector3::new(0.0, 1.0, 0.0));
+        assert!((hit.distance - 8.0).abs() < 1e-4);
+        assert_eq!(hit.surface, Surface::Asphalt);
+
+        let hit = colliders.sphere_cast(&origin, &down(), 1.0, 20.0).unwrap();
+        assert_near(hit.point, Vector3::new(0.0, 2.0, 0.0));
+        assert!((hit.distance - 7.0).abs() < 1e-4);
+
+        // Next to the box only the ground is hit
+        let hit = colliders.raycast(&Vector3::new(5.0, 10.0, 0.0), &down(), 20.0).unwrap();
+        assert_eq!(hit.surface, Surface::Sand);
+        assert!(colliders.raycast(&origin, &down(), 7.9).is_none());
+        assert!(colliders.raycast(&origin, &Vector3::new(0.0, 0.0, 1.0), 100.0).is_none());
+    }
+}
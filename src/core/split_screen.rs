@@ -0,0 +1,59 @@
+/// A part of the window in pixels, counted from the bottom left corner like OpenGL does
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Viewport {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Viewport {
+    pub fn aspect_ratio(&self) -> f32 {
+        self.width.max(1) as f32 / self.height.max(1) as f32
+    }
+}
+
+/// Split the window between the players. Two players get the top and bottom half, so both see far
+/// to the sides, and three or four get a quarter each with player one top left
+pub fn split_screen_viewports(width: u32, height: u32, players: usize) -> Vec<Viewport> {
+    let (width, height) = (width as i32, height as i32);
+    let (half_width, half_height) = (width / 2, height / 2);
+
+    match players {
+        0 | 1 => vec![Viewport { x: 0, y: 0, width, height }],
+        2 => vec![
+            Viewport { x: 0, y: height - half_height, width, height: half_height },
+            Viewport { x: 0, y: 0, width, height: height - half_height },
+        ],
+        _ => {
+            let quarters = [
+                Viewport { x: 0, y: height - half_height, width: half_width, height: half_height },
+                Viewport { x: half_width, y: height - half_height, width: width - half_width, height: half_height },
+                Viewport { x: 0, y: 0, width: half_width, height: height - half_height },
+                Viewport { x: half_width, y: 0, width: width - half_width, height: height - half_height },
+            ];
+            quarters.into_iter().take(players).collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn viewports_cover_the_window() {
+        assert_eq!(split_screen_viewports(1280, 720, 1), [Viewport { x: 0, y: 0, width: 1280, height: 720 }]);
+
+        let two = split_screen_viewports(1280, 721, 2);
+        assert_eq!(two[0].y, two[1].height);
+        assert_eq!(two[0].height + two[1].height, 721);
+        assert!(two[0].aspect_ratio() > 3.0);
+
+        let four = split_screen_viewports(1281, 720, 4);
+        assert_eq!(four.iter().map(|v| v.width * v.height).sum::<i32>(), 1281 * 720);
+        // Player one is in the top left corner
+        assert_eq!((four[0].x, four[0].y), (0, 360));
+        assert_eq!(split_screen_viewports(1280, 720, 3).len(), 3);
+    }
+}
@@ -0,0 +1,45 @@
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Surface {
+    Asphalt,
+    Kerb,
+    Sand,
+    Ice,
+}
+
+impl Surface {
+    /// Multiplier for how much lateral and traction force the tires can produce
+    pub fn grip(&self) -> f32 {
+        use Surface::*;
+        match self {
+            Asphalt => 1.0,
+            Kerb => 0.85,
+            Sand => 0.6,
+            Ice => 0.25,
+        }
+    }
+
+    /// Multiplier for the rolling resistance of the tires
+    pub fn rolling_resistance(&self) -> f32 {
+        use Surface::*;
+        match self {
+            Asphalt => 1.0,
+            Kerb => 1.5,
+            Sand => 6.0,
+            Ice => 0.5,
+        }
+    }
+
+    /// Speed above which the surface starts slowing the car down
+    pub fn max_speed(&self) -> f32 {
+        use Surface::*;
+        match self {
+            Asphalt | Ice => f32::INFINITY,
+            Kerb => 35.0,
+            Sand => 15.0,
+        }
+    }
+
+    pub fn is_road(&self) -> bool {
+        !matches!(self, Surface::Sand)
+    }
+}
@@ -0,0 +1,67 @@
+use super::tuning::CarTuning;
+
+// A boost can't be started with less than this in the meter, so tapping the button doesn't stutter
+const MIN_BOOST_START: f32 = 0.1;
+
+#[derive(Clone)]
+pub struct Boost {
+    // From 0 (empty) to 1 (full)
+    meter: f32,
+    requested: bool,
+    active: bool,
+}
+
+impl Default for Boost {
+    fn default() -> Boost {
+        Boost::new()
+    }
+}
+
+impl Boost {
+    pub fn new() -> Boost {
+        Boost {
+            meter: 1.0,
+            requested: false,
+            active: false,
+        }
+    }
+
+    pub fn meter(&self) -> f32 {
+        self.meter
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Set if the player is holding the boost button
+    pub fn set_requested(&mut self, value: bool) {
+        self.requested = value;
+    }
+
+    /// Set the boost state directly, used for cars that are controlled over the network
+    pub fn set_active(&mut self, value: bool) {
+        self.active = value;
+    }
+
+    /// Drain or recharge the meter, returns the extra traction force for this time step
+    pub fn update(&mut self, tuning: &CarTuning, delta_time: f32, drifting: bool) -> f32 {
+        self.active = self.requested
+            && self.meter > 0.0
+            && (self.active || self.meter >= MIN_BOOST_START);
+
+        if self.active {
+            self.meter = (self.meter - delta_time / tuning.boost_duration).max(0.0);
+            return tuning.boost_force;
+        }
+
+        let recharge_time = if drifting {
+            tuning.drift_recharge_time
+        } else {
+            tuning.boost_recharge_time
+        };
+        self.meter = (self.meter + delta_time / recharge_time).min(1.0);
+
+        0.0
+    }
+}
@@ -0,0 +1,222 @@
+use std::rc::Rc;
+
+use glow::Context;
+use nalgebra::Vector3;
+
+use crate::{
+    core::{
+        color::Color,
+        game::Game,
+        game_object::{Collider, GameObject},
+    },
+    game_objects::{
+        replay::replay_file::ReplayEvent,
+        track::{
+            checkpoints::{CheckpointEvent, LapTracker},
+            racing_line::RacingLine,
+            track::Track,
+        },
+    },
+    objects::mesh_model::MeshModel,
+};
+
+use super::{
+    bot_driver::{BotDifficulty, BotDriver},
+    car::Car,
+};
+
+// Bots are moved back to the track sooner than the player, nobody is waiting for them to drive back
+const OFF_TRACK_RESPAWN_TIME: f32 = 2.0;
+const UPSIDE_DOWN_RESPAWN_TIME: f32 = 2.0;
+const STUCK_RESPAWN_TIME: f32 = 3.0;
+const STUCK_SPEED: f32 = 1.0;
+
+/// A car driven by the computer around the racing line
+pub struct BotCar<'a> {
+    bot_id: usize,
+    name: String,
+    car: Car<'a>,
+    // Where the bot starts the race
+    grid_position: (Vector3<f32>, f32),
+    lap_tracker: LapTracker,
+    // Where the car was after the last update, checkpoints are crossed on the way from there
+    previous_position: Vector3<f32>,
+    start_number: u32,
+    driver: BotDriver,
+    racing_line: Rc<RacingLine>,
+    collider: Option<Collider>,
+    off_track_time: f32,
+    upside_down_time: f32,
+    stuck_time: f32,
+}
+
+impl<'a> BotCar<'a> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        bot_id: usize,
+        position: Vector3<f32>,
+        angle: f32,
+        racing_line: Rc<RacingLine>,
+        difficulty: BotDifficulty,
+        car_model: Rc<MeshModel<'a>>,
+        wheel_model: Rc<MeshModel<'a>>,
+        gl: &'a Context,
+        game: &Game,
+    ) -> BotCar<'a> {
+        let mut car = Car::new(true, car_model, wheel_model, gl, game);
+        car.set_position(position);
+        car.set_angle(angle);
+        let name = format!("Bot {bot_id}");
+        car.set_name(name.clone());
+
+        let mut race = game.race.borrow_mut();
+        race.add_car(&name, false);
+        let start_number = race.start_number();
+        drop(race);
+
+        let light_id = format!("BOT_CAR_{}", bot_id);
+        let mut lights = game.lights.borrow_mut();
+        lights.add_light(&light_id);
+        lights.set_light_diffuse(&light_id, &Color::new(0.89, 0.91, 1.00));
+        lights.set_light_ambient(&light_id, &Color::with_alpha(0.0, 0.0, 0.0, 0.0));
+        lights.set_light_specular(&light_id, &Color::new(0.89, 0.91, 1.00));
+        lights.set_light_max_radius(&light_id, 50.0);
+
+        BotCar {
+            bot_id,
+            name,
+            car,
+            grid_position: (position, angle),
+            lap_tracker: LapTracker::new(),
+            previous_position: position,
+            start_number,
+            driver: BotDriver::new(&racing_line, difficulty),
+            racing_line,
+            collider: None,
+            off_track_time: 0.0,
+            upside_down_time: 0.0,
+            stuck_time: 0.0,
+        }
+    }
+
+    fn light_id(&self) -> String {
+        format!("BOT_CAR_{}", self.bot_id)
+    }
+
+    fn move_to_grid(&mut self) {
+        let (position, angle) = self.grid_position;
+        self.car.reset_physics();
+        self.car.set_position(position);
+        self.car.set_angle(angle);
+        self.previous_position = position;
+        self.lap_tracker.reset();
+        self.driver.reset();
+    }
+
+    fn check_laps(&mut self, game: &Game) {
+        let events = self.lap_tracker.update(
+            &game.track_file.checkpoints,
+            &self.previous_position,
+            self.car.position(),
+            game.delta_time,
+        );
+
+        for event in events {
+            if let CheckpointEvent::Lap { time, .. } = event {
+                game.record_replay_event(self.car.id(), ReplayEvent::Lap { time });
+                game.race.borrow_mut().lap_completed(&self.name, time);
+            }
+        }
+    }
+
+    fn check_respawn(&mut self, game: &Game) {
+        let delta_time = game.delta_time;
+        let add_time = |time: &mut f32, condition: bool| {
+            *time = if condition { *time + delta_time } else { 0.0 };
+        };
+
+        add_time(&mut self.off_track_time, self.car.is_off_road());
+        add_time(&mut self.upside_down_time, self.car.is_upside_down());
+        add_time(
+            &mut self.stuck_time,
+            self.car.throttle() > 0.0 && self.car.car_state().velocity_wc.norm() < STUCK_SPEED,
+        );
+
+        let respawn = Track::is_below_road(&game.respawn_points.borrow(), self.car.position())
+            || self.off_track_time > OFF_TRACK_RESPAWN_TIME
+            || self.upside_down_time > UPSIDE_DOWN_RESPAWN_TIME
+            || self.stuck_time > STUCK_RESPAWN_TIME;
+        if !respawn {
+            return;
+        }
+
+        let checkpoints = &game.track_file.checkpoints;
+        let last_checkpoint = self.lap_tracker.last_checkpoint(checkpoints.len()).map(|i| &checkpoints[i]);
+        let respawn_point =
+            Track::respawn_point(&game.respawn_points.borrow(), last_checkpoint, self.car.position());
+        if let Some(respawn_point) = respawn_point {
+            self.car.respawn(&respawn_point);
+            self.driver.reset();
+            game.record_replay_event(self.car.id(), ReplayEvent::Respawn);
+        }
+
+        self.off_track_time = 0.0;
+        self.upside_down_time = 0.0;
+        self.stuck_time = 0.0;
+    }
+}
+
+impl<'a> GameObject<'a> for BotCar<'a> {
+    fn collision_info(&self) -> Collider {
+        self.collider.clone().unwrap_or(Collider::NoCollision)
+    }
+
+    fn on_event(&mut self, _game: &Game, _event: &sdl2::event::Event) {}
+
+    fn update(&mut self, game: &Game, gl: &'a Context) {
+        let (start_number, inputs_frozen) = {
+            let race = game.race.borrow();
+            (race.start_number(), race.inputs_frozen())
+        };
+        if start_number != self.start_number {
+            self.start_number = start_number;
+            self.move_to_grid();
+        }
+
+        let pose = self.car.pose();
+        let other_cars = game
+            .car_poses
+            .borrow()
+            .iter()
+            .filter(|(id, _)| **id != self.car.id())
+            .map(|(_, other)| *other)
+            .collect::<Vec<_>>();
+
+        let input = self.driver.drive(&self.racing_line, &pose, &self.car.car_state().velocity_wc, &other_cars);
+        self.car.set_throttle(input.throttle);
+        self.car.set_brake(input.brake);
+        self.car.set_steering_angle(input.steering_angle);
+        self.car.set_held(inputs_frozen);
+
+        self.car.set_owner(self as *const _ as *const usize);
+        self.car.update(game, gl);
+        self.check_laps(game);
+        if !inputs_frozen {
+            self.check_respawn(game);
+        }
+        // After a respawn the car didn't drive from where it was
+        self.previous_position = *self.car.position();
+
+        let (min_x, min_y, min_z, max_x, max_y, max_z) = self.car.car_cube();
+        self.collider = Some(Collider::BoxCollider(
+            min_x, min_y, min_z, max_x, max_y, max_z,
+        ));
+
+        let pos = self.car.light_position();
+        game.lights.borrow_mut().set_light_position(&self.light_id(), &Vector3::new(pos.x, pos.y, pos.z));
+    }
+
+    fn display(&self, game: &Game, gl: &'a Context) {
+        self.car.display(game, gl);
+    }
+}
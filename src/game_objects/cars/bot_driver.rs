@@ -0,0 +1,180 @@
+use std::f32;
+
+use clap::ValueEnum;
+use nalgebra::{Vector2, Vector3};
+
+use crate::{game_objects::track::racing_line::RacingLine, utils::limit};
+
+use super::slipstream::CarPose;
+
+// The same as the player can steer with the keyboard
+const MAX_STEERING_ANGLE: f32 = 0.25 * f32::consts::PI / 4.0;
+// The point on the racing line the bot steers towards is this far ahead, plus the distance covered in LOOKAHEAD_TIME
+const LOOKAHEAD_DISTANCE: f32 = 8.0;
+const LOOKAHEAD_TIME: f32 = 0.4;
+const STEERING_GAIN: f32 = 0.6;
+// Speed differences smaller than this are driven with part throttle or part brake
+const SPEED_CONTROL_RANGE: f32 = 3.0;
+// The speed on the racing line is looked up where the car will be after this many seconds
+const SPEED_LOOKAHEAD_TIME: f32 = 0.3;
+// Cars closer than this in front of the bot, and less than AVOID_WIDTH to the side of it, are steered around
+const AVOID_DISTANCE: f32 = 20.0;
+const AVOID_WIDTH: f32 = 4.0;
+const AVOID_STEERING: f32 = 0.08;
+// Right behind another car the bot lifts off the throttle instead of running into it
+const FOLLOW_DISTANCE: f32 = 8.0;
+
+/// How fast the bots take the corners and how hard they brake
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum BotDifficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl BotDifficulty {
+    /// Sideways acceleration the bot dares to corner with
+    fn lateral_acceleration(&self) -> f32 {
+        match self {
+            BotDifficulty::Easy => 6.0,
+            BotDifficulty::Medium => 9.0,
+            BotDifficulty::Hard => 12.0,
+        }
+    }
+
+    fn deceleration(&self) -> f32 {
+        match self {
+            BotDifficulty::Easy => 5.0,
+            BotDifficulty::Medium => 7.0,
+            BotDifficulty::Hard => 9.0,
+        }
+    }
+
+    fn max_speed(&self) -> f32 {
+        match self {
+            BotDifficulty::Easy => 28.0,
+            BotDifficulty::Medium => 34.0,
+            BotDifficulty::Hard => 40.0,
+        }
+    }
+}
+
+/// What the bot does with the pedals and the steering wheel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DriverInput {
+    pub throttle: f32,
+    pub brake: f32,
+    pub steering_angle: f32,
+}
+
+/// Follows the racing line, slowing down for the corners and steering around other cars.
+/// It only sees the poses of the cars, so it works the same on any car the physics moves
+pub struct BotDriver {
+    // Target speed at every point of the racing line
+    speeds: Vec<f32>,
+    // Closest point on the racing line in the last update
+    line_index: Option<usize>,
+}
+
+impl BotDriver {
+    pub fn new(racing_line: &RacingLine, difficulty: BotDifficulty) -> BotDriver {
+        BotDriver {
+            speeds: racing_line.speed_profile(
+                difficulty.lateral_acceleration(),
+                difficulty.deceleration(),
+                difficulty.max_speed(),
+            ),
+            line_index: None,
+        }
+    }
+
+    /// Forget where on the track the car was, after it has been moved
+    pub fn reset(&mut self) {
+        self.line_index = None;
+    }
+
+    pub fn drive(
+        &mut self,
+        racing_line: &RacingLine,
+        pose: &CarPose,
+        velocity: &Vector3<f32>,
+        other_cars: &[CarPose],
+    ) -> DriverInput {
+        if racing_line.points().is_empty() {
+            return DriverInput { throttle: 0.0, brake: 100.0, steering_angle: 0.0 };
+        }
+
+        let index = racing_line.closest_point(&pose.position, self.line_index);
+        self.line_index = Some(index);
+
+        let forward = Vector2::new(pose.angle.sin(), pose.angle.cos());
+        let left = Vector2::new(pose.angle.cos(), -pose.angle.sin());
+        let speed = velocity.xz().dot(&forward);
+
+        // Steer towards a point ahead on the line, further ahead the faster the car goes
+        let lookahead = LOOKAHEAD_DISTANCE + speed.max(0.0) * LOOKAHEAD_TIME;
+        let target = racing_line.points()[racing_line.index_ahead(index, lookahead)].position.xz() - pose.position.xz();
+        let heading_error = f32::atan2(target.dot(&left), target.dot(&forward));
+        let mut steering_angle = STEERING_GAIN * heading_error;
+
+        let mut target_speed = self.speeds[racing_line.index_ahead(index, speed.max(0.0) * SPEED_LOOKAHEAD_TIME)];
+
+        for other in other_cars {
+            let offset = other.position.xz() - pose.position.xz();
+            let (ahead, side) = (offset.dot(&forward), offset.dot(&left));
+            if ahead <= 0.0 || ahead >= AVOID_DISTANCE || side.abs() >= AVOID_WIDTH {
+                continue;
+            }
+
+            // Steer away from the side the other car is on, harder the closer it is
+            let away = if side > 0.0 { -1.0 } else { 1.0 };
+            steering_angle += away * AVOID_STEERING * (1.0 - ahead / AVOID_DISTANCE);
+
+            if ahead < FOLLOW_DISTANCE && side.abs() < AVOID_WIDTH / 2.0 {
+                target_speed = target_speed.min(speed - SPEED_CONTROL_RANGE);
+            }
+        }
+
+        let speed_error = target_speed - speed;
+        DriverInput {
+            throttle: limit(speed_error / SPEED_CONTROL_RANGE, 0.0, 1.0) * 100.0,
+            brake: limit(-speed_error / SPEED_CONTROL_RANGE, 0.0, 1.0) * 100.0,
+            steering_angle: limit(steering_angle, -MAX_STEERING_ANGLE, MAX_STEERING_ANGLE),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A circle so large it is almost straight, driven counter clockwise
+    fn wide_circle() -> RacingLine {
+        let centre_line = (0..360)
+            .map(|i| {
+                let angle = (i as f32).to_radians();
+                Vector3::new(1000.0 * angle.cos(), 0.0, 1000.0 * angle.sin())
+            })
+            .collect::<Vec<_>>();
+        RacingLine::new(&centre_line)
+    }
+
+    #[test]
+    fn steers_around_a_car_in_front() {
+        let racing_line = wide_circle();
+        let mut driver = BotDriver::new(&racing_line, BotDifficulty::Medium);
+        let start = racing_line.points()[0].position;
+        let pose = CarPose { position: start, angle: 0.0 };
+        let velocity = Vector3::new(0.0, 0.0, 20.0);
+
+        let alone = driver.drive(&racing_line, &pose, &velocity, &[]);
+        assert!(alone.throttle > 0.0 && alone.brake == 0.0);
+        assert!(alone.steering_angle.abs() < 0.01);
+
+        // A car slightly to the left, heading 0 has the left side towards positive x
+        let other = CarPose { position: start + Vector3::new(1.0, 0.0, 5.0), angle: 0.0 };
+        let blocked = driver.drive(&racing_line, &pose, &velocity, &[other]);
+        assert!(blocked.steering_angle < 0.0, "steered {}", blocked.steering_angle);
+        assert!(blocked.throttle == 0.0 && blocked.brake > 0.0);
+    }
+}
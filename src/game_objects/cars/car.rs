@@ -1,19 +1,33 @@
 use std::{f32, rc::Rc};
 
 use glow::*;
-use nalgebra::{Vector3, Vector4, Vector2};
+use nalgebra::Vector3;
 use sdl2::event::Event;
 
 use crate::{
     core::{
+        color::Color,
         game::Game,
-        game_object::{Collider, GameObject},
+        game_object::GameObject,
+        matrices::ModelMatrix,
     },
+    game_objects::track::track::RespawnPoint,
     objects::mesh_model::MeshModel,
-    utils::line_contains_point,
 };
 
-use super::car_state::CarState;
+use super::{
+    boost::Boost,
+    car_physics::CarPhysics,
+    car_state::CarState,
+    damage::{Damage, DamageArea},
+    slipstream::{self, CarPose},
+    suspension::{Suspension, SPRING_LENGTH},
+    tuning::CarTuning,
+};
+
+// Damaged cars get darker, as if they were scorched
+const DAMAGE_TINT: Color = Color { r: 0.08, g: 0.06, b: 0.05, a: 1.0 };
+const MAX_DAMAGE_TINT: f32 = 0.7;
 
 pub enum ViewState {
     ThirdPerson,
@@ -23,237 +37,137 @@ pub enum ViewState {
 pub struct Car<'a> {
     car_model: Rc<MeshModel<'a>>,
     wheel_model: Rc<MeshModel<'a>>,
-    car_state: CarState,
-    y_velocity: f32,
-    handbrake: bool,
+    physics: CarPhysics,
     wheel_rotation: f32,
     view_state: ViewState,
-    enable_plane_collision: bool,
-    reverse: bool,
+    // The area that was hit and the impact energy, if the car hit something in the last update
+    last_impact: Option<(DamageArea, f32)>,
+    // Less than 1 draws the car see through, like the ghost in time trial
+    alpha: f32,
+    // Drawn instead of the damage tint when set, the color and how much of it is mixed in
+    tint: Option<(Color, f32)>,
+    // Who drives the car, shown in replays
+    name: String,
+    // The game object the car is part of, it doesn't collide with itself
+    owner: *const usize,
 }
 
 impl<'a> Car<'a> {
     pub fn new(enable_plane_collision: bool, car_model: Rc<MeshModel<'a>>, wheel_model: Rc<MeshModel<'a>>, _gl: &'a Context, _game: &Game) -> Car<'a> {
-        let mut car_state = CarState::new();
-        car_state.position_wc.y = 40.0;
+        let mut physics = CarPhysics::new(enable_plane_collision);
+        physics.car_state.position_wc.y = 40.0;
 
         Car {
             car_model,
             wheel_model,
-            car_state,
-            y_velocity: 0.0,
-            handbrake: false,
+            physics,
             wheel_rotation: 0.0,
             view_state: ViewState::ThirdPerson,
-            enable_plane_collision,
-            reverse: false,
+            last_impact: None,
+            alpha: 1.0,
+            tint: None,
+            name: String::from("Car"),
+            owner: std::ptr::null(),
         }
     }
 
-    pub fn car_cube(&self, game: &Game) -> (f32, f32, f32, f32, f32, f32) {
-        let mut model_matrix = game.model_matrix.borrow_mut();
-
-        model_matrix.push_stack();
-        model_matrix.add_translate(
-            self.car_state.position_wc.x,
-            self.car_state.position_wc.y,
-            self.car_state.position_wc.z,
-        );
-        model_matrix.add_rotation(0.0, self.car_state.angle, 0.0);
-        model_matrix.add_scale(5.0, 3.0, 10.0);
-
-        let local_max = Vector4::new(0.5, 0.5, 0.5, 1.0);
-        let local_min = Vector4::new(-0.5, -0.5, -0.5, 1.0);
-
-        let max = model_matrix.matrix * local_max;
-        let min = model_matrix.matrix * local_min;
-
-        model_matrix.pop_stack();
-
-        return (
-            min.x.min(max.x),
-            min.y.min(max.y),
-            min.z.min(max.z),
-            max.x.max(min.x),
-            max.y.max(min.y),
-            max.z.max(min.z),
-        );
-    }
-
-    fn full_car_cube(&self, game: &Game, custom_car_state: Option<&CarState>) -> [Vector3<f32>; 8] {
-        let car_state = custom_car_state.unwrap_or(&self.car_state);
-
-        let mut model_matrix = game.model_matrix.borrow_mut();
-
-        model_matrix.push_stack();
-        model_matrix.add_translate(
-            car_state.position_wc.x,
-            car_state.position_wc.y,
-            car_state.position_wc.z,
-        );
-        model_matrix.add_rotation(0.0, car_state.angle, 0.0);
-        model_matrix.add_scale(5.0, 3.0, 10.0);
-
-        let bottom_left_inner_local = Vector4::new(-0.5, -0.5, -0.5, 1.0);
-        let bottom_right_inner_local = Vector4::new(0.5, -0.5, -0.5, 1.0);
-        let top_left_inner_local = Vector4::new(-0.5, 0.5, -0.5, 1.0);
-        let top_right_inner_local = Vector4::new(0.5, 0.5, -0.5, 1.0);
-        let bottom_left_outer_local = Vector4::new(-0.5, -0.5, 0.5, 1.0);
-        let bottom_right_outer_local = Vector4::new(0.5, -0.5, 0.5, 1.0);
-        let top_left_outer_local = Vector4::new(-0.5, 0.5, 0.5, 1.0);
-        let top_right_outer_local = Vector4::new(0.5, 0.5, 0.5, 1.0);
-
-        let bottom_left_inner = (model_matrix.matrix * bottom_left_inner_local).xyz();
-        let bottom_right_inner = (model_matrix.matrix * bottom_right_inner_local).xyz();
-        let bottom_left_outer = (model_matrix.matrix * bottom_left_outer_local).xyz();
-        let bottom_right_outer = (model_matrix.matrix * bottom_right_outer_local).xyz();
-
-        let top_left_inner = (model_matrix.matrix * top_left_inner_local).xyz();
-        let top_right_inner = (model_matrix.matrix * top_right_inner_local).xyz();
-        let top_left_outer = (model_matrix.matrix * top_left_outer_local).xyz();
-        let top_right_outer = (model_matrix.matrix * top_right_outer_local).xyz();
-
-        model_matrix.pop_stack();
-
-        [
-            bottom_left_inner,
-            bottom_right_inner,
-            bottom_left_outer,
-            bottom_right_outer,
-            top_left_inner,
-            top_right_inner,
-            top_left_outer,
-            top_right_outer,
-        ]
-    }
-
-    fn check_collision(&mut self, info: &Collider, game: &Game) {
-        use Collider::*;
-        match info {
-            &HeightCollider(y) => {
-                let (_, c_min_y, ..) = self.car_cube(game);
-                if c_min_y <= y {
-                    self.car_state.position_wc.y = y + 1.5;
-                    self.y_velocity = 0.0;
-                }
-            }
-            &BoxCollider(min_x, min_y, min_z, max_x, max_y, max_z) => {
-                let (c_min_x, c_min_y, c_min_z, c_max_x, c_max_y, c_max_z) = self.car_cube(game);
-
-                let perform_collision_detect = c_min_x <= max_x
-                    && c_max_x >= min_x
-                    && c_min_y <= max_y
-                    && c_max_y >= min_y
-                    && c_min_z <= max_z
-                    && c_max_z >= min_z;
-
-                if perform_collision_detect {
-                    let mut closest_x = self.car_state.position_wc.x;
-                    if self.car_state.position_wc.x > max_x {
-                        closest_x = max_x + 2.5;
-                    } else if self.car_state.position_wc.x < min_x {
-                        closest_x = min_x - 2.5;
-                    }
-
-                    let mut closest_y = self.car_state.position_wc.y;
-                    if self.car_state.position_wc.y > max_y {
-                        self.y_velocity = 0.0;
-                        closest_y = max_y + 1.5;
-                    } else if self.car_state.position_wc.y < min_y {
-                        self.y_velocity = -1.0;
-                        closest_y = min_y - 1.5;
-                    }
-
-                    let mut closest_z = self.car_state.position_wc.z;
-                    if self.car_state.position_wc.z > max_z {
-                        closest_z = max_z + 5.0;
-                    } else if self.car_state.position_wc.z < min_z {
-                        closest_z = min_z - 5.0;
-                    }
-
-                    self.car_state.position_wc.x = closest_x;
-                    self.car_state.position_wc.y = closest_y;
-                    self.car_state.position_wc.z = closest_z;
-                }
-            }
-            InfiniteYPlaneCollider(p0, p1) => {
-                if !self.enable_plane_collision {
-                    return;
-                }
-
-                let corners = self.full_car_cube(game, None);
-                let car_future_state = self.car_state.peek_time_step(game.delta_time, self.handbrake, self.handbrake, self.reverse);
-                let future_corners = self.full_car_cube(game, Some(&car_future_state));
-
-                for (corner, f_corner) in corners[..4].iter().zip(&future_corners[..4]) {
-                    let v = (p1 - p0).xz();
-
-                    let a_mat = corner.xz();
-                    let b_mat = p0.xz();
-                    let c = (f_corner.xz() - corner.xz()) / game.delta_time;
-                    let n = Vector2::new(-v.y, v.x);
-
-                    let t_hit = (n.dot(&(b_mat-a_mat))) / (n.dot(&c));
-                    let p_hit = a_mat + t_hit * c;
-
-                    if line_contains_point(&p0.xz(), &p1.xz(), &p_hit) && t_hit <= game.delta_time && t_hit >= 0.0 {
-                        let reflected = c - ((2.0 * (c.dot(&n))) / (n.dot(&n))) * n;
-
-                        self.car_state.velocity_wc.x = reflected.x;
-                        self.car_state.velocity_wc.z = reflected.y;
-                    }
-                }
-            }
-            MultiCollider(c) => c.iter().for_each(|info| self.check_collision(&info, game)),
-            NoCollision => (),
-        }
+    pub fn car_cube(&self) -> (f32, f32, f32, f32, f32, f32) {
+        self.physics.car_cube()
     }
 
     fn check_all_collision(&mut self, game: &Game) {
         for object in &game.game_objects {
-            if self as *const _ as *const usize == object.as_ptr() as *const _ as *const usize {
+            if object.as_ptr() as *const _ as *const usize == self.owner {
                 continue;
             }
 
-            self.check_collision(&object.borrow().collision_info(), game);
+            let object = match object.try_borrow() {
+                Ok(o) => o,
+                Err(e) => {
+                    log::error!("Failed to check the collision with a game object. {e}");
+                    continue;
+                }
+            };
+
+            self.physics
+                .check_collision(&object.collision_info(), game.delta_time);
         }
     }
 
-    fn update_gravity(&mut self, game: &Game) {
-        self.car_state.position_wc.y += self.y_velocity * game.delta_time;
-        self.y_velocity -= 9.8 * 1.7 * game.delta_time;
+    fn update_suspension(&mut self, game: &Game) {
+        let owner = self.owner;
+        self.physics
+            .update_suspension(game.delta_time, |origin, dir, max_dist| {
+                game.raycast(origin, dir, max_dist, owner)
+            });
     }
 
     pub fn throttle(&self) -> f32 {
-        self.car_state.throttle
+        self.physics.car_state.throttle
     }
 
     pub fn set_throttle(&mut self, value: f32) {
-        self.car_state.throttle = value;
+        self.physics.car_state.throttle = value;
     }
 
     pub fn brake(&self) -> f32 {
-        self.car_state.brake
+        self.physics.car_state.brake
     }
 
     pub fn set_brake(&mut self, value: f32) {
-        self.car_state.brake = value;
+        self.physics.car_state.brake = value;
     }
 
     pub fn steering_angle(&self) -> f32 {
-        self.car_state.steering_angle
+        self.physics.car_state.steering_angle
     }
 
     pub fn set_steering_angle(&mut self, value: f32) {
-        self.car_state.steering_angle = value;
+        self.physics.car_state.steering_angle = value;
     }
 
     pub fn y_velocity(&self) -> f32 {
-        self.y_velocity
+        self.physics.suspension.y_velocity
     }
 
     pub fn set_y_velocity(&mut self, value: f32) {
-        self.y_velocity = value;
+        self.physics.suspension.y_velocity = value;
+    }
+
+    pub fn damage(&self) -> &Damage {
+        &self.physics.car_state.damage
+    }
+
+    pub fn set_damage(&mut self, damage: Damage) {
+        self.physics.car_state.damage = damage;
+    }
+
+    pub fn tuning(&self) -> &CarTuning {
+        &self.physics.tuning
+    }
+
+    pub fn set_tuning(&mut self, tuning: CarTuning) {
+        self.physics.tuning = tuning;
+    }
+
+    pub fn boost(&self) -> &Boost {
+        &self.physics.boost
+    }
+
+    pub fn boost_mut(&mut self) -> &mut Boost {
+        &mut self.physics.boost
+    }
+
+    pub fn last_impact(&self) -> Option<(DamageArea, f32)> {
+        self.last_impact
+    }
+
+    pub fn is_drifting(&self) -> bool {
+        self.physics.is_drifting()
+    }
+
+    pub fn suspension(&self) -> &Suspension {
+        &self.physics.suspension
     }
 
     pub fn view_state(&self) -> &ViewState {
@@ -265,47 +179,143 @@ impl<'a> Car<'a> {
     }
 
     pub fn handbrake(&self) -> bool {
-        self.handbrake
+        self.physics.handbrake
     }
 
     pub fn set_handbrake(&mut self, value: bool) {
-        self.handbrake = value;
+        self.physics.handbrake = value;
+    }
+
+    /// Keep the car where it stands, the inputs are kept for when it is let go
+    pub fn set_held(&mut self, value: bool) {
+        self.physics.held = value;
     }
 
     pub fn position(&self) -> &Vector3<f32> {
-        &self.car_state.position_wc
+        &self.physics.car_state.position_wc
     }
 
     pub fn set_position(&mut self, position: Vector3<f32>) {
-        self.car_state.position_wc = position;
+        self.physics.car_state.position_wc = position;
     }
 
     pub fn angle(&self) -> f32 {
-        self.car_state.angle
+        self.physics.car_state.angle
     }
 
     pub fn set_angle(&mut self, value: f32) {
-        self.car_state.angle = value;
+        self.physics.car_state.angle = value;
     }
 
     pub fn car_state(&self) -> &CarState {
-        &self.car_state
+        &self.physics.car_state
     }
 
     pub fn car_state_mut(&mut self) -> &mut CarState {
-        &mut self.car_state
+        &mut self.physics.car_state
     }
 
     pub fn reverse(&self) -> bool {
-        self.reverse
+        self.physics.reverse
     }
 
     pub fn set_reverse(&mut self, value: bool) {
-        self.reverse = value;
+        self.physics.reverse = value;
+    }
+
+    pub fn set_name(&mut self, name: impl Into<String>) {
+        self.name = name.into();
+    }
+
+    pub fn set_alpha(&mut self, alpha: f32) {
+        self.alpha = alpha;
+    }
+
+    pub fn set_tint(&mut self, tint: Option<(Color, f32)>) {
+        self.tint = tint;
     }
 
     pub fn reset_physics(&mut self) {
-        self.car_state = CarState::new();
+        self.physics.reset();
+    }
+
+    // How far a wheel has moved up from its resting position, in model coordinates
+    fn wheel_travel(&self, wheel: usize) -> f32 {
+        let rest_compression = SPRING_LENGTH - 1.5;
+        let compression = if self.physics.car_state.wheel_contact[wheel] {
+            self.physics.suspension.compression[wheel]
+        } else {
+            0.0
+        };
+
+        (compression - rest_compression) / 5.0
+    }
+
+    /// All wheels touching the ground are off the road
+    pub fn is_off_road(&self) -> bool {
+        let mut wheels_on_ground = self
+            .physics
+            .car_state
+            .wheel_surfaces
+            .iter()
+            .zip(self.physics.car_state.wheel_contact)
+            .filter(|(_, contact)| *contact)
+            .peekable();
+
+        wheels_on_ground.peek().is_some() && wheels_on_ground.all(|(surface, _)| !surface.is_road())
+    }
+
+    pub fn is_upside_down(&self) -> bool {
+        self.physics.suspension.up(&self.physics.car_state).y < 0.2
+    }
+
+    pub fn respawn(&mut self, respawn_point: &RespawnPoint) {
+        self.reset_physics();
+        self.set_position(respawn_point.position + Vector3::new(0.0, 1.5, 0.0));
+        self.set_angle(respawn_point.angle);
+    }
+
+    /// The game object the car is part of, by the pointer in its RefCell. The object is moved into
+    /// the game after it is created, so it sets this in its update before updating the car
+    pub fn set_owner(&mut self, owner: *const usize) {
+        self.owner = owner;
+    }
+
+    /// Key of the car in the car poses of the game
+    pub fn id(&self) -> *const usize {
+        self as *const _ as *const usize
+    }
+
+    pub fn pose(&self) -> CarPose {
+        CarPose {
+            position: self.physics.car_state.position_wc,
+            angle: self.physics.car_state.angle,
+        }
+    }
+
+    /// Remove the car from the cars other cars can draft behind and from the replay, must be called when the car is deleted
+    pub fn remove_from_game(&self, game: &Game) {
+        game.car_poses.borrow_mut().remove(&self.id());
+        if let Some(recorder) = game.replay_recorder.borrow_mut().as_mut() {
+            recorder.remove_car(self.id());
+        }
+    }
+
+    // Drawn in the scaled car body coordinates
+    fn display_boost_flame(&self, game: &Game, model_matrix: &mut ModelMatrix) {
+        // Flicker the flame a bit with the wheel rotation so it doesn't look static
+        let length = 0.3 + 0.1 * self.wheel_rotation.sin().abs();
+        let color = Color::with_alpha(1.0, 0.55, 0.1, 0.7);
+        game.shader.set_material_diffuse(&color);
+        game.shader.set_material_ambient(&color);
+        game.shader.set_material_specular(&Color::zeros());
+
+        model_matrix.push_stack();
+        model_matrix.add_translate(0.0, 0.05, -0.8 - length / 2.0);
+        model_matrix.add_scale(0.15, 0.1, length);
+        game.shader.set_model_matrix(model_matrix.matrix.as_slice());
+        game.cube.draw(&game.shader);
+        model_matrix.pop_stack();
     }
 
     pub fn light_position(&self) -> Vector3<f32> {
@@ -322,16 +332,25 @@ impl<'a> GameObject<'a> for Car<'a> {
 
     fn update(&mut self, game: &Game, _gl: &'a Context) {
         self.check_all_collision(game);
+        self.last_impact = self.physics.apply_pending_impact();
 
-        self.car_state
-            .perform_physics_time_step(game.delta_time, self.handbrake, self.handbrake, self.reverse);
+        let pose = self.pose();
+        self.physics.car_state.drag_factor = slipstream::drag_factor(game, self.id(), &pose);
+        game.car_poses.borrow_mut().insert(self.id(), pose);
 
-        self.wheel_rotation += self.car_state.wheel_rotation_speed;
+        self.physics.step(game.delta_time);
+
+        self.wheel_rotation += self.physics.car_state.wheel_rotation_speed;
         if self.wheel_rotation >= 2.0 * f32::consts::PI {
             self.wheel_rotation = self.wheel_rotation % (2.0 * f32::consts::PI);
         }
 
-        self.update_gravity(game);
+        self.update_suspension(game);
+
+        if let Some(recorder) = game.replay_recorder.borrow_mut().as_mut() {
+            let state = &self.physics.car_state;
+            recorder.record_car(self.id(), &self.name, &state.position_wc, state.angle, state.steering_angle);
+        }
     }
 
     fn display(&self, game: &Game, _gl: &'a Context) {
@@ -340,54 +359,65 @@ impl<'a> GameObject<'a> for Car<'a> {
         // Car
         model_matrix.push_stack();
         model_matrix.add_translate(
-            self.car_state.position_wc.x,
-            self.car_state.position_wc.y,
-            self.car_state.position_wc.z,
+            self.physics.car_state.position_wc.x,
+            self.physics.car_state.position_wc.y,
+            self.physics.car_state.position_wc.z,
         );
         model_matrix.add_scale(5.0, 5.0, 5.0);
-        model_matrix.add_rotation(0.0, self.car_state.angle, 0.0);
+        model_matrix.add_rotation(0.0, self.physics.car_state.angle, 0.0);
+        model_matrix.add_rotation(self.physics.suspension.pitch, 0.0, 0.0);
+        model_matrix.add_rotation(0.0, 0.0, self.physics.suspension.roll);
 
         game.shader.set_model_matrix(model_matrix.matrix.as_slice());
-        self.car_model.draw(&game.shader);
+        let (tint, amount) = self
+            .tint
+            .unwrap_or((DAMAGE_TINT, self.physics.car_state.damage.total() * MAX_DAMAGE_TINT));
+        self.car_model.draw_translucent(&game.shader, &tint, amount, self.alpha);
 
         // Front wheels
         model_matrix.push_stack();
-        model_matrix.add_translate(0.4, -0.1, 0.8);
+        model_matrix.add_translate(0.4, -0.1 + self.wheel_travel(0), 0.8);
         model_matrix.add_rotation(
             0.0,
-            90.0f32.to_radians() + self.car_state.steering_angle,
+            90.0f32.to_radians() + self.physics.car_state.steering_angle,
             0.0,
         );
         game.shader.set_model_matrix(model_matrix.matrix.as_slice());
-        self.wheel_model.draw(&game.shader);
+        self.wheel_model.draw_translucent(&game.shader, &Color::zeros(), 0.0, self.alpha);
         model_matrix.pop_stack();
 
         model_matrix.push_stack();
-        model_matrix.add_translate(-0.4, -0.1, 0.8);
+        model_matrix.add_translate(-0.4, -0.1 + self.wheel_travel(1), 0.8);
         model_matrix.add_rotation(
             0.0,
-            -90.0f32.to_radians() + self.car_state.steering_angle,
+            -90.0f32.to_radians() + self.physics.car_state.steering_angle,
             0.0,
         );
         game.shader.set_model_matrix(model_matrix.matrix.as_slice());
-        self.wheel_model.draw(&game.shader);
+        self.wheel_model.draw_translucent(&game.shader, &Color::zeros(), 0.0, self.alpha);
         model_matrix.pop_stack();
 
         // Rear wheels
         model_matrix.push_stack();
-        model_matrix.add_translate(0.4, -0.05, -0.6);
+        model_matrix.add_translate(0.4, -0.05 + self.wheel_travel(2), -0.6);
         model_matrix.add_rotation(self.wheel_rotation, 90.0f32.to_radians(), 0.0);
         game.shader.set_model_matrix(model_matrix.matrix.as_slice());
-        self.wheel_model.draw(&game.shader);
+        self.wheel_model.draw_translucent(&game.shader, &Color::zeros(), 0.0, self.alpha);
         model_matrix.pop_stack();
 
         model_matrix.push_stack();
-        model_matrix.add_translate(-0.4, -0.05, -0.6);
+        model_matrix.add_translate(-0.4, -0.05 + self.wheel_travel(3), -0.6);
         model_matrix.add_rotation(self.wheel_rotation, -90.0f32.to_radians(), 0.0);
         game.shader.set_model_matrix(model_matrix.matrix.as_slice());
-        self.wheel_model.draw(&game.shader);
+        self.wheel_model.draw_translucent(&game.shader, &Color::zeros(), 0.0, self.alpha);
         model_matrix.pop_stack();
 
+        if self.physics.boost.is_active() {
+            self.display_boost_flame(game, &mut model_matrix);
+        }
+
         model_matrix.pop_stack();
+
+        slipstream::display_draft_cone(game, &mut model_matrix, self.id(), &self.pose());
     }
 }
@@ -0,0 +1,293 @@
+use std::f32;
+
+use nalgebra::{Vector2, Vector3, Vector4};
+
+use crate::{
+    core::{game_object::Collider, matrices::ModelMatrix, raycast::RaycastHit},
+    utils::line_contains_point,
+};
+
+use super::{
+    boost::Boost,
+    car_state::{CarState, CAR_MASS},
+    damage::DamageArea,
+    suspension::Suspension,
+    tuning::CarTuning,
+};
+
+// The lowest the body can get to the ground when the springs are fully compressed
+const MIN_RIDE_HEIGHT: f32 = 0.6;
+// How far above the body the ground is looked for, so the car is pushed back up when it sinks into a slope
+const GROUND_PROBE_HEIGHT: f32 = 1.0;
+
+// The car counts as drifting when it slides sideways faster than this
+const DRIFT_SPEED: f32 = 5.0;
+
+/// Everything that moves the car, without anything that needs SDL or OpenGL
+#[derive(Clone)]
+pub struct CarPhysics {
+    pub car_state: CarState,
+    pub suspension: Suspension,
+    pub handbrake: bool,
+    pub reverse: bool,
+    pub tuning: CarTuning,
+    pub boost: Boost,
+    // The car may not move, like on the grid before the start. It still settles on its springs
+    pub held: bool,
+    enable_plane_collision: bool,
+    // The hardest impact since the last update, a car can touch several colliders in the same crash
+    pending_impact: Option<(DamageArea, f32)>,
+}
+
+impl CarPhysics {
+    pub fn new(enable_plane_collision: bool) -> CarPhysics {
+        CarPhysics {
+            car_state: CarState::new(),
+            suspension: Suspension::new(),
+            handbrake: false,
+            reverse: false,
+            tuning: CarTuning::standard(),
+            boost: Boost::new(),
+            held: false,
+            enable_plane_collision,
+            pending_impact: None,
+        }
+    }
+
+    /// Reset everything that moves, the tuning and boost meter are kept
+    pub fn reset(&mut self) {
+        self.car_state = CarState::new();
+        self.suspension = Suspension::new();
+    }
+
+    pub fn car_cube(&self) -> (f32, f32, f32, f32, f32, f32) {
+        let mut model_matrix = ModelMatrix::new();
+        model_matrix.add_translate(
+            self.car_state.position_wc.x,
+            self.car_state.position_wc.y,
+            self.car_state.position_wc.z,
+        );
+        model_matrix.add_rotation(0.0, self.car_state.angle, 0.0);
+        model_matrix.add_scale(5.0, 3.0, 10.0);
+
+        let local_max = Vector4::new(0.5, 0.5, 0.5, 1.0);
+        let local_min = Vector4::new(-0.5, -0.5, -0.5, 1.0);
+
+        let max = model_matrix.matrix * local_max;
+        let min = model_matrix.matrix * local_min;
+
+        (
+            min.x.min(max.x),
+            min.y.min(max.y),
+            min.z.min(max.z),
+            max.x.max(min.x),
+            max.y.max(min.y),
+            max.z.max(min.z),
+        )
+    }
+
+    fn full_car_cube(&self, custom_car_state: Option<&CarState>) -> [Vector3<f32>; 8] {
+        let car_state = custom_car_state.unwrap_or(&self.car_state);
+
+        let mut model_matrix = ModelMatrix::new();
+        model_matrix.add_translate(
+            car_state.position_wc.x,
+            car_state.position_wc.y,
+            car_state.position_wc.z,
+        );
+        model_matrix.add_rotation(0.0, car_state.angle, 0.0);
+        model_matrix.add_scale(5.0, 3.0, 10.0);
+
+        let bottom_left_inner_local = Vector4::new(-0.5, -0.5, -0.5, 1.0);
+        let bottom_right_inner_local = Vector4::new(0.5, -0.5, -0.5, 1.0);
+        let top_left_inner_local = Vector4::new(-0.5, 0.5, -0.5, 1.0);
+        let top_right_inner_local = Vector4::new(0.5, 0.5, -0.5, 1.0);
+        let bottom_left_outer_local = Vector4::new(-0.5, -0.5, 0.5, 1.0);
+        let bottom_right_outer_local = Vector4::new(0.5, -0.5, 0.5, 1.0);
+        let top_left_outer_local = Vector4::new(-0.5, 0.5, 0.5, 1.0);
+        let top_right_outer_local = Vector4::new(0.5, 0.5, 0.5, 1.0);
+
+        let bottom_left_inner = (model_matrix.matrix * bottom_left_inner_local).xyz();
+        let bottom_right_inner = (model_matrix.matrix * bottom_right_inner_local).xyz();
+        let bottom_left_outer = (model_matrix.matrix * bottom_left_outer_local).xyz();
+        let bottom_right_outer = (model_matrix.matrix * bottom_right_outer_local).xyz();
+
+        let top_left_inner = (model_matrix.matrix * top_left_inner_local).xyz();
+        let top_right_inner = (model_matrix.matrix * top_right_inner_local).xyz();
+        let top_left_outer = (model_matrix.matrix * top_left_outer_local).xyz();
+        let top_right_outer = (model_matrix.matrix * top_right_outer_local).xyz();
+
+        [
+            bottom_left_inner,
+            bottom_right_inner,
+            bottom_left_outer,
+            bottom_right_outer,
+            top_left_inner,
+            top_right_inner,
+            top_left_outer,
+            top_right_outer,
+        ]
+    }
+
+    pub fn check_collision(&mut self, info: &Collider, delta_time: f32) {
+        use Collider::*;
+        match info {
+            &BoxCollider(min_x, min_y, min_z, max_x, max_y, max_z) => {
+                let (c_min_x, c_min_y, c_min_z, c_max_x, c_max_y, c_max_z) = self.car_cube();
+
+                let perform_collision_detect = c_min_x <= max_x
+                    && c_max_x >= min_x
+                    && c_min_y <= max_y
+                    && c_max_y >= min_y
+                    && c_min_z <= max_z
+                    && c_max_z >= min_z;
+
+                if perform_collision_detect {
+                    let mut closest_x = self.car_state.position_wc.x;
+                    if self.car_state.position_wc.x > max_x {
+                        closest_x = max_x + 2.5;
+                    } else if self.car_state.position_wc.x < min_x {
+                        closest_x = min_x - 2.5;
+                    }
+
+                    let mut closest_y = self.car_state.position_wc.y;
+                    if self.car_state.position_wc.y > max_y {
+                        self.suspension.y_velocity = 0.0;
+                        closest_y = max_y + 1.5;
+                    } else if self.car_state.position_wc.y < min_y {
+                        self.suspension.y_velocity = -1.0;
+                        closest_y = min_y - 1.5;
+                    }
+
+                    let mut closest_z = self.car_state.position_wc.z;
+                    if self.car_state.position_wc.z > max_z {
+                        closest_z = max_z + 5.0;
+                    } else if self.car_state.position_wc.z < min_z {
+                        closest_z = min_z - 5.0;
+                    }
+
+                    let box_center = Vector2::new(min_x + max_x, min_z + max_z) * 0.5;
+                    if let Some(direction) = (box_center - self.car_state.position_wc.xz()).try_normalize(f32::EPSILON) {
+                        let speed = self.car_state.velocity_wc.xz().dot(&direction);
+                        if speed > 0.0 {
+                            self.register_impact(&direction, speed);
+                        }
+                    }
+
+                    self.car_state.position_wc.x = closest_x;
+                    self.car_state.position_wc.y = closest_y;
+                    self.car_state.position_wc.z = closest_z;
+                }
+            }
+            InfiniteYPlaneCollider(p0, p1) => {
+                if !self.enable_plane_collision {
+                    return;
+                }
+
+                let corners = self.full_car_cube(None);
+                let car_future_state = self.car_state.peek_time_step(delta_time, self.handbrake, self.handbrake, self.reverse);
+                let future_corners = self.full_car_cube(Some(&car_future_state));
+
+                for (corner, f_corner) in corners[..4].iter().zip(&future_corners[..4]) {
+                    let v = (p1 - p0).xz();
+
+                    let a_mat = corner.xz();
+                    let b_mat = p0.xz();
+                    let c = (f_corner.xz() - corner.xz()) / delta_time;
+                    let n = Vector2::new(-v.y, v.x);
+
+                    let t_hit = (n.dot(&(b_mat-a_mat))) / (n.dot(&c));
+                    let p_hit = a_mat + t_hit * c;
+
+                    if line_contains_point(&p0.xz(), &p1.xz(), &p_hit) && t_hit <= delta_time && t_hit >= 0.0 {
+                        let reflected = c - ((2.0 * (c.dot(&n))) / (n.dot(&n))) * n;
+
+                        let normal_speed = c.dot(&n) / n.norm();
+                        self.register_impact(&(n.normalize() * normal_speed.signum()), normal_speed.abs());
+
+                        self.car_state.velocity_wc.x = reflected.x;
+                        self.car_state.velocity_wc.z = reflected.y;
+                    }
+                }
+            }
+            MultiCollider(c) => c.iter().for_each(|info| self.check_collision(info, delta_time)),
+            // Driving on the ground is handled by the suspension
+            HeightCollider(..) | SurfaceCollider(..) | NoCollision => (),
+        }
+    }
+
+    /// Remember an impact for damage, the direction is in world coordinates and points from the car to what it hit
+    fn register_impact(&mut self, direction_wc: &Vector2<f32>, speed: f32) {
+        let (ang_sin, ang_cos) = self.car_state.angle.sin_cos();
+        let direction = Vector2::new(
+            direction_wc.x * ang_sin + direction_wc.y * ang_cos,
+            direction_wc.x * ang_cos - direction_wc.y * ang_sin,
+        );
+        let area = DamageArea::from_direction(&direction);
+        let energy = 0.5 * CAR_MASS * speed * speed;
+
+        match self.pending_impact {
+            Some((_, pending_energy)) if pending_energy >= energy => (),
+            _ => self.pending_impact = Some((area, energy)),
+        }
+    }
+
+    /// Damage the car from the hardest impact found by the collision checks since the last call, the impact is returned
+    pub fn apply_pending_impact(&mut self) -> Option<(DamageArea, f32)> {
+        let (area, energy) = self.pending_impact.take()?;
+        if self.car_state.damage.apply_impact(area, energy) {
+            log::debug!("Car damaged at {:?}, damage {:?}", area, self.car_state.damage.areas());
+        }
+
+        Some((area, energy))
+    }
+
+    /// Move the car on the ground plane, collisions should be checked before this
+    pub fn step(&mut self, delta_time: f32) {
+        if self.held {
+            // The pedals, handbrake and boost do nothing, and nothing is left over when the car is let go
+            self.boost.set_active(false);
+            let car_state = &mut self.car_state;
+            car_state.velocity_wc.x = 0.0;
+            car_state.velocity_wc.z = 0.0;
+            car_state.angular_velocity = 0.0;
+            car_state.wheel_rotation_speed = 0.0;
+            car_state.boost_force = 0.0;
+            return;
+        }
+
+        let drifting = self.is_drifting();
+        self.car_state.boost_force = self.boost.update(&self.tuning, delta_time, drifting);
+
+        self.car_state
+            .perform_physics_time_step(delta_time, self.handbrake, self.handbrake, self.reverse);
+    }
+
+    /// Move the car body up and down on its springs, the raycast function takes an origin, a direction and a max distance
+    pub fn update_suspension(
+        &mut self,
+        delta_time: f32,
+        raycast: impl Fn(&Vector3<f32>, &Vector3<f32>, f32) -> Option<RaycastHit>,
+    ) {
+        self.suspension.update(&mut self.car_state, delta_time, &raycast);
+
+        // The springs keep the car above the ground, this only stops it from sinking through when they bottom out
+        let down = Vector3::new(0.0, -1.0, 0.0);
+        let origin = self.car_state.position_wc - down * GROUND_PROBE_HEIGHT;
+        if let Some(hit) = raycast(&origin, &down, GROUND_PROBE_HEIGHT + MIN_RIDE_HEIGHT) {
+            let min_y = hit.point.y + MIN_RIDE_HEIGHT;
+            if self.car_state.position_wc.y < min_y {
+                self.car_state.position_wc.y = min_y;
+                self.suspension.y_velocity = self.suspension.y_velocity.max(0.0);
+            }
+        }
+    }
+
+    /// If the car is sliding sideways on the ground
+    pub fn is_drifting(&self) -> bool {
+        let (ang_sin, ang_cos) = self.car_state.angle.sin_cos();
+        let lateral_speed =
+            self.car_state.velocity_wc.x * ang_cos - self.car_state.velocity_wc.z * ang_sin;
+        self.suspension.is_grounded() && lateral_speed.abs() > DRIFT_SPEED
+    }
+}
@@ -2,7 +2,9 @@ use std::f32;
 
 use nalgebra::Vector3;
 
-use crate::utils::limit;
+use crate::{core::surface::Surface, utils::limit};
+
+use super::damage::Damage;
 
 const DRAG_FORCE: f32 = 5.0;
 const ROLLING_RESISTANCE: f32 = 30.0;
@@ -15,7 +17,7 @@ const CAR_C: f32 = 1.0;
 const CAR_WHEELBASE: f32 = CAR_B * CAR_C;
 #[allow(dead_code)]
 const CAR_H: f32 = 1.0;
-const CAR_MASS: f32 = 600.0;
+pub const CAR_MASS: f32 = 600.0;
 const CAR_INERTIA: f32 = 600.0;
 #[allow(dead_code)]
 const CAR_WIDTH: f32 = 1.5;
@@ -26,6 +28,10 @@ const CAR_WHEEL_LENGTH: f32 = 1.4;
 const CAR_WHEEL_WIDTH: f32 = 0.3;
 
 const GRAVITY: f32 = 9.8;
+// Top speed with full throttle on asphalt, used to limit the speed of a damaged car
+const TOP_SPEED: f32 = 42.0;
+// How strongly a surface slows the car down when it is going faster than the surface allows
+const OVERSPEED_RESISTANCE: f32 = 200.0;
 
 #[derive(Clone)]
 pub struct CarState {
@@ -37,6 +43,24 @@ pub struct CarState {
     pub throttle: f32,
     pub brake: f32,
     pub wheel_rotation_speed: f32,
+    // Acceleration in car coordinates (x is forward, z is to the left) from the last time step
+    pub acceleration: Vector3<f32>,
+    // Front left, front right, rear left, rear right
+    pub wheel_contact: [bool; 4],
+    // The surface each wheel last touched
+    pub wheel_surfaces: [Surface; 4],
+    // Average normal of the ground under the wheels that touch it, gravity pulls the car down slopes
+    pub ground_normal: Vector3<f32>,
+    pub damage: Damage,
+    // Multiplier for the aerodynamic drag, lower when driving in the slipstream of another car
+    pub drag_factor: f32,
+    // Extra traction force from the boost
+    pub boost_force: f32,
+    // Tire slip angles and lateral forces from the last time step, only used for telemetry
+    pub slip_angle_front: f32,
+    pub slip_angle_rear: f32,
+    pub front_lateral_force: f32,
+    pub rear_lateral_force: f32,
 }
 
 impl CarState {
@@ -50,6 +74,17 @@ impl CarState {
             throttle: 0.0,
             brake: 0.0,
             wheel_rotation_speed: 0.0,
+            acceleration: Vector3::zeros(),
+            wheel_contact: [true; 4],
+            wheel_surfaces: [Surface::Asphalt; 4],
+            ground_normal: Vector3::new(0.0, 1.0, 0.0),
+            damage: Damage::new(),
+            drag_factor: 1.0,
+            boost_force: 0.0,
+            slip_angle_front: 0.0,
+            slip_angle_rear: 0.0,
+            front_lateral_force: 0.0,
+            rear_lateral_force: 0.0,
         }
     }
 
@@ -84,16 +119,40 @@ impl CarState {
             f32::atan2(velocity.z, velocity.x)
         };
 
-        let slip_angle_front = sideslip + rot_angle - self.steering_angle;
+        // Damage to the sides of the car makes it pull to one side
+        let steering_angle = self.steering_angle + self.damage.steering_pull();
+
+        let slip_angle_front = sideslip + rot_angle - steering_angle;
         let slip_angle_rear = sideslip - rot_angle;
 
         let weight = CAR_MASS * GRAVITY * 0.5;
 
+        // Tires only produce force while they are touching the ground
+        let front_contact = self.axle_contact(0, 1);
+        let rear_contact = self.axle_contact(2, 3);
+
+        let front_grip = self.axle_surface(0, 1, Surface::grip);
+        let rear_grip = self.axle_surface(2, 3, Surface::grip);
+        let rolling_resistance = ROLLING_RESISTANCE
+            * (self.axle_surface(0, 1, Surface::rolling_resistance)
+                + self.axle_surface(2, 3, Surface::rolling_resistance))
+            * 0.5;
+        let max_speed = self
+            .wheel_surfaces
+            .iter()
+            .zip(self.wheel_contact)
+            .filter(|(_, contact)| *contact)
+            .map(|(surface, _)| surface.max_speed())
+            .fold(f32::INFINITY, f32::min)
+            .min(self.damaged_top_speed());
+
         // Lateral force on the front wheels
         let mut front_lateral_force = Vector3::new(
             0.0,
             0.0,
-            limit(CA_F * slip_angle_front, -MAX_GRIP, MAX_GRIP) * weight,
+            limit(CA_F * front_grip * slip_angle_front, -MAX_GRIP * front_grip, MAX_GRIP * front_grip)
+                * weight
+                * front_contact,
         );
         if front_slip {
             front_lateral_force.z *= 0.5;
@@ -103,14 +162,16 @@ impl CarState {
         let mut rear_lateral_force = Vector3::new(
             0.0,
             0.0,
-            limit(CA_R * slip_angle_rear, -MAX_GRIP, MAX_GRIP) * weight,
+            limit(CA_R * rear_grip * slip_angle_rear, -MAX_GRIP * rear_grip, MAX_GRIP * rear_grip)
+                * weight
+                * rear_contact,
         );
         if rear_slip {
             rear_lateral_force.z *= 0.5;
         }
 
         let mut traction_force = Vector3::new(
-            100.0 * (self.throttle - self.brake * velocity.x.signum()),
+            100.0 * (self.throttle - self.brake * velocity.x.signum()) + self.boost_force,
             0.0,
             0.0,
         );
@@ -120,30 +181,45 @@ impl CarState {
         if rear_slip {
             traction_force.x *= 0.5;
         }
+        traction_force.x *= rear_contact * rear_grip;
+        if self.damage.is_disabled() {
+            traction_force.x = 0.0;
+        }
 
         // Force and torque on body
 
+        let overspeed = (velocity.x.abs() - max_speed).max(0.0);
         let resistance = -Vector3::new(
-            ROLLING_RESISTANCE * velocity.x + DRAG_FORCE * velocity.x * velocity.x.abs(),
+            rolling_resistance * velocity.x
+                + DRAG_FORCE * self.drag_factor * velocity.x * velocity.x.abs()
+                + OVERSPEED_RESISTANCE * overspeed * velocity.x.signum(),
             0.0,
-            ROLLING_RESISTANCE * velocity.z + DRAG_FORCE * velocity.z * velocity.z.abs(),
+            rolling_resistance * velocity.z
+                + DRAG_FORCE * self.drag_factor * velocity.z * velocity.z.abs(),
         );
 
         let force = traction_force
+            + self.slope_force(sin_ang, cos_ang)
             + Vector3::new(
-                self.steering_angle.sin() * front_lateral_force.x,
+                steering_angle.sin() * front_lateral_force.x,
                 0.0,
-                self.steering_angle.cos() * front_lateral_force.z,
+                steering_angle.cos() * front_lateral_force.z,
             )
             + rear_lateral_force
             + resistance;
 
         let torque = CAR_B * front_lateral_force.z - CAR_C * rear_lateral_force.z;
 
+        self.slip_angle_front = slip_angle_front;
+        self.slip_angle_rear = slip_angle_rear;
+        self.front_lateral_force = front_lateral_force.z;
+        self.rear_lateral_force = rear_lateral_force.z;
+
         // Acceleration
 
         let acceleration = force / CAR_MASS;
         let angular_acceleration = torque / CAR_INERTIA;
+        self.acceleration = acceleration;
 
         // Velocity and position
         let acceleration_wc = Vector3::new(
@@ -169,6 +245,38 @@ impl CarState {
         self.angle += delta_time * self.angular_velocity;
     }
 
+    /// The part of gravity that pulls the car along the ground, in car coordinates.
+    /// On a banked road this is what pushes the car towards the inside of the corner
+    fn slope_force(&self, sin_ang: f32, cos_ang: f32) -> Vector3<f32> {
+        let contact = (self.axle_contact(0, 1) + self.axle_contact(2, 3)) * 0.5;
+        let n = &self.ground_normal;
+        let force_wc = CAR_MASS * GRAVITY * n.y * contact * Vector3::new(n.x, 0.0, n.z);
+
+        Vector3::new(
+            sin_ang * force_wc.x + cos_ang * force_wc.z,
+            0.0,
+            cos_ang * force_wc.x - sin_ang * force_wc.z,
+        )
+    }
+
+    fn axle_contact(&self, left: usize, right: usize) -> f32 {
+        (self.wheel_contact[left] as i32 + self.wheel_contact[right] as i32) as f32 * 0.5
+    }
+
+    fn damaged_top_speed(&self) -> f32 {
+        let factor = self.damage.top_speed_factor();
+        if factor < 1.0 {
+            TOP_SPEED * factor
+        } else {
+            f32::INFINITY
+        }
+    }
+
+    // Average of a surface property over the wheels on an axle
+    fn axle_surface(&self, left: usize, right: usize, property: fn(&Surface) -> f32) -> f32 {
+        (property(&self.wheel_surfaces[left]) + property(&self.wheel_surfaces[right])) * 0.5
+    }
+
     pub fn peek_time_step(&self, delta_time: f32, front_slip: bool, rear_slip: bool, reverse: bool) -> CarState {
         let mut future_state = self.clone();
         future_state.perform_physics_time_step(delta_time, front_slip, rear_slip, reverse);
@@ -0,0 +1,105 @@
+use nalgebra::Vector2;
+
+// Impacts with less energy than this don't damage the car
+const MIN_IMPACT_ENERGY: f32 = 5000.0;
+// Impact energy needed to fully damage an area of the car
+const FULL_DAMAGE_ENERGY: f32 = 1_500_000.0;
+// How much slower the car gets with a fully damaged front or rear
+const MAX_TOP_SPEED_LOSS: f32 = 0.5;
+// Steering angle the car pulls with when one side is fully damaged
+const MAX_STEERING_PULL: f32 = 0.04;
+// The car stops working when any area has taken this much damage
+const DISABLED_DAMAGE: f32 = 0.9;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DamageArea {
+    Front,
+    Rear,
+    Left,
+    Right,
+}
+
+impl DamageArea {
+    /// The area of the car that hits something in the given direction.
+    /// The direction is in car coordinates, x is forward and y is to the left
+    pub fn from_direction(direction: &Vector2<f32>) -> DamageArea {
+        use DamageArea::*;
+        if direction.x.abs() >= direction.y.abs() {
+            if direction.x >= 0.0 {
+                Front
+            } else {
+                Rear
+            }
+        } else if direction.y >= 0.0 {
+            Left
+        } else {
+            Right
+        }
+    }
+
+    fn index(&self) -> usize {
+        use DamageArea::*;
+        match self {
+            Front => 0,
+            Rear => 1,
+            Left => 2,
+            Right => 3,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Damage {
+    // Front, rear, left, right, from 0 (no damage) to 1 (fully damaged)
+    areas: [f32; 4],
+}
+
+impl Damage {
+    pub fn new() -> Damage {
+        Damage { areas: [0.0; 4] }
+    }
+
+    pub fn from_areas(areas: [f32; 4]) -> Damage {
+        Damage {
+            areas: areas.map(|a| a.clamp(0.0, 1.0)),
+        }
+    }
+
+    pub fn areas(&self) -> [f32; 4] {
+        self.areas
+    }
+
+    pub fn area(&self, area: DamageArea) -> f32 {
+        self.areas[area.index()]
+    }
+
+    /// Add damage to an area from an impact with the given energy, returns true if the car was damaged
+    pub fn apply_impact(&mut self, area: DamageArea, energy: f32) -> bool {
+        if energy < MIN_IMPACT_ENERGY {
+            return false;
+        }
+
+        let damage = &mut self.areas[area.index()];
+        *damage = (*damage + (energy - MIN_IMPACT_ENERGY) / FULL_DAMAGE_ENERGY).min(1.0);
+        true
+    }
+
+    pub fn total(&self) -> f32 {
+        self.areas.iter().cloned().fold(0.0, f32::max)
+    }
+
+    /// Multiplier for the top speed of the car
+    pub fn top_speed_factor(&self) -> f32 {
+        let engine_damage = self.area(DamageArea::Front).max(self.area(DamageArea::Rear));
+        1.0 - engine_damage * MAX_TOP_SPEED_LOSS
+    }
+
+    /// Steering angle added to the steering, a damaged side pulls the car towards it
+    pub fn steering_pull(&self) -> f32 {
+        (self.area(DamageArea::Left) - self.area(DamageArea::Right)) * MAX_STEERING_PULL
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.areas.iter().any(|&a| a >= DISABLED_DAMAGE)
+    }
+}
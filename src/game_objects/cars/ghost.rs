@@ -0,0 +1,311 @@
+use std::{
+    f32, fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use anyhow::anyhow;
+use glow::Context;
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::{color::Color, constants::GHOST_FOLDER, game::Game, game_object::GameObject},
+    game_objects::race::lap_records::user_data_folder,
+    objects::mesh_model::MeshModel,
+};
+
+use super::car::Car;
+
+// Seconds between the samples of a recorded lap, the ghost is interpolated in between
+const SAMPLE_INTERVAL: f32 = 0.05;
+// Samples searched ahead of the last closest sample when comparing the player with the ghost
+const SEARCH_AHEAD: usize = 40;
+const GHOST_ALPHA: f32 = 0.35;
+// The ghost turns green when the player is ahead of it and red when behind, fully at this many seconds
+const FULL_TINT_DELTA: f32 = 1.0;
+const MAX_TINT: f32 = 0.8;
+
+/// Where the car was at a time into the lap
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GhostSample {
+    pub time: f32,
+    pub position: (f32, f32, f32),
+    pub angle: f32,
+    pub steering_angle: f32,
+}
+
+/// A recorded lap that can be driven again by a ghost
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GhostLap {
+    pub lap_time: f32,
+    pub samples: Vec<GhostSample>,
+}
+
+impl GhostLap {
+    pub fn load(path: &Path) -> anyhow::Result<GhostLap> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read ghost file {}. {e}", path.display()))?;
+        ron::from_str(&text).map_err(|e| anyhow!("Failed to parse ghost file {}. {e}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(folder) = path.parent() {
+            fs::create_dir_all(folder)
+                .map_err(|e| anyhow!("Failed to create ghost folder {}. {e}", folder.display()))?;
+        }
+        let text = ron::to_string(self).map_err(|e| anyhow!("Failed to serialize ghost. {e}"))?;
+        fs::write(path, text).map_err(|e| anyhow!("Failed to write ghost file {}. {e}", path.display()))
+    }
+
+    /// Where the ghost is at the time into the lap, None after it has finished the lap
+    pub fn sample_at(&self, time: f32) -> Option<GhostSample> {
+        let next = self.samples.iter().position(|s| s.time >= time)?;
+        let (a, b) = (self.samples[next.saturating_sub(1)], self.samples[next]);
+        if b.time <= a.time {
+            return Some(b);
+        }
+
+        let t = (time - a.time) / (b.time - a.time);
+        let lerp = |from: f32, to: f32| from + (to - from) * t;
+        // Turn the short way around when the angle wraps
+        let turn = (b.angle - a.angle + f32::consts::PI).rem_euclid(2.0 * f32::consts::PI) - f32::consts::PI;
+
+        Some(GhostSample {
+            time,
+            position: (
+                lerp(a.position.0, b.position.0),
+                lerp(a.position.1, b.position.1),
+                lerp(a.position.2, b.position.2),
+            ),
+            angle: a.angle + turn * t,
+            steering_angle: lerp(a.steering_angle, b.steering_angle),
+        })
+    }
+
+    /// Index of the sample closest to the position. With a previous index only the samples from it
+    /// a bit ahead are searched, so parts of the track that pass close to each other aren't mixed up
+    pub fn closest_sample(&self, position: &Vector3<f32>, previous: Option<usize>) -> Option<usize> {
+        let distance = |i: &usize| {
+            let (x, y, z) = self.samples[*i].position;
+            (Vector3::new(x, y, z) - position).norm()
+        };
+
+        let range = match previous {
+            Some(previous) => previous..(previous + SEARCH_AHEAD).min(self.samples.len()),
+            None => 0..self.samples.len(),
+        };
+        range.min_by(|a, b| distance(a).total_cmp(&distance(b)))
+    }
+}
+
+/// Samples the car while a lap is driven
+pub struct GhostRecorder {
+    samples: Vec<GhostSample>,
+}
+
+impl Default for GhostRecorder {
+    fn default() -> GhostRecorder {
+        GhostRecorder::new()
+    }
+}
+
+impl GhostRecorder {
+    pub fn new() -> GhostRecorder {
+        GhostRecorder { samples: Vec::new() }
+    }
+
+    pub fn start(&mut self) {
+        self.samples.clear();
+    }
+
+    pub fn record(&mut self, time: f32, car: &Car) {
+        let due = self.samples.last().is_none_or(|s| time - s.time >= SAMPLE_INTERVAL);
+        if due {
+            let position = car.position();
+            self.samples.push(GhostSample {
+                time,
+                position: (position.x, position.y, position.z),
+                angle: car.angle(),
+                steering_angle: car.steering_angle(),
+            });
+        }
+    }
+
+    pub fn finish(&mut self, lap_time: f32) -> GhostLap {
+        GhostLap { lap_time, samples: std::mem::take(&mut self.samples) }
+    }
+}
+
+/// Records the laps of the player and shows the best one as a see through car driving along
+pub struct TimeTrial<'a> {
+    // None when the laps can't be saved
+    path: Option<PathBuf>,
+    best: Option<GhostLap>,
+    recorder: GhostRecorder,
+    ghost_car: Car<'a>,
+    ghost_visible: bool,
+    closest_sample: Option<usize>,
+    // Seconds the player is behind the ghost, negative when ahead
+    delta: Option<f32>,
+}
+
+impl<'a> TimeTrial<'a> {
+    pub fn new(
+        track: &str,
+        profile: &str,
+        car_model: Rc<MeshModel<'a>>,
+        wheel_model: Rc<MeshModel<'a>>,
+        gl: &'a Context,
+        game: &Game,
+    ) -> TimeTrial<'a> {
+        let path = ghost_path(track, profile);
+        if path.is_none() {
+            log::warn!("No user data folder, the best laps will not be saved");
+        }
+        // Without a file nobody has finished a lap on the track yet
+        let best = path
+            .as_deref()
+            .filter(|path| path.exists())
+            .and_then(|path| GhostLap::load(path).map_err(|e| log::error!("{e:#}")).ok());
+        if let Some(best) = &best {
+            log::info!("Racing against the best lap of {:.3}", best.lap_time);
+        }
+
+        let mut ghost_car = Car::new(false, car_model, wheel_model, gl, game);
+        ghost_car.set_alpha(GHOST_ALPHA);
+
+        TimeTrial {
+            path,
+            best,
+            recorder: GhostRecorder::new(),
+            ghost_car,
+            ghost_visible: false,
+            closest_sample: None,
+            delta: None,
+        }
+    }
+
+    pub fn delta(&self) -> Option<f32> {
+        self.delta
+    }
+
+    /// A new lap starts, the lap that was being recorded didn't count
+    pub fn lap_started(&mut self) {
+        self.recorder.start();
+        self.closest_sample = None;
+    }
+
+    /// A valid lap was finished, it becomes the ghost if it is the fastest so far
+    pub fn lap_finished(&mut self, lap_time: f32) {
+        let lap = self.recorder.finish(lap_time);
+        let faster = self.best.as_ref().is_none_or(|best| lap_time < best.lap_time);
+        if faster && !lap.samples.is_empty() {
+            log::info!("New best lap: {lap_time:.3}");
+            if let Err(e) = self.path.as_deref().map_or(Ok(()), |path| lap.save(path)) {
+                log::error!("{e:#}");
+            }
+            self.best = Some(lap);
+        }
+        self.lap_started();
+    }
+
+    /// Record the player car and move the ghost to where it was at the same time into its lap
+    pub fn update(&mut self, lap_time: Option<f32>, car: &Car) {
+        let lap_time = match lap_time {
+            Some(t) => t,
+            None => {
+                self.ghost_visible = false;
+                self.delta = None;
+                return;
+            }
+        };
+        self.recorder.record(lap_time, car);
+
+        let best = match &self.best {
+            Some(b) => b,
+            None => return,
+        };
+
+        let sample = best.sample_at(lap_time);
+        self.ghost_visible = sample.is_some();
+        if let Some(sample) = sample {
+            let (x, y, z) = sample.position;
+            self.ghost_car.set_position(Vector3::new(x, y, z));
+            self.ghost_car.set_angle(sample.angle);
+            self.ghost_car.set_steering_angle(sample.steering_angle);
+        }
+
+        // The ghost was at the point the player is at now this long ago, or will be this long from now
+        self.closest_sample = best.closest_sample(car.position(), self.closest_sample);
+        self.delta = self.closest_sample.map(|i| lap_time - best.samples[i].time);
+
+        let tint = self.delta.map(|delta| {
+            let amount = (delta.abs() / FULL_TINT_DELTA).min(1.0) * MAX_TINT;
+            let color = if delta < 0.0 { Color::new(0.1, 0.9, 0.2) } else { Color::new(0.9, 0.1, 0.1) };
+            (color, amount)
+        });
+        self.ghost_car.set_tint(tint);
+    }
+
+    /// The ghost is see through, so it is drawn in the translucent pass of the game
+    pub fn display(&self, game: &Game, gl: &'a Context) {
+        if self.ghost_visible {
+            self.ghost_car.display(game, gl);
+        }
+    }
+}
+
+/// The best lap of a track is saved in the ghost folder, under the name of the track and the car profile
+/// like the lap records, so an arcade lap with the boost isn't the ghost for the standard car.
+/// None when the system has no user data folder
+pub fn ghost_path(track: &str, profile: &str) -> Option<PathBuf> {
+    user_data_folder().map(|folder| folder.join(GHOST_FOLDER).join(format!("{track}-{profile}.ron")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn straight_lap() -> GhostLap {
+        let samples = (0..=10)
+            .map(|i| GhostSample {
+                time: i as f32,
+                position: (0.0, 30.0, 10.0 * i as f32),
+                angle: 0.0,
+                steering_angle: 0.0,
+            })
+            .collect();
+        GhostLap { lap_time: 10.0, samples }
+    }
+
+    #[test]
+    fn ghost_is_interpolated_between_samples() {
+        let mut lap = straight_lap();
+        lap.samples[4].angle = 3.0;
+        lap.samples[5].angle = -3.0;
+
+        let sample = lap.sample_at(4.5).unwrap();
+        assert!((sample.position.2 - 45.0).abs() < 1e-4);
+        // Half way between turns through PI, not through 0
+        assert!((sample.angle.abs() - f32::consts::PI).abs() < 1e-4, "angle {}", sample.angle);
+        assert!(lap.sample_at(10.5).is_none());
+    }
+
+    #[test]
+    fn delta_is_found_from_the_closest_sample() {
+        let lap = straight_lap();
+        // The ghost passed z = 52 at 5 seconds, a player getting there at 6 seconds is a second behind
+        let closest = lap.closest_sample(&Vector3::new(1.0, 30.0, 52.0), Some(2)).unwrap();
+        assert_eq!(closest, 5);
+        assert_eq!(lap.closest_sample(&Vector3::new(0.0, 30.0, 0.0), Some(8)), Some(8));
+    }
+
+    #[test]
+    fn lap_survives_saving() {
+        let lap = straight_lap();
+        let path = std::env::temp_dir().join("ghost_lap_survives_saving.ron");
+        lap.save(&path).unwrap();
+        assert_eq!(GhostLap::load(&path).unwrap(), lap);
+    }
+}
@@ -1,4 +1,17 @@
 pub mod car;
 pub mod network_car;
 pub mod player_car;
-mod car_state;
\ No newline at end of file
+pub mod player_input;
+mod car_physics;
+mod car_state;
+pub mod damage;
+mod suspension;
+pub mod slipstream;
+pub mod boost;
+pub mod ghost;
+pub mod bot_car;
+pub mod bot_driver;
+pub mod tuning;
+pub mod telemetry;
+#[cfg(test)]
+mod simulation;
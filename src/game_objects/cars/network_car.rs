@@ -12,12 +12,16 @@ use crate::{
     objects::mesh_model::MeshModel,
 };
 
-use super::car::Car;
+use super::{car::Car, damage::Damage};
 
 pub struct NetworkCar<'a> {
     player_id: u8,
     car: Car<'a>,
     collider: Option<Collider>,
+    // The start of the race the car is in, laps are timed from the start
+    start_number: u32,
+    // Race time when the current lap started, the server only says when a lap is done
+    lap_start_time: f32,
 }
 
 impl<'a> NetworkCar<'a> {
@@ -30,6 +34,13 @@ impl<'a> NetworkCar<'a> {
     ) -> NetworkCar<'a> {
         let mut car = Car::new(false, car_model, wheel_model, gl, game);
         car.set_position(Vector3::new(5.0, 35.0, 0.0));
+        let name = format!("Player {player_id}");
+        car.set_name(name.clone());
+
+        let mut race = game.race.borrow_mut();
+        race.add_car(&name, false);
+        let start_number = race.start_number();
+        drop(race);
 
         let light_id = format!("NETWORK_CAR_{}", player_id);
         let mut lights = game.lights.borrow_mut();
@@ -44,12 +55,18 @@ impl<'a> NetworkCar<'a> {
             player_id,
             car,
             collider: None,
+            start_number,
+            lap_start_time: 0.0,
         }
     }
 
     fn light_id(&self) -> String {
         format!("NETWORK_CAR_{}", self.player_id)
     }
+
+    fn name(&self) -> String {
+        format!("Player {}", self.player_id)
+    }
 }
 
 impl<'a> GameObject<'a> for NetworkCar<'a> {
@@ -60,17 +77,32 @@ impl<'a> GameObject<'a> for NetworkCar<'a> {
     fn on_event(&mut self, _game: &Game, _event: &sdl2::event::Event) {}
 
     fn update(&mut self, game: &Game, gl: &'a Context) {
+        self.car.set_owner(self as *const _ as *const usize);
         self.car.update(game, gl);
-        let (min_x, min_y, min_z, max_x, max_y, max_z) = self.car.car_cube(game);
+        let (min_x, min_y, min_z, max_x, max_y, max_z) = self.car.car_cube();
         self.collider = Some(Collider::BoxCollider(
             min_x, min_y, min_z, max_x, max_y, max_z,
         ));
 
+        let start_number = game.race.borrow().start_number();
+        if start_number != self.start_number {
+            self.start_number = start_number;
+            self.lap_start_time = 0.0;
+        }
+
         loop {
             let mut game_events = game.server_connection.game_events.borrow_mut();
             let event = game_events.front();
 
             match event {
+                Some(NetworkEvent::LapCompleted { player_id }) if self.player_id == *player_id => {
+                    let mut race = game.race.borrow_mut();
+                    let lap_time = race.race_time() - self.lap_start_time;
+                    self.lap_start_time = race.race_time();
+                    race.lap_completed(&self.name(), lap_time);
+
+                    game_events.pop_front();
+                }
                 Some(NetworkEvent::PlayerDisconnected { player_id })
                     if self.player_id == *player_id =>
                 {
@@ -79,6 +111,8 @@ impl<'a> GameObject<'a> for NetworkCar<'a> {
                         .borrow_mut()
                         .push_back(self as *const _ as *const usize);
                     game.lights.borrow_mut().remove_light(&self.light_id());
+                    game.race.borrow_mut().remove_car(&self.name());
+                    self.car.remove_from_game(game);
 
                     game_events.pop_front();
                 }
@@ -93,6 +127,12 @@ impl<'a> GameObject<'a> for NetworkCar<'a> {
         self.car.set_position(status.position.into());
         self.car.set_angle(status.rotation);
         self.car.set_steering_angle(status.steering_angle);
+        self.car
+            .boost_mut()
+            .set_active(game.server_connection.is_boosting(self.player_id));
+        if let Some(areas) = game.server_connection.last_damage(self.player_id) {
+            self.car.set_damage(Damage::from_areas(areas));
+        }
 
         let pos = self.car.light_position();
         game.lights.borrow_mut().set_light_position(&self.light_id(), &Vector3::new(pos.x, pos.y, pos.z));
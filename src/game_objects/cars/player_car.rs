@@ -6,22 +6,49 @@ use sdl2::{event::Event, keyboard::Keycode};
 
 use crate::{
     core::{
-        constants::{
-            FINISH_LINE_X_START, FINISH_LINE_X_STOP, FINISH_LINE_Z, HALF_RING_LINE_Z,
-            HALF_RING_X_START, HALF_RING_X_STOP,
-        },
         game::Game,
-        game_object::GameObject, color::Color,
+        game_object::{Collider, GameObject}, color::Color,
+    },
+    game_objects::{
+        cars::car::ViewState,
+        race::{
+            lap_records::{car_profile, track_name},
+            lap_timer::LapTimer,
+            race_controller::RaceState,
+        },
+        replay::replay_file::ReplayEvent,
     },
-    game_objects::cars::car::ViewState,
     network::server_connection::NetworkEvent,
     objects::mesh_model::MeshModel,
 };
 
-use super::{super::track::track_segment::TRACK_ELEVATION, car::Car};
+use super::{
+    super::track::{
+        checkpoints::{CheckpointEvent, LapTracker},
+        track::Track,
+        track_segment::TRACK_ELEVATION,
+    },
+    car::Car,
+    ghost::TimeTrial,
+    player_input::{CarAction, PlayerInput},
+    telemetry::TelemetryRecorder,
+};
 
 const LOOK_DIST: f32 = 0.9;
 
+// Seconds the car can be in each bad state before it is moved back to the track
+const OFF_TRACK_RESPAWN_TIME: f32 = 5.0;
+const UPSIDE_DOWN_RESPAWN_TIME: f32 = 2.0;
+const STUCK_RESPAWN_TIME: f32 = 4.0;
+// The car counts as stuck if it is slower than this while the throttle is pressed
+const STUCK_SPEED: f32 = 1.0;
+// Damage and boost are sent when they change and also this often in seconds, in case a packet is lost
+const STATE_RESEND_TIME: f32 = 1.0;
+// Y on an xbox controller
+const JOYSTICK_RESET_BUTTON: u32 = 3;
+// A on an xbox controller
+const JOYSTICK_BOOST_BUTTON: u32 = 0;
+
 enum BrakingState {
     None,
     Braking,
@@ -29,34 +56,122 @@ enum BrakingState {
 }
 
 pub struct PlayerCar<'a> {
+    // Index of the player at this computer, it picks the camera and the place on the grid
+    player: usize,
+    name: String,
+    input: PlayerInput,
+    light_id: String,
     car: Car<'a>,
-    lap_half_ring_complete: bool,
+    lap_tracker: LapTracker,
+    // Where the car was after the last update, checkpoints are crossed on the way from there
+    previous_position: Vector3<f32>,
     braking_state: BrakingState,
     joystic_braking_state: BrakingState,
+    off_track_time: f32,
+    upside_down_time: f32,
+    stuck_time: f32,
+    respawn_requested: bool,
+    joystick_reset_pressed: bool,
+    state_resend_time: f32,
+    keyboard_boost: bool,
+    telemetry: Option<TelemetryRecorder>,
+    // So the bots can run into the player
+    collider: Option<Collider>,
+    time_trial: Option<TimeTrial<'a>>,
+    lap_timer: LapTimer,
+    // The start of the race the car is in, when the race is restarted the car moves to the grid
+    start_number: u32,
 }
 
 impl<'a> PlayerCar<'a> {
+    /// The player is counted from 0, each player at the computer drives with their own input
     pub fn new(
+        player: usize,
+        input: PlayerInput,
         car_model: Rc<MeshModel<'a>>,
         wheel_model: Rc<MeshModel<'a>>,
         gl: &'a Context,
         game: &Game,
     ) -> PlayerCar<'a> {
-        let car = Car::new(true, car_model, wheel_model, gl, game);
+        let split_screen = game.players.len() > 1;
+        let name = match split_screen {
+            true => format!("Player {}", player + 1),
+            false => String::from("Player"),
+        };
+        if split_screen {
+            log::info!("{name} drives with {input}");
+        }
+
+        let mut car = Car::new(true, car_model.clone(), wheel_model.clone(), gl, game);
+        car.set_name(name.clone());
+        // In multiplayer the server tells the car when to move to the grid
+        if !game.server_connection.is_multiplayer() {
+            let (position, angle) = PlayerCar::spawn_position(game, player as u8 + 1);
+            car.set_position(position);
+            car.set_angle(angle);
+        }
+
+        // There is one telemetry file, it is for the first player
+        let telemetry = game.telemetry_path.as_ref().filter(|_| player == 0).and_then(|path| {
+            TelemetryRecorder::new(path)
+                .map_err(|e| log::error!("{e:#}"))
+                .ok()
+        });
+
+        let (track, profile) = (track_name(&game.track_path, &game.track_file), car_profile(game.arcade_mode));
+        // There is one ghost file for the track and car, so only one player can drive against it
+        let time_trial = match (game.time_trial, game.server_connection.is_multiplayer() || split_screen) {
+            (true, true) => {
+                if player == 0 {
+                    log::warn!("Time trial is only for one player, racing without a ghost");
+                }
+                None
+            }
+            (true, false) if player == 0 => Some(TimeTrial::new(&track, profile, car_model, wheel_model, gl, game)),
+            _ => None,
+        };
+
+        let lap_timer = LapTimer::new(&track, profile);
 
+        let mut race = game.race.borrow_mut();
+        race.add_car(&name, true);
+        let start_number = race.start_number();
+        drop(race);
+
+        let light_id = match player {
+            0 => String::from("PLAYER_CAR"),
+            _ => format!("PLAYER_CAR_{}", player + 1),
+        };
         let mut lights = game.lights.borrow_mut();
-        lights.add_light("PLAYER_CAR");
-        lights.set_light_diffuse("PLAYER_CAR", &Color::new(0.89, 0.91, 1.00));
+        lights.add_light(&light_id);
+        lights.set_light_diffuse(&light_id, &Color::new(0.89, 0.91, 1.00));
         lights
-            .set_light_ambient("PLAYER_CAR", &Color::with_alpha(0.0, 0.0, 0.0, 0.0));
-        lights.set_light_specular("PLAYER_CAR", &Color::new(0.89, 0.91, 1.00));
-        lights.set_light_max_radius("PLAYER_CAR", 50.0);
+            .set_light_ambient(&light_id, &Color::with_alpha(0.0, 0.0, 0.0, 0.0));
+        lights.set_light_specular(&light_id, &Color::new(0.89, 0.91, 1.00));
+        lights.set_light_max_radius(&light_id, 50.0);
 
         PlayerCar {
+            player,
+            name,
+            input,
+            light_id,
+            previous_position: *car.position(),
             car,
-            lap_half_ring_complete: false,
+            lap_tracker: LapTracker::new(),
             braking_state: BrakingState::None,
             joystic_braking_state: BrakingState::None,
+            off_track_time: 0.0,
+            upside_down_time: 0.0,
+            stuck_time: 0.0,
+            respawn_requested: false,
+            joystick_reset_pressed: false,
+            state_resend_time: 0.0,
+            keyboard_boost: false,
+            telemetry,
+            lap_timer,
+            collider: None,
+            time_trial,
+            start_number,
         }
     }
 
@@ -65,8 +180,8 @@ impl<'a> PlayerCar<'a> {
     }
 
     fn handle_joystick_controls(&mut self, game: &Game) {
-        let joystick = match &game.joystick {
-            Some(j) if j.attached() => j,
+        let joystick = match self.input.joystick.and_then(|index| game.joystick(index)) {
+            Some(j) => j,
             _ => return,
         };
 
@@ -106,6 +221,17 @@ impl<'a> PlayerCar<'a> {
             self.joystic_braking_state = BrakingState::None;
         }
 
+        let reset_pressed = joystick.button(JOYSTICK_RESET_BUTTON).unwrap_or(false);
+        if reset_pressed && !self.joystick_reset_pressed {
+            self.respawn_requested = true;
+        }
+        self.joystick_reset_pressed = reset_pressed;
+
+        let joystick_boost = joystick.button(JOYSTICK_BOOST_BUTTON).unwrap_or(false);
+        self.car
+            .boost_mut()
+            .set_requested(game.arcade_mode && (self.keyboard_boost || joystick_boost));
+
         let left_x_axis = joystick.axis(0).unwrap_or(0);
         // let left_y_axis = joystick.axis(1).unwrap_or(0);
         self.car.set_steering_angle(
@@ -114,141 +240,329 @@ impl<'a> PlayerCar<'a> {
         );
     }
 
-    fn spawn_position(player_id: u8) -> Vector3<f32> {
-        match player_id {
-            1 => Vector3::new(-4.5, TRACK_ELEVATION, 120.0),
-            2 => Vector3::new(5.0, TRACK_ELEVATION, 100.0),
-            3 => Vector3::new(-4.5, TRACK_ELEVATION, 80.0),
-            4 => Vector3::new(5.0, TRACK_ELEVATION, 60.0),
-            5 => Vector3::new(-4.5, TRACK_ELEVATION, 40.0),
-            6 => Vector3::new(5.0, TRACK_ELEVATION, 20.0),
-            _ => Vector3::new(-4.5, TRACK_ELEVATION, 0.0),
+    fn check_respawn(&mut self, game: &Game) {
+        let delta_time = game.delta_time;
+        let add_time = |time: &mut f32, condition: bool| {
+            *time = if condition { *time + delta_time } else { 0.0 };
+        };
+
+        add_time(&mut self.off_track_time, self.car.is_off_road());
+        add_time(&mut self.upside_down_time, self.car.is_upside_down());
+        add_time(
+            &mut self.stuck_time,
+            self.car.throttle() > 0.0 && self.car.car_state().velocity_wc.norm() < STUCK_SPEED,
+        );
+
+        let reason = if self.respawn_requested {
+            "reset"
+        } else if Track::is_below_road(&game.respawn_points.borrow(), self.car.position()) {
+            "fell off the track"
+        } else if self.off_track_time > OFF_TRACK_RESPAWN_TIME {
+            "off track"
+        } else if self.upside_down_time > UPSIDE_DOWN_RESPAWN_TIME {
+            "upside down"
+        } else if self.stuck_time > STUCK_RESPAWN_TIME {
+            "stuck"
+        } else {
+            return;
+        };
+
+        let checkpoints = &game.track_file.checkpoints;
+        let last_checkpoint = self.lap_tracker.last_checkpoint(checkpoints.len()).map(|i| &checkpoints[i]);
+        let respawn_point =
+            Track::respawn_point(&game.respawn_points.borrow(), last_checkpoint, self.car.position());
+        if let Some(respawn_point) = respawn_point {
+            log::debug!("Respawning car, {reason}");
+            self.car.respawn(&respawn_point);
+            self.add_telemetry_event("respawn");
+            game.record_replay_event(self.car.id(), ReplayEvent::Respawn);
+        }
+
+        self.respawn_requested = false;
+        self.off_track_time = 0.0;
+        self.upside_down_time = 0.0;
+        self.stuck_time = 0.0;
+    }
+
+    fn add_telemetry_event(&mut self, event: &'static str) {
+        if let Some(telemetry) = &mut self.telemetry {
+            telemetry.add_event(event);
         }
     }
-}
 
-impl<'a> GameObject<'a> for PlayerCar<'a> {
-    fn on_event(&mut self, _game: &Game, event: &Event) {
-        match event {
-            Event::KeyDown {
-                keycode: Some(key), ..
-            } => {
-                use Keycode::*;
-                match key {
-                    W => {
-                        self.car.set_throttle(100.0);
-                    }
-                    S => {
-                        use BrakingState::*;
-                        match self.braking_state {
-                            Braking => {
-                                if self.car.car_state().velocity_wc.norm() < 0.3 {
-                                    self.braking_state = Reversing;
-                                }
-
-                                self.car.set_throttle(0.0);
-                                self.car.set_brake(100.0);
-                            }
-                            Reversing => {
-                                self.car.set_brake(0.0);
-                                self.car.set_throttle(20.0);
-                                self.car.set_reverse(true);
-                            }
-                            None => {
-                                self.braking_state = Braking;
-                            }
+    /// Position and angle in the start grid of the track, players that don't fit start at the back of the grid
+    fn spawn_position(game: &Game, player_id: u8) -> (Vector3<f32>, f32) {
+        let track_file = &game.track_file;
+        let index = (player_id.max(1) as usize - 1).min(track_file.start_grid.len().saturating_sub(1));
+
+        track_file
+            .grid_position(index)
+            .unwrap_or((Vector3::new(0.0, TRACK_ELEVATION, 0.0), 0.0))
+    }
+
+    fn move_to_grid(&mut self, game: &Game) {
+        let player_id = game.server_connection.player_id().unwrap_or(self.player as u8 + 1);
+        let (position, angle) = PlayerCar::spawn_position(game, player_id);
+        self.car.reset_physics();
+        self.car.set_position(position);
+        self.car.set_angle(angle);
+        self.previous_position = position;
+        self.lap_tracker.reset();
+    }
+
+    fn action_pressed(&mut self, game: &Game, action: CarAction) {
+        match action {
+            CarAction::Throttle => self.car.set_throttle(100.0),
+            CarAction::Brake => {
+                use BrakingState::*;
+                match self.braking_state {
+                    Braking => {
+                        if self.car.car_state().velocity_wc.norm() < 0.3 {
+                            self.braking_state = Reversing;
                         }
+
+                        self.car.set_throttle(0.0);
+                        self.car.set_brake(100.0);
                     }
-                    A => {
-                        self.car.set_steering_angle((f32::consts::PI / 4.0) * 0.25);
-                    }
-                    D => self
-                        .car
-                        .set_steering_angle((-std::f32::consts::PI / 4.0) * 0.25),
-                    Space => {
-                        self.car.set_handbrake(true);
+                    Reversing => {
+                        self.car.set_brake(0.0);
+                        self.car.set_throttle(20.0);
+                        self.car.set_reverse(true);
                     }
-                    V => {
-                        let view_state = match self.car.view_state() {
-                            ViewState::ThirdPerson => ViewState::FirstPerson,
-                            ViewState::FirstPerson => ViewState::ThirdPerson,
-                        };
-                        self.car.set_view_state(view_state);
+                    None => {
+                        self.braking_state = Braking;
                     }
-                    _ => (),
                 }
             }
-            Event::KeyUp {
-                keycode: Some(key), ..
-            } => {
-                use Keycode::*;
-                match key {
-                    W => {
-                        self.car.set_throttle(0.0);
-                    }
-                    S => {
-                        if matches!(self.braking_state, BrakingState::Reversing) {
-                            self.car.car_state_mut().velocity_wc = -self.car.car_state().velocity_wc;
-                        }
+            CarAction::SteerLeft => self.car.set_steering_angle((f32::consts::PI / 4.0) * 0.25),
+            CarAction::SteerRight => self.car.set_steering_angle((-f32::consts::PI / 4.0) * 0.25),
+            CarAction::Handbrake => self.car.set_handbrake(true),
+            CarAction::Boost => {
+                self.keyboard_boost = true;
+                self.car.boost_mut().set_requested(game.arcade_mode);
+            }
+            CarAction::Reset => self.respawn_requested = true,
+            CarAction::ChangeView => {
+                let view_state = match self.car.view_state() {
+                    ViewState::ThirdPerson => ViewState::FirstPerson,
+                    ViewState::FirstPerson => ViewState::ThirdPerson,
+                };
+                self.car.set_view_state(view_state);
+            }
+        }
+    }
 
-                        self.braking_state = BrakingState::None;
-                        self.car.set_brake(0.0);
-                        self.car.set_throttle(0.0);
-                        self.car.set_reverse(false);
+    fn action_released(&mut self, action: CarAction) {
+        match action {
+            CarAction::Throttle => self.car.set_throttle(0.0),
+            CarAction::Brake => {
+                if matches!(self.braking_state, BrakingState::Reversing) {
+                    self.car.car_state_mut().velocity_wc = -self.car.car_state().velocity_wc;
+                }
+
+                self.braking_state = BrakingState::None;
+                self.car.set_brake(0.0);
+                self.car.set_throttle(0.0);
+                self.car.set_reverse(false);
+            }
+            CarAction::SteerLeft | CarAction::SteerRight => self.car.set_steering_angle(0.0),
+            CarAction::Handbrake => self.car.set_handbrake(false),
+            CarAction::Boost => {
+                self.keyboard_boost = false;
+                self.car.boost_mut().set_requested(false);
+            }
+            CarAction::Reset | CarAction::ChangeView => (),
+        }
+    }
+
+    fn other_key(&mut self, game: &Game, key: Keycode, pressed: bool) {
+        use Keycode::*;
+        match (key, pressed) {
+            // In multiplayer the server restarts the race
+            (Return, true) if !game.server_connection.is_multiplayer() => {
+                let mut race = game.race.borrow_mut();
+                if race.state() == RaceState::Results {
+                    race.restart();
+                }
+            }
+            (Tab, true) => self.lap_timer.log_leaderboard(&game.lap_records.borrow()),
+            (T, true) => match &mut self.telemetry {
+                Some(telemetry) => telemetry.toggle_recording(),
+                None => log::info!("Start the game with --telemetry to record telemetry"),
+            },
+            // TODO: Remove this after testing
+            (L, false) => {
+                self.car.set_y_velocity(20.0);
+            }
+            (P, false) => {
+                let pos = self.car.position();
+                println!("Pos: {}, {}, {}", pos.x, pos.y, pos.z);
+            }
+            _ => (),
+        }
+    }
+
+    /// What the race and the lap timer show in the window title
+    fn status_text(&self, game: &Game) -> String {
+        let race = game.race.borrow();
+        let timer = self.lap_timer.status(&game.lap_records.borrow(), self.lap_tracker.lap_time());
+        let timer = match self.time_trial.as_ref().and_then(|t| t.delta()) {
+            Some(delta) => format!("{timer} | Ghost {delta:+.3}"),
+            None => timer,
+        };
+        match race.state() {
+            RaceState::Grid if race.waiting_for_start() => String::from("Waiting for the server"),
+            RaceState::Grid => String::from("Get ready"),
+            RaceState::Countdown => race.countdown().map_or_else(String::new, |c| c.to_string()),
+            RaceState::Racing => match race.lap_count() {
+                Some(count) => format!("Lap {}/{count} | {timer}", (race.laps(&self.name) + 1).min(count)),
+                None => timer,
+            },
+            RaceState::Finished => format!("Finished | {timer}"),
+            RaceState::Results if game.server_connection.is_multiplayer() => format!("Results | {timer}"),
+            RaceState::Results => String::from("Results, press Enter to race again"),
+        }
+    }
+
+    fn check_checkpoints(&mut self, game: &Game, previous_pos: &Vector3<f32>, current_pos: &Vector3<f32>) {
+        let events = self.lap_tracker.update(
+            &game.track_file.checkpoints,
+            previous_pos,
+            current_pos,
+            game.delta_time,
+        );
+
+        for event in events {
+            use CheckpointEvent::*;
+            match event {
+                LapStarted => {
+                    log::debug!("Lap started");
+                    self.lap_timer.lap_started();
+                    if let Some(time_trial) = &mut self.time_trial {
+                        time_trial.lap_started();
+                    }
+                }
+                Sector { checkpoint, time } => {
+                    game.record_replay_event(self.car.id(), ReplayEvent::Sector { checkpoint: checkpoint as u8, time });
+                    let split = self.lap_timer.sector_finished(&game.lap_records.borrow(), self.lap_tracker.sectors());
+                    match (self.time_trial.as_ref().and_then(|t| t.delta()), split) {
+                        (Some(delta), _) => log::info!("Sector {checkpoint}: {time:.3} ({delta:+.3} to the ghost)"),
+                        (None, Some(split)) => log::info!("Sector {checkpoint}: {time:.3} ({split:+.3} to your best)"),
+                        (None, None) => log::info!("Sector {checkpoint}: {time:.3}"),
                     }
-                    A | D => {
-                        self.car.set_steering_angle(0.0);
+                    self.add_telemetry_event("checkpoint");
+                }
+                Lap { time, sectors } => {
+                    let sector_times = sectors.iter().map(|t| format!("{t:.3}")).collect::<Vec<_>>();
+                    log::info!("Lap: {time:.3} ({})", sector_times.join(", "));
+                    let place = self.lap_timer.lap_finished(&mut game.lap_records.borrow_mut(), time, &sectors);
+                    if place.is_some() {
+                        game.save_lap_records();
                     }
-                    Space => {
-                        self.car.set_handbrake(false);
+                    self.add_telemetry_event("lap");
+                    game.record_replay_event(self.car.id(), ReplayEvent::Lap { time });
+                    game.race.borrow_mut().lap_completed(&self.name, time);
+                    if let Some(time_trial) = &mut self.time_trial {
+                        time_trial.lap_finished(time);
                     }
-                    // TODO: Remove this after testing
-                    L => {
-                        self.car.set_y_velocity(20.0);
+                    if game.server_connection.is_multiplayer() {
+                        game.server_connection.send_lap_complete();
                     }
-                    P => {
-                        let pos = self.car.position();
-                        println!("Pos: {}, {}, {}", pos.x, pos.y, pos.z);
+                }
+                InvalidLap => {
+                    log::info!("Lap not counted, a checkpoint was missed");
+                    game.record_replay_event(self.car.id(), ReplayEvent::InvalidLap);
+                    self.lap_timer.lap_started();
+                    if let Some(time_trial) = &mut self.time_trial {
+                        time_trial.lap_started();
                     }
-                    _ => (),
+                    self.add_telemetry_event("invalid_lap");
+                }
+                Missed { checkpoint } => {
+                    log::info!("Missed checkpoint {checkpoint}");
+                    self.add_telemetry_event("missed_checkpoint");
+                }
+                WrongWay { checkpoint } => {
+                    log::info!("Wrong way through checkpoint {checkpoint}");
+                    self.add_telemetry_event("wrong_way");
                 }
             }
-            _ => (),
         }
     }
+}
 
-    fn update(&mut self, game: &Game, gl: &'a Context) {
-        let current_pos = self.car.position();
-        let future_pos = self
-            .car
-            .car_state()
-            .peek_time_step(game.delta_time, self.car.handbrake(), self.car.handbrake(), self.car.reverse())
-            .position_wc;
-
-        if self.lap_half_ring_complete
-            && current_pos.x > FINISH_LINE_X_START
-            && current_pos.x < FINISH_LINE_X_STOP
-            && FINISH_LINE_Z >= current_pos.z
-            && FINISH_LINE_Z <= future_pos.z
-        {
-            log::debug!("Lap!");
-            if game.server_connection.is_multiplayer() {
-                game.server_connection.send_lap_complete();
+impl<'a> GameObject<'a> for PlayerCar<'a> {
+    fn collision_info(&self) -> Collider {
+        self.collider.clone().unwrap_or(Collider::NoCollision)
+    }
+
+    fn on_event(&mut self, game: &Game, event: &Event) {
+        let (key, pressed) = match event {
+            Event::KeyDown { keycode: Some(key), .. } => (*key, true),
+            Event::KeyUp { keycode: Some(key), .. } => (*key, false),
+            _ => return,
+        };
+
+        match self.input.keyboard.and_then(|layout| layout.action(key)) {
+            Some(action) if pressed => self.action_pressed(game, action),
+            Some(action) => self.action_released(action),
+            // Keys that aren't for driving are handled once, by the first player
+            None if self.player == 0 => {
+                let driving_key = game.players.iter().any(|p| p.keyboard.and_then(|l| l.action(key)).is_some());
+                if !driving_key {
+                    self.other_key(game, key, pressed);
+                }
             }
-            self.lap_half_ring_complete = false;
+            None => (),
         }
+    }
 
-        if current_pos.x > HALF_RING_X_START
-            && current_pos.x < HALF_RING_X_STOP
-            && HALF_RING_LINE_Z <= current_pos.z
-            && HALF_RING_LINE_Z >= future_pos.z
-        {
-            self.lap_half_ring_complete = true;
+    fn update(&mut self, game: &Game, gl: &'a Context) {
+        let (start_number, inputs_frozen) = {
+            let race = game.race.borrow();
+            (race.start_number(), race.inputs_frozen())
+        };
+        if start_number != self.start_number {
+            self.start_number = start_number;
+            self.move_to_grid(game);
         }
 
         self.handle_joystick_controls(game);
 
+        let damage_before = *self.car.damage();
+        let boost_before = self.car.boost().is_active();
+        // The keys stay pressed during the countdown, so the car drives off when it ends
+        self.car.set_held(inputs_frozen);
+        self.car.set_owner(self as *const _ as *const usize);
         self.car.update(game, gl);
+        let (previous, position) = (self.previous_position, *self.car.position());
+        self.check_checkpoints(game, &previous, &position);
+        if !inputs_frozen {
+            self.check_respawn(game);
+        }
+        // After a respawn the car didn't drive from where it was
+        self.previous_position = *self.car.position();
+        let (min_x, min_y, min_z, max_x, max_y, max_z) = self.car.car_cube();
+        self.collider = Some(Collider::BoxCollider(
+            min_x, min_y, min_z, max_x, max_y, max_z,
+        ));
+        let wrong_way = self.lap_tracker.check_wrong_way(
+            &game.respawn_points.borrow(),
+            self.car.position(),
+            &self.car.car_state().velocity_wc,
+            game.delta_time,
+        );
+        if wrong_way {
+            log::info!("Wrong way!");
+            self.add_telemetry_event("wrong_way");
+        }
+        if let Some(telemetry) = &mut self.telemetry {
+            telemetry.record(&self.car, game.delta_time);
+        }
+        if let Some(time_trial) = &mut self.time_trial {
+            time_trial.update(self.lap_tracker.lap_time(), &self.car);
+        }
+        game.status_texts.borrow_mut()[self.player] = self.status_text(game);
 
         // Send status update
         if game.server_connection.is_multiplayer() {
@@ -258,10 +572,9 @@ impl<'a> GameObject<'a> for PlayerCar<'a> {
 
                 match event {
                     Some(NetworkEvent::MoveToStartPos) => {
-                        self.car.reset_physics();
-                        self.car.set_position(PlayerCar::spawn_position(
-                            game.server_connection.player_id().unwrap_or(1),
-                        ));
+                        game.race.borrow_mut().start();
+                        self.start_number = game.race.borrow().start_number();
+                        self.move_to_grid(game);
                         game_events.pop_front();
                     }
                     _ => break,
@@ -273,14 +586,27 @@ impl<'a> GameObject<'a> for PlayerCar<'a> {
                 self.car.angle(),
                 self.car.steering_angle(),
             );
+
+            self.state_resend_time -= game.delta_time;
+            let resend = self.state_resend_time <= 0.0;
+            if resend {
+                self.state_resend_time = STATE_RESEND_TIME;
+            }
+            if *self.car.damage() != damage_before || resend {
+                game.server_connection.send_damage_update(self.car.damage().areas());
+            }
+            if self.car.boost().is_active() != boost_before || resend {
+                game.server_connection.send_boost_update(self.car.boost().is_active());
+            }
         }
 
         // Update lights
         let pos = self.car.light_position();
-        game.lights.borrow_mut().set_light_position("PLAYER_CAR", &Vector3::new(pos.x, pos.y, pos.z));
+        game.lights.borrow_mut().set_light_position(&self.light_id, &Vector3::new(pos.x, pos.y, pos.z));
 
         // Update camera pos
-        let mut view_matrix = game.view_matrix.borrow_mut();
+        let mut player_cameras = game.player_cameras.borrow_mut();
+        let view_matrix = &mut player_cameras[self.player];
         let ang_sin = self.car.angle().sin();
         let ang_cos = self.car.angle().cos();
 
@@ -298,4 +624,10 @@ impl<'a> GameObject<'a> for PlayerCar<'a> {
     fn display(&self, game: &Game, gl: &'a Context) {
         self.car.display(game, gl);
     }
+
+    fn display_translucent(&self, game: &Game, gl: &'a Context) {
+        if let Some(time_trial) = &self.time_trial {
+            time_trial.display(game, gl);
+        }
+    }
 }
@@ -0,0 +1,137 @@
+use std::{fmt, str::FromStr};
+
+use sdl2::keyboard::Keycode;
+
+/// Something the player does with the car
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CarAction {
+    Throttle,
+    Brake,
+    SteerLeft,
+    SteerRight,
+    Handbrake,
+    Boost,
+    Reset,
+    ChangeView,
+}
+
+/// Keys a player drives with, so several players can share the keyboard
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyboardLayout {
+    // W, S, A, D, Space for the handbrake, left shift to boost, R to reset and V for the view
+    Wasd,
+    // The arrows, right ctrl for the handbrake, right shift to boost, backspace to reset and . for the view
+    Arrows,
+    // I, K, J, L, M for the handbrake, U to boost, O to reset and P for the view
+    Ijkl,
+}
+
+impl KeyboardLayout {
+    /// Keys of the throttle, brake, left, right, handbrake, boost, reset and view, in the order of CarAction
+    fn keys(&self) -> [Keycode; 8] {
+        use Keycode::*;
+        match self {
+            KeyboardLayout::Wasd => [W, S, A, D, Space, LShift, R, V],
+            KeyboardLayout::Arrows => [Up, Down, Left, Right, RCtrl, RShift, Backspace, Period],
+            KeyboardLayout::Ijkl => [I, K, J, L, M, U, O, P],
+        }
+    }
+
+    pub fn action(&self, key: Keycode) -> Option<CarAction> {
+        use CarAction::*;
+        let actions = [Throttle, Brake, SteerLeft, SteerRight, Handbrake, Boost, Reset, ChangeView];
+        self.keys().iter().position(|k| *k == key).map(|i| actions[i])
+    }
+}
+
+/// The keyboard layout and the joystick a player drives with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PlayerInput {
+    pub keyboard: Option<KeyboardLayout>,
+    // Index of the joystick as SDL counts them, from 0
+    pub joystick: Option<u32>,
+}
+
+impl PlayerInput {
+    /// Alone at the computer the player can use both the keyboard and the first joystick
+    pub fn single_player() -> PlayerInput {
+        PlayerInput { keyboard: Some(KeyboardLayout::Wasd), joystick: Some(0) }
+    }
+}
+
+/// Two players can't drive with the same keyboard layout or joystick
+pub fn check_split_screen(players: &[PlayerInput]) -> Result<(), String> {
+    for (i, player) in players.iter().enumerate() {
+        let shared = players[..i].iter().position(|p| {
+            (player.keyboard.is_some() && p.keyboard == player.keyboard)
+                || (player.joystick.is_some() && p.joystick == player.joystick)
+        });
+        if let Some(other) = shared {
+            return Err(format!(
+                "Players {} and {} both use {player}, every player needs their own input",
+                other + 1,
+                i + 1
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Parsed from the command line as wasd, arrows, ijkl or joystick followed by its index, like joystick1
+impl FromStr for PlayerInput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PlayerInput, String> {
+        let keyboard = |layout| Ok(PlayerInput { keyboard: Some(layout), joystick: None });
+        match s.to_lowercase().as_str() {
+            "wasd" => keyboard(KeyboardLayout::Wasd),
+            "arrows" => keyboard(KeyboardLayout::Arrows),
+            "ijkl" => keyboard(KeyboardLayout::Ijkl),
+            other => match other.strip_prefix("joystick").map(str::parse::<u32>) {
+                Some(Ok(index)) => Ok(PlayerInput { keyboard: None, joystick: Some(index) }),
+                _ => Err(format!("Unknown input device {s}, use wasd, arrows, ijkl or joystick with its index, like joystick0")),
+            },
+        }
+    }
+}
+
+impl fmt::Display for PlayerInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.keyboard, self.joystick) {
+            (Some(layout), Some(index)) => write!(f, "{layout:?} and joystick {index}"),
+            (Some(layout), None) => write!(f, "{layout:?}"),
+            (None, Some(index)) => write!(f, "joystick {index}"),
+            (None, None) => write!(f, "nothing"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn players_share_the_keyboard() {
+        let players = ["wasd", "Arrows", "joystick1"].map(|s| s.parse::<PlayerInput>().unwrap());
+        assert_eq!(players[1].keyboard, Some(KeyboardLayout::Arrows));
+        assert_eq!(players[2], PlayerInput { keyboard: None, joystick: Some(1) });
+        assert!("joystick".parse::<PlayerInput>().is_err());
+        assert!("mouse".parse::<PlayerInput>().is_err());
+
+        // No key does something for two layouts
+        let layouts = [KeyboardLayout::Wasd, KeyboardLayout::Arrows, KeyboardLayout::Ijkl];
+        for key in [Keycode::W, Keycode::Up, Keycode::I, Keycode::Space, Keycode::RShift, Keycode::P] {
+            assert_eq!(layouts.iter().filter(|l| l.action(key).is_some()).count(), 1);
+        }
+        assert_eq!(KeyboardLayout::Ijkl.action(Keycode::L), Some(CarAction::SteerRight));
+    }
+
+    #[test]
+    fn players_need_their_own_input() {
+        let parse = |inputs: &[&str]| inputs.iter().map(|s| s.parse().unwrap()).collect::<Vec<PlayerInput>>();
+        assert!(check_split_screen(&parse(&["wasd", "arrows", "joystick0", "joystick1"])).is_ok());
+        assert!(check_split_screen(&parse(&["wasd", "WASD"])).is_err());
+        assert!(check_split_screen(&parse(&["joystick0", "arrows", "joystick0"])).is_err());
+    }
+}
@@ -0,0 +1,343 @@
+use std::fmt::Write;
+
+use nalgebra::Vector3;
+
+use crate::core::{game_object::Collider, raycast::RaycastHit};
+
+use super::car_physics::CarPhysics;
+
+/// The driver inputs from a point in time, they are held until the next input
+#[derive(Clone, Copy)]
+pub struct ScriptedInput {
+    pub time: f32,
+    pub throttle: f32,
+    pub brake: f32,
+    pub steering_angle: f32,
+}
+
+impl ScriptedInput {
+    pub fn new(time: f32, throttle: f32, brake: f32, steering_angle: f32) -> ScriptedInput {
+        ScriptedInput { time, throttle, brake, steering_angle }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct TrajectorySample {
+    pub time: f32,
+    pub position: Vector3<f32>,
+    pub velocity: Vector3<f32>,
+    pub angle: f32,
+}
+
+/// Runs the car physics against a fixed set of colliders, without a window or OpenGL
+pub struct Simulation {
+    pub physics: CarPhysics,
+    colliders: Vec<Collider>,
+}
+
+impl Simulation {
+    pub fn new(colliders: Vec<Collider>) -> Simulation {
+        Simulation {
+            physics: CarPhysics::new(true),
+            colliders,
+        }
+    }
+
+    /// Place the car at a position, facing the given angle and standing still
+    pub fn place_car(&mut self, position: Vector3<f32>, angle: f32) {
+        self.physics.reset();
+        self.physics.car_state.position_wc = position;
+        self.physics.car_state.angle = angle;
+    }
+
+    /// Advance the simulation by one time step, the same way Car::update does it
+    pub fn step(&mut self, delta_time: f32) {
+        for collider in &self.colliders {
+            self.physics.check_collision(collider, delta_time);
+        }
+        self.physics.apply_pending_impact();
+
+        self.physics.step(delta_time);
+
+        let colliders = &self.colliders;
+        self.physics
+            .update_suspension(delta_time, |origin, dir, max_dist| {
+                colliders.iter().fold(None, |closest, collider| {
+                    RaycastHit::closest(closest, collider.raycast(origin, dir, max_dist))
+                })
+            });
+    }
+
+    /// Run the inputs for a duration with a fixed time step, the car is sampled every sample_interval seconds
+    pub fn run(
+        &mut self,
+        inputs: &[ScriptedInput],
+        duration: f32,
+        delta_time: f32,
+        sample_interval: f32,
+    ) -> Vec<TrajectorySample> {
+        let steps = (duration / delta_time).round() as usize;
+        let steps_per_sample = ((sample_interval / delta_time).round() as usize).max(1);
+
+        let mut samples = vec![self.sample(0.0)];
+        for step in 1..=steps {
+            let time = step as f32 * delta_time;
+
+            if let Some(input) = inputs.iter().rev().find(|i| i.time < time) {
+                let car_state = &mut self.physics.car_state;
+                car_state.throttle = input.throttle;
+                car_state.brake = input.brake;
+                car_state.steering_angle = input.steering_angle;
+            }
+
+            self.step(delta_time);
+
+            if step % steps_per_sample == 0 {
+                samples.push(self.sample(time));
+            }
+        }
+
+        samples
+    }
+
+    fn sample(&self, time: f32) -> TrajectorySample {
+        let car_state = &self.physics.car_state;
+        TrajectorySample {
+            time,
+            position: car_state.position_wc,
+            velocity: car_state.velocity_wc,
+            angle: car_state.angle,
+        }
+    }
+}
+
+/// One line per sample with the time, position, horizontal velocity and angle
+pub fn trajectory_to_string(samples: &[TrajectorySample]) -> String {
+    let mut output = String::from("# time x y z velocity_x velocity_z angle\n");
+    for s in samples {
+        writeln!(
+            output,
+            "{:.3} {:.3} {:.3} {:.3} {:.3} {:.3} {:.4}",
+            s.time, s.position.x, s.position.y, s.position.z, s.velocity.x, s.velocity.z, s.angle
+        )
+        .unwrap();
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use nalgebra::{Vector2, Vector3};
+
+    use crate::{
+        core::{game_object::Collider, surface::Surface},
+        game_objects::{
+            cars::{
+                bot_driver::{BotDifficulty, BotDriver},
+                slipstream::CarPose,
+            },
+            track::{racing_line::RacingLine, track_segment::TRACK_WIDTH},
+        },
+    };
+
+    use super::*;
+
+    const DELTA_TIME: f32 = 1.0 / 60.0;
+    const SAMPLE_INTERVAL: f32 = 0.5;
+    // Golden values are rounded, and the math functions may differ a little between platforms
+    const TOLERANCE: f32 = 0.05;
+
+    fn flat_ground() -> Collider {
+        Collider::HeightCollider(0.0, Surface::Asphalt)
+    }
+
+    fn start_position() -> Vector3<f32> {
+        Vector3::new(0.0, 1.5, 0.0)
+    }
+
+    /// Compare a trajectory with its golden file, run with UPDATE_GOLDEN=1 to write the golden files instead
+    fn check_golden(name: &str, samples: &[TrajectorySample]) {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/golden")
+            .join(format!("{name}.txt"));
+        let actual = trajectory_to_string(samples);
+
+        if env::var("UPDATE_GOLDEN").is_ok() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, actual).unwrap();
+            return;
+        }
+
+        let expected = fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("Failed to read golden file {}. {e}", path.display()));
+
+        let values = |text: &str| -> Vec<Vec<f32>> {
+            text.lines()
+                .filter(|l| !l.starts_with('#'))
+                .map(|l| l.split_whitespace().map(|v| v.parse().unwrap()).collect())
+                .collect()
+        };
+        let expected = values(&expected);
+        let actual = values(&actual);
+
+        assert_eq!(expected.len(), actual.len(), "{name}: number of samples changed");
+        for (line, (e, a)) in expected.iter().zip(&actual).enumerate() {
+            let matches = e.len() == a.len()
+                && e.iter().zip(a).all(|(e, a)| (e - a).abs() <= TOLERANCE);
+            assert!(matches, "{name}: sample {line} changed, expected {e:?}, got {a:?}");
+        }
+    }
+
+    #[test]
+    fn acceleration_to_top_speed() {
+        let mut simulation = Simulation::new(vec![flat_ground()]);
+        simulation.place_car(start_position(), 0.0);
+
+        let inputs = [ScriptedInput::new(0.0, 100.0, 0.0, 0.0)];
+        let samples = simulation.run(&inputs, 20.0, DELTA_TIME, SAMPLE_INTERVAL);
+
+        check_golden("acceleration", &samples);
+    }
+
+    #[test]
+    fn braking_distance() {
+        let mut simulation = Simulation::new(vec![flat_ground()]);
+        simulation.place_car(start_position(), 0.0);
+
+        let inputs = [
+            ScriptedInput::new(0.0, 100.0, 0.0, 0.0),
+            ScriptedInput::new(8.0, 0.0, 100.0, 0.0),
+        ];
+        let samples = simulation.run(&inputs, 14.0, DELTA_TIME, SAMPLE_INTERVAL);
+
+        check_golden("braking", &samples);
+    }
+
+    #[test]
+    fn steady_state_cornering() {
+        let mut simulation = Simulation::new(vec![flat_ground()]);
+        simulation.place_car(start_position(), 0.0);
+
+        let inputs = [
+            ScriptedInput::new(0.0, 40.0, 0.0, 0.0),
+            ScriptedInput::new(3.0, 40.0, 0.0, 0.1),
+        ];
+        let samples = simulation.run(&inputs, 15.0, DELTA_TIME, SAMPLE_INTERVAL);
+
+        check_golden("cornering", &samples);
+    }
+
+    #[test]
+    fn held_car_stays_on_the_grid() {
+        let mut simulation = Simulation::new(vec![flat_ground()]);
+        simulation.place_car(start_position(), 0.3);
+        simulation.physics.held = true;
+        simulation.physics.handbrake = true;
+        simulation.physics.boost.set_requested(true);
+
+        let inputs = [ScriptedInput::new(0.0, 100.0, 100.0, 0.5)];
+        let samples = simulation.run(&inputs, 5.0, DELTA_TIME, SAMPLE_INTERVAL);
+        let last = samples.last().unwrap();
+        assert_eq!((last.position.x, last.position.z, last.angle), (0.0, 0.0, 0.3));
+        assert_eq!(simulation.physics.boost.meter(), 1.0);
+        // Settled on the springs
+        assert!(last.position.y < start_position().y + 0.5);
+
+        // Let go, it drives off with the inputs that were held
+        simulation.physics.held = false;
+        simulation.physics.car_state.brake = 0.0;
+        for _ in 0..60 {
+            simulation.step(DELTA_TIME);
+        }
+        assert!(simulation.physics.car_state.velocity_wc.norm() > 1.0);
+    }
+
+    #[test]
+    fn rolls_back_down_a_slope() {
+        // A road going uphill along z
+        let slope = 10f32.to_radians().tan();
+        let corner = |x: f32, z: f32| Vector3::new(x, z * slope, z);
+        let road = Collider::SurfaceCollider(
+            vec![
+                [corner(-50.0, -200.0), corner(50.0, -200.0), corner(-50.0, 200.0)],
+                [corner(50.0, -200.0), corner(50.0, 200.0), corner(-50.0, 200.0)],
+            ],
+            Surface::Asphalt,
+        );
+        let mut simulation = Simulation::new(vec![road]);
+        simulation.place_car(start_position(), 0.0);
+
+        let samples = simulation.run(&[], 5.0, DELTA_TIME, SAMPLE_INTERVAL);
+        let last = samples.last().unwrap();
+        assert!(last.velocity.z < -1.0, "car did not roll down, velocity {}", last.velocity);
+        assert!(last.position.z < 0.0);
+        // Still on the road, not sunk through it
+        assert!(last.position.y > last.position.z * slope);
+    }
+
+    #[test]
+    fn wall_bounce() {
+        let wall = Collider::InfiniteYPlaneCollider(
+            Vector3::new(-50.0, 0.0, 80.0),
+            Vector3::new(50.0, 0.0, 60.0),
+        );
+        let mut simulation = Simulation::new(vec![flat_ground(), wall]);
+        simulation.place_car(start_position(), 0.0);
+
+        let inputs = [ScriptedInput::new(0.0, 100.0, 0.0, 0.0)];
+        let samples = simulation.run(&inputs, 6.0, DELTA_TIME, SAMPLE_INTERVAL);
+
+        check_golden("wall_bounce", &samples);
+    }
+
+    #[test]
+    fn bot_drives_around_an_oval() {
+        // Two 150 long straights joined by half circles with a radius of 40, driven counter clockwise
+        let half_circle = |z: f32, start: f32| {
+            (0..=20).map(move |i| {
+                let angle = start + std::f32::consts::PI * i as f32 / 20.0;
+                Vector3::new(40.0 * angle.cos(), 0.0, z + 40.0 * angle.sin())
+            })
+        };
+        let centre_line = half_circle(75.0, 0.0)
+            .chain(half_circle(-75.0, std::f32::consts::PI))
+            .collect::<Vec<_>>();
+        let racing_line = RacingLine::new(&centre_line);
+
+        for difficulty in [BotDifficulty::Easy, BotDifficulty::Hard] {
+            let mut driver = BotDriver::new(&racing_line, difficulty);
+            let mut simulation = Simulation::new(vec![flat_ground()]);
+            simulation.place_car(Vector3::new(40.0, 1.5, -60.0), 0.0);
+
+            let mut distance = 0.0;
+            for _ in 0..(60.0 / DELTA_TIME) as usize {
+                let car_state = &simulation.physics.car_state;
+                let pose = CarPose { position: car_state.position_wc, angle: car_state.angle };
+                let input = driver.drive(&racing_line, &pose, &car_state.velocity_wc, &[]);
+
+                let car_state = &mut simulation.physics.car_state;
+                car_state.throttle = input.throttle;
+                car_state.brake = input.brake;
+                car_state.steering_angle = input.steering_angle;
+
+                let before = simulation.physics.car_state.position_wc;
+                simulation.step(DELTA_TIME);
+                let position = simulation.physics.car_state.position_wc;
+                distance += (position - before).xz().norm();
+
+                let off_centre = if position.z.abs() <= 75.0 {
+                    (position.x.abs() - 40.0).abs()
+                } else {
+                    ((position.xz() - Vector2::new(0.0, 75.0 * position.z.signum())).norm() - 40.0).abs()
+                };
+                assert!(off_centre < TRACK_WIDTH / 2.0, "{difficulty:?} bot left the road at {position}");
+            }
+
+            // A lap is about 550 long
+            assert!(distance > 1000.0, "{difficulty:?} bot only drove {distance}");
+        }
+    }
+}
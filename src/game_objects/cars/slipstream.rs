@@ -0,0 +1,98 @@
+use std::f32;
+
+use nalgebra::{Vector2, Vector3};
+
+use crate::core::{color::Color, game::Game, matrices::ModelMatrix};
+
+// Show the draft cone behind every car
+const SHOW_DRAFT_CONES: bool = false;
+
+// How far behind a car the draft reaches
+const DRAFT_LENGTH: f32 = 40.0;
+// Half of the opening angle of the draft cone
+const DRAFT_HALF_ANGLE: f32 = 12.0 * f32::consts::PI / 180.0;
+// Drag is reduced by this much right behind the car in front, falling off to nothing at the end of the cone
+const MAX_DRAG_REDUCTION: f32 = 0.6;
+// The following car has to be pointing roughly the same way as the car in front
+const MIN_HEADING_ALIGNMENT: f32 = 0.7;
+
+/// Where a car was during the last update, used by the other cars to find out if they are in its draft
+#[derive(Clone, Copy)]
+pub struct CarPose {
+    pub position: Vector3<f32>,
+    pub angle: f32,
+}
+
+impl CarPose {
+    fn forward(&self) -> Vector2<f32> {
+        Vector2::new(self.angle.sin(), self.angle.cos())
+    }
+
+    /// How much the draft of this car reduces the drag of a car at the given pose, from 0 to MAX_DRAG_REDUCTION
+    fn drag_reduction(&self, follower: &CarPose) -> f32 {
+        let offset = follower.position.xz() - self.position.xz();
+        let distance = offset.norm();
+        if distance <= f32::EPSILON || distance >= DRAFT_LENGTH {
+            return 0.0;
+        }
+
+        let backward = -self.forward();
+        if offset.dot(&backward) / distance < DRAFT_HALF_ANGLE.cos() {
+            return 0.0;
+        }
+
+        if follower.forward().dot(&self.forward()) < MIN_HEADING_ALIGNMENT {
+            return 0.0;
+        }
+
+        MAX_DRAG_REDUCTION * (1.0 - distance / DRAFT_LENGTH)
+    }
+}
+
+/// Multiplier for the aerodynamic drag of a car, the strongest draft of any other car is used
+pub fn drag_factor(game: &Game, car: *const usize, pose: &CarPose) -> f32 {
+    let reduction = game
+        .car_poses
+        .borrow()
+        .iter()
+        .filter(|(other, _)| **other != car)
+        .map(|(_, other_pose)| other_pose.drag_reduction(pose))
+        .fold(0.0, f32::max);
+
+    1.0 - reduction
+}
+
+/// Draws the edges of the draft cone behind a car, brighter when another car is drafting in it
+pub fn display_draft_cone(game: &Game, model_matrix: &mut ModelMatrix, car: *const usize, pose: &CarPose) {
+    if !SHOW_DRAFT_CONES {
+        return;
+    }
+
+    let drafted = game
+        .car_poses
+        .borrow()
+        .iter()
+        .any(|(other, other_pose)| *other != car && pose.drag_reduction(other_pose) > 0.0);
+    let color = if drafted {
+        Color::with_alpha(0.2, 1.0, 0.2, 0.6)
+    } else {
+        Color::with_alpha(0.2, 0.4, 1.0, 0.3)
+    };
+    game.shader.set_material_diffuse(&color);
+    game.shader.set_material_ambient(&color);
+    game.shader.set_material_specular(&Color::zeros());
+
+    for side in [-1.0, 1.0] {
+        let angle = pose.angle + f32::consts::PI + side * DRAFT_HALF_ANGLE;
+        let center = pose.position
+            + Vector3::new(angle.sin(), 0.0, angle.cos()) * (DRAFT_LENGTH * 0.5);
+
+        model_matrix.push_stack();
+        model_matrix.add_translate(center.x, center.y, center.z);
+        model_matrix.add_rotation(0.0, angle, 0.0);
+        model_matrix.add_scale(0.2, 0.2, DRAFT_LENGTH);
+        game.shader.set_model_matrix(model_matrix.matrix.as_slice());
+        game.cube.draw(&game.shader);
+        model_matrix.pop_stack();
+    }
+}
@@ -0,0 +1,147 @@
+use nalgebra::{Vector3, Vector4};
+
+use crate::core::{matrices::ModelMatrix, raycast::RaycastHit};
+
+use super::car_state::CarState;
+
+const GRAVITY: f32 = 9.8 * 1.7;
+const CAR_MASS: f32 = 600.0;
+const PITCH_INERTIA: f32 = 5000.0;
+const ROLL_INERTIA: f32 = 1250.0;
+// Height of the center of gravity above the wheel mounts, used for weight transfer
+const CG_HEIGHT: f32 = 0.8;
+
+// Length of an unloaded spring, measured from the wheel mount to the ground
+pub const SPRING_LENGTH: f32 = 2.0;
+const SPRING_STIFFNESS: f32 = 5000.0;
+const SPRING_DAMPING: f32 = 900.0;
+// Air has a tiny bit of damping so the car doesn't keep spinning forever after a jump
+const AIR_ANGULAR_DAMPING: f32 = 0.2;
+
+// Front left, front right, rear left, rear right (relative to the center of the car body)
+pub const WHEEL_MOUNTS: [Vector3<f32>; 4] = [
+    Vector3::new(2.0, 0.0, 4.0),
+    Vector3::new(-2.0, 0.0, 4.0),
+    Vector3::new(2.0, 0.0, -3.0),
+    Vector3::new(-2.0, 0.0, -3.0),
+];
+
+#[derive(Clone)]
+pub struct Suspension {
+    pub y_velocity: f32,
+    // Positive pitch is nose down, positive roll lifts the left side of the car
+    pub pitch: f32,
+    pub roll: f32,
+    pub pitch_velocity: f32,
+    pub roll_velocity: f32,
+    pub compression: [f32; 4],
+}
+
+impl Suspension {
+    pub fn new() -> Suspension {
+        Suspension {
+            y_velocity: 0.0,
+            pitch: 0.0,
+            roll: 0.0,
+            pitch_velocity: 0.0,
+            roll_velocity: 0.0,
+            compression: [0.0; 4],
+        }
+    }
+
+    /// Model matrix of the car body, translated to its position and rotated by yaw, pitch and roll
+    pub fn body_matrix(&self, car_state: &CarState) -> ModelMatrix {
+        let mut model_matrix = ModelMatrix::new();
+        model_matrix.add_translate(
+            car_state.position_wc.x,
+            car_state.position_wc.y,
+            car_state.position_wc.z,
+        );
+        model_matrix.add_rotation(0.0, car_state.angle, 0.0);
+        model_matrix.add_rotation(self.pitch, 0.0, 0.0);
+        model_matrix.add_rotation(0.0, 0.0, self.roll);
+        model_matrix
+    }
+
+    pub fn up(&self, car_state: &CarState) -> Vector3<f32> {
+        (self.body_matrix(car_state).matrix * Vector4::new(0.0, 1.0, 0.0, 0.0))
+            .xyz()
+            .normalize()
+    }
+
+    pub fn is_grounded(&self) -> bool {
+        self.compression.iter().any(|&c| c > 0.0)
+    }
+
+    /// Raycast from each wheel mount to the ground and apply the spring forces to the car body.
+    /// The raycast function takes an origin, a direction and a max distance
+    pub fn update(
+        &mut self,
+        car_state: &mut CarState,
+        delta_time: f32,
+        raycast: impl Fn(&Vector3<f32>, &Vector3<f32>, f32) -> Option<RaycastHit>,
+    ) {
+        if delta_time <= 0.0 {
+            return;
+        }
+
+        let body_matrix = self.body_matrix(car_state);
+        let up = (body_matrix.matrix * Vector4::new(0.0, 1.0, 0.0, 0.0))
+            .xyz()
+            .normalize();
+
+        let mut vertical_force = -CAR_MASS * GRAVITY;
+        let mut ground_normal = Vector3::zeros();
+        let mut pitch_torque = 0.0;
+        let mut roll_torque = 0.0;
+
+        for (i, mount) in WHEEL_MOUNTS.iter().enumerate() {
+            let mount_wc = (body_matrix.matrix * mount.push(1.0)).xyz();
+
+            let compression = match raycast(&mount_wc, &-up, SPRING_LENGTH) {
+                Some(hit) => {
+                    car_state.wheel_surfaces[i] = hit.surface;
+                    ground_normal += hit.normal;
+                    SPRING_LENGTH - hit.distance
+                }
+                None => 0.0,
+            };
+            let compression_speed = (compression - self.compression[i]) / delta_time;
+            self.compression[i] = compression;
+            car_state.wheel_contact[i] = compression > 0.0;
+
+            if compression <= 0.0 {
+                continue;
+            }
+
+            // Springs can only push the car away from the ground
+            let force = (SPRING_STIFFNESS * compression + SPRING_DAMPING * compression_speed).max(0.0);
+
+            vertical_force += force * up.y;
+            pitch_torque -= force * mount.z;
+            roll_torque += force * mount.x;
+        }
+
+        car_state.ground_normal = ground_normal
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(|| Vector3::new(0.0, 1.0, 0.0));
+
+        if self.is_grounded() {
+            // Weight transfer, the body dives under braking and rolls to the outside of corners
+            pitch_torque -= CAR_MASS * car_state.acceleration.x * CG_HEIGHT;
+            roll_torque += CAR_MASS * car_state.acceleration.z * CG_HEIGHT;
+        } else {
+            let damping = 1.0 - (AIR_ANGULAR_DAMPING * delta_time).min(1.0);
+            self.pitch_velocity *= damping;
+            self.roll_velocity *= damping;
+        }
+
+        self.y_velocity += (vertical_force / CAR_MASS) * delta_time;
+        car_state.position_wc.y += self.y_velocity * delta_time;
+
+        self.pitch_velocity += (pitch_torque / PITCH_INERTIA) * delta_time;
+        self.roll_velocity += (roll_torque / ROLL_INERTIA) * delta_time;
+        self.pitch += self.pitch_velocity * delta_time;
+        self.roll += self.roll_velocity * delta_time;
+    }
+}
@@ -0,0 +1,184 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+
+use super::{car::Car, damage::DamageArea};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum TelemetryFormat {
+    Csv,
+    JsonLines,
+}
+
+impl TelemetryFormat {
+    /// JSON lines for .json and .jsonl files, CSV for everything else
+    pub fn from_path(path: &Path) -> TelemetryFormat {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json" | "jsonl") => TelemetryFormat::JsonLines,
+            _ => TelemetryFormat::Csv,
+        }
+    }
+}
+
+const CSV_HEADER: &str = "time,x,y,z,velocity_x,velocity_y,velocity_z,slip_angle_front,slip_angle_rear,\
+front_lateral_force,rear_lateral_force,throttle,brake,steering_angle,collision_area,collision_energy,event";
+
+/// Writes the state of a car on every physics tick to a file while recording
+pub struct TelemetryRecorder {
+    writer: BufWriter<File>,
+    format: TelemetryFormat,
+    recording: bool,
+    time: f32,
+    // Lap events and such that happened since the last tick
+    events: Vec<&'static str>,
+}
+
+impl TelemetryRecorder {
+    pub fn new(path: &Path) -> anyhow::Result<TelemetryRecorder> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create telemetry file {}", path.display()))?;
+        let format = TelemetryFormat::from_path(path);
+
+        let mut writer = BufWriter::new(file);
+        if format == TelemetryFormat::Csv {
+            writeln!(writer, "{CSV_HEADER}")?;
+        }
+
+        Ok(TelemetryRecorder {
+            writer,
+            format,
+            recording: false,
+            time: 0.0,
+            events: Vec::new(),
+        })
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    pub fn toggle_recording(&mut self) {
+        self.recording = !self.recording;
+        if self.recording {
+            log::info!("Started recording telemetry");
+        } else {
+            log::info!("Stopped recording telemetry");
+            if let Err(e) = self.writer.flush() {
+                log::error!("Failed to write telemetry. {e}");
+            }
+        }
+    }
+
+    /// Add an event, like a completed lap, to the next tick
+    pub fn add_event(&mut self, event: &'static str) {
+        if self.recording {
+            self.events.push(event);
+        }
+    }
+
+    /// Record the car after a physics tick
+    pub fn record(&mut self, car: &Car, delta_time: f32) {
+        if !self.recording {
+            return;
+        }
+
+        self.time += delta_time;
+        let result = match self.format {
+            TelemetryFormat::Csv => self.write_csv(car),
+            TelemetryFormat::JsonLines => self.write_json(car),
+        };
+        self.events.clear();
+
+        if let Err(e) = result {
+            log::error!("Failed to write telemetry, recording stopped. {e}");
+            self.recording = false;
+        }
+    }
+
+    fn write_csv(&mut self, car: &Car) -> std::io::Result<()> {
+        let s = car.car_state();
+        let (collision_area, collision_energy) = match car.last_impact() {
+            Some((area, energy)) => (area_name(area), energy.to_string()),
+            None => ("", String::new()),
+        };
+
+        writeln!(
+            self.writer,
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.time,
+            s.position_wc.x,
+            s.position_wc.y,
+            s.position_wc.z,
+            s.velocity_wc.x,
+            car.y_velocity(),
+            s.velocity_wc.z,
+            s.slip_angle_front,
+            s.slip_angle_rear,
+            s.front_lateral_force,
+            s.rear_lateral_force,
+            s.throttle,
+            s.brake,
+            s.steering_angle,
+            collision_area,
+            collision_energy,
+            self.events.join(" "),
+        )
+    }
+
+    fn write_json(&mut self, car: &Car) -> std::io::Result<()> {
+        let s = car.car_state();
+        let collision = match car.last_impact() {
+            Some((area, energy)) => format!("{{\"area\":\"{}\",\"energy\":{}}}", area_name(area), energy),
+            None => "null".to_string(),
+        };
+        let events = self
+            .events
+            .iter()
+            .map(|e| format!("\"{e}\""))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        writeln!(
+            self.writer,
+            "{{\"time\":{},\"position\":[{},{},{}],\"velocity\":[{},{},{}],\"slip_angle_front\":{},\
+\"slip_angle_rear\":{},\"front_lateral_force\":{},\"rear_lateral_force\":{},\"throttle\":{},\
+\"brake\":{},\"steering_angle\":{},\"collision\":{},\"events\":[{}]}}",
+            self.time,
+            s.position_wc.x,
+            s.position_wc.y,
+            s.position_wc.z,
+            s.velocity_wc.x,
+            car.y_velocity(),
+            s.velocity_wc.z,
+            s.slip_angle_front,
+            s.slip_angle_rear,
+            s.front_lateral_force,
+            s.rear_lateral_force,
+            s.throttle,
+            s.brake,
+            s.steering_angle,
+            collision,
+            events,
+        )
+    }
+}
+
+impl Drop for TelemetryRecorder {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
+
+fn area_name(area: DamageArea) -> &'static str {
+    use DamageArea::*;
+    match area {
+        Front => "front",
+        Rear => "rear",
+        Left => "left",
+        Right => "right",
+    }
+}
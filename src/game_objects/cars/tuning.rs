@@ -0,0 +1,23 @@
+/// Values that change how a car drives
+#[derive(Clone, Copy, Debug)]
+pub struct CarTuning {
+    // Extra traction force while boosting, full throttle gives 100
+    pub boost_force: f32,
+    // Seconds a full boost meter lasts
+    pub boost_duration: f32,
+    // Seconds it takes to fill an empty boost meter by driving normally
+    pub boost_recharge_time: f32,
+    // Seconds it takes to fill an empty boost meter while drifting
+    pub drift_recharge_time: f32,
+}
+
+impl CarTuning {
+    pub fn standard() -> CarTuning {
+        CarTuning {
+            boost_force: 120.0,
+            boost_duration: 3.0,
+            boost_recharge_time: 20.0,
+            drift_recharge_time: 5.0,
+        }
+    }
+}
@@ -1,6 +1,9 @@
 use glow::*;
 use nalgebra::Vector3;
-use sdl2::{event::Event, keyboard::Keycode};
+use sdl2::{
+    event::Event,
+    keyboard::{Keycode, Mod},
+};
 
 use crate::core::{game::Game, game_object::GameObject};
 
@@ -16,6 +19,8 @@ pub struct FreecamController {
     moving_foward: bool,
     moving_backward: bool,
     arrow_direction: ArrowDir,
+    // Units per second the camera moves forward and backward
+    speed: f32,
 }
 
 impl<'a> FreecamController {
@@ -24,8 +29,14 @@ impl<'a> FreecamController {
             moving_foward: false,
             moving_backward: false,
             arrow_direction: ArrowDir::None,
+            speed: 10.0,
         }
     }
+
+    pub fn with_speed(mut self, speed: f32) -> FreecamController {
+        self.speed = speed;
+        self
+    }
 }
 
 impl<'a> GameObject<'a> for FreecamController {
@@ -43,10 +54,12 @@ impl<'a> GameObject<'a> for FreecamController {
             } => {
                 self.moving_foward = false;
             }
+            // Ctrl+S saves in the track editor
             Event::KeyDown {
                 keycode: Some(Keycode::S),
+                keymod,
                 ..
-            } => {
+            } if !keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
                 self.moving_backward = true;
             }
             Event::KeyUp {
@@ -86,7 +99,7 @@ impl<'a> GameObject<'a> for FreecamController {
             view_matrix.slide(
                 0.0,
                 0.0,
-                -game.delta_time * 10.0,
+                -game.delta_time * self.speed,
                 Vector3::zeros(),
                 Vector3::zeros(),
                 n,
@@ -98,7 +111,7 @@ impl<'a> GameObject<'a> for FreecamController {
             view_matrix.slide(
                 0.0,
                 0.0,
-                game.delta_time * 10.0,
+                game.delta_time * self.speed,
                 Vector3::zeros(),
                 Vector3::zeros(),
                 n,
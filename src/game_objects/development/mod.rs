@@ -1 +1,2 @@
-pub mod freecam_controller;
\ No newline at end of file
+pub mod freecam_controller;
+pub mod track_editor;
@@ -0,0 +1,507 @@
+use glow::Context;
+use nalgebra::Vector3;
+use sdl2::{
+    event::Event,
+    keyboard::{Keycode, Mod},
+    mouse::MouseButton,
+};
+
+use crate::{
+    core::{color::Color, game::Game, game_object::GameObject},
+    game_objects::{
+        environment::cactus::{Cactus, CactusModels, CactusType},
+        track::{
+            track::Track,
+            track_file::{segment_position, track_position, Prop, SegmentDescription, TrackFile},
+            track_segment::{TRACK_ELEVATION, TRACK_WIDTH},
+            track_spline::SplinePoint,
+        },
+    },
+};
+
+// Degrees a selected segment or prop is turned per key press
+const ROTATION_STEP: f32 = 15.0;
+// How far from a handle of an item a click may be to select it
+const PICK_DISTANCE: f32 = 20.0;
+// Length of a newly placed straight
+const NEW_STRAIGHT_LENGTH: f32 = 50.0;
+// Most edits that can be undone
+const MAX_UNDO: usize = 100;
+
+/// Something in the track file that can be selected and edited
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Selection {
+    Segment(usize),
+    Prop(usize),
+}
+
+/// Snapshots of the track file before and after each edit
+pub struct EditHistory {
+    undo_stack: Vec<TrackFile>,
+    redo_stack: Vec<TrackFile>,
+}
+
+impl Default for EditHistory {
+    fn default() -> EditHistory {
+        EditHistory::new()
+    }
+}
+
+impl EditHistory {
+    pub fn new() -> EditHistory {
+        EditHistory {
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+        }
+    }
+
+    /// Remember the track as it was before an edit, this clears the edits that were undone
+    pub fn record(&mut self, before: TrackFile) {
+        self.undo_stack.push(before);
+        if self.undo_stack.len() > MAX_UNDO {
+            self.undo_stack.remove(0);
+        }
+        self.redo_stack.clear();
+    }
+
+    /// Go back to the track before the last edit, returns false if there is nothing to undo
+    pub fn undo(&mut self, track_file: &mut TrackFile) -> bool {
+        match self.undo_stack.pop() {
+            Some(before) => {
+                self.redo_stack.push(std::mem::replace(track_file, before));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Apply the last undone edit again, returns false if there is nothing to redo
+    pub fn redo(&mut self, track_file: &mut TrackFile) -> bool {
+        match self.redo_stack.pop() {
+            Some(after) => {
+                self.undo_stack.push(std::mem::replace(track_file, after));
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Points of an item that can be clicked to select it, relative to the track surface
+pub fn handles(track_file: &TrackFile, selection: Selection) -> Vec<Vector3<f32>> {
+    match selection {
+        Selection::Segment(i) => match track_file.segments.get(i) {
+            Some(SegmentDescription::Spline { points, .. }) => {
+                points.iter().map(|p| segment_position(p.position)).collect()
+            }
+            Some(segment) => {
+                let (entry, exit) = (segment.entry().position, segment.exit().position);
+                vec![entry, 0.5 * entry + 0.5 * exit, exit]
+            }
+            None => Vec::new(),
+        },
+        Selection::Prop(i) => match track_file.props.get(i) {
+            Some(Prop::Cactus { position, .. }) => vec![segment_position(*position)],
+            None => Vec::new(),
+        },
+    }
+}
+
+/// The segment or prop with a handle closest to the point, if it is close enough
+pub fn pick(track_file: &TrackFile, point: &Vector3<f32>) -> Option<Selection> {
+    let segments = (0..track_file.segments.len()).map(Selection::Segment);
+    let props = (0..track_file.props.len()).map(Selection::Prop);
+
+    segments
+        .chain(props)
+        .flat_map(|s| {
+            handles(track_file, s)
+                .into_iter()
+                .map(move |h| (s, (h - point).xz().norm()))
+        })
+        .filter(|(_, distance)| *distance <= PICK_DISTANCE)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(s, _)| s)
+}
+
+/// Move the selected item along the ground
+pub fn translate(track_file: &mut TrackFile, selection: Selection, offset: &Vector3<f32>) {
+    match selection {
+        Selection::Segment(i) => {
+            if let Some(segment) = track_file.segments.get_mut(i) {
+                *segment = segment.translated(offset);
+            }
+        }
+        Selection::Prop(i) => {
+            if let Some(Prop::Cactus { position, .. }) = track_file.props.get_mut(i) {
+                *position = (position.0 + offset.x, position.1 + offset.y, position.2 + offset.z);
+            }
+        }
+    }
+}
+
+/// Turn the selected item around its position, splines turn around the middle of their points.
+/// The angle is in degrees
+pub fn rotate(track_file: &mut TrackFile, selection: Selection, angle: f32) {
+    match selection {
+        Selection::Segment(i) => match track_file.segments.get_mut(i) {
+            Some(
+                SegmentDescription::Straight { rotation, .. }
+                | SegmentDescription::RightCorner { rotation, .. }
+                | SegmentDescription::UCorner { rotation, .. },
+            ) => *rotation = (*rotation + angle).rem_euclid(360.0),
+            Some(SegmentDescription::Spline { points, .. }) => {
+                let centre = points.iter().map(|p| segment_position(p.position)).sum::<Vector3<f32>>()
+                    / points.len().max(1) as f32;
+                let (rot_sin, rot_cos) = angle.to_radians().sin_cos();
+
+                for point in points.iter_mut() {
+                    let local = segment_position(point.position) - centre;
+                    point.position = (
+                        centre.x + local.x * rot_cos + local.z * rot_sin,
+                        point.position.1,
+                        centre.z - local.x * rot_sin + local.z * rot_cos,
+                    );
+                }
+            }
+            None => (),
+        },
+        Selection::Prop(i) => {
+            if let Some(Prop::Cactus { rotation, .. }) = track_file.props.get_mut(i) {
+                *rotation = (*rotation + angle).rem_euclid(360.0);
+            }
+        }
+    }
+}
+
+/// Remove the selected item
+pub fn delete(track_file: &mut TrackFile, selection: Selection) {
+    match selection {
+        Selection::Segment(i) if i < track_file.segments.len() => {
+            track_file.segments.remove(i);
+        }
+        Selection::Prop(i) if i < track_file.props.len() => {
+            track_file.props.remove(i);
+        }
+        _ => (),
+    }
+}
+
+/// An item being moved with the mouse
+struct Drag {
+    // Last point on the ground under the mouse
+    last: Vector3<f32>,
+    // The track before the drag started
+    before: TrackFile,
+    moved: bool,
+}
+
+/// Edits a copy of the track file with the mouse and keyboard while the free camera flies around.
+///
+/// Left click selects the segment or prop under the mouse and dragging moves it.
+/// Q and E turn the selection, Delete removes it and 1 to 5 place a straight, a corner, a U corner,
+/// a spline or a cactus under the mouse. Ctrl+Z undoes, Ctrl+Y or Ctrl+Shift+Z redoes
+/// and Ctrl+S saves the track to the file it was loaded from
+pub struct TrackEditor<'a> {
+    track_file: TrackFile,
+    track: Track<'a>,
+    props: Vec<Cactus<'a>>,
+    cactus_models: CactusModels<'a>,
+    history: EditHistory,
+    selection: Option<Selection>,
+    drag: Option<Drag>,
+    mouse_position: (i32, i32),
+    rebuild_track: bool,
+    rebuild_props: bool,
+}
+
+impl<'a> TrackEditor<'a> {
+    pub fn new(track_file: TrackFile, gl: &'a Context, game: &Game) -> TrackEditor<'a> {
+        let track = Track::new(&track_file, gl, game);
+        let cactus_models = CactusModels::load(gl, game);
+        let props = TrackEditor::create_props(&track_file, &cactus_models);
+
+        // Look down on the start of the track
+        let start = track_file
+            .grid_position(0)
+            .map(|(p, _)| p)
+            .unwrap_or_else(|| Vector3::new(0.0, TRACK_ELEVATION, 0.0));
+        game.view_matrix.borrow_mut().look(
+            start + Vector3::new(0.0, 120.0, -120.0),
+            start,
+            Vector3::new(0.0, 1.0, 0.0),
+        );
+
+        TrackEditor {
+            track_file,
+            track,
+            props,
+            cactus_models,
+            history: EditHistory::new(),
+            selection: None,
+            drag: None,
+            mouse_position: (0, 0),
+            rebuild_track: false,
+            rebuild_props: false,
+        }
+    }
+
+    fn create_props(track_file: &TrackFile, cactus_models: &CactusModels<'a>) -> Vec<Cactus<'a>> {
+        track_file
+            .props
+            .iter()
+            .map(|prop| match *prop {
+                Prop::Cactus { position, rotation, kind } => {
+                    Cactus::new(track_position(position), rotation.to_radians(), kind, cactus_models)
+                }
+            })
+            .collect()
+    }
+
+    /// Point on the ground under the mouse, relative to the track surface
+    fn ground_point(&self, game: &Game, x: i32, y: i32) -> Option<Vector3<f32>> {
+        let (width, height) = game.window_size();
+        let view_matrix = game.view_matrix.borrow();
+        let projection_matrix = game.projection_matrix.borrow();
+
+        let screen_x = 2.0 * x as f32 / width as f32 - 1.0;
+        let screen_y = 1.0 - 2.0 * y as f32 / height as f32;
+        let dir = -view_matrix.n.normalize()
+            + view_matrix.u.normalize() * screen_x * projection_matrix.right / projection_matrix.near
+            + view_matrix.v.normalize() * screen_y * projection_matrix.top / projection_matrix.near;
+
+        let distance = (TRACK_ELEVATION - view_matrix.eye.y) / dir.y;
+        if !distance.is_finite() || distance <= 0.0 {
+            return None;
+        }
+
+        let point = view_matrix.eye + distance * dir;
+        Some(Vector3::new(point.x, 0.0, point.z))
+    }
+
+    /// Make an edit that can be undone
+    fn edit(&mut self, edit: impl FnOnce(&mut TrackFile)) {
+        let before = self.track_file.clone();
+        edit(&mut self.track_file);
+        self.changed(&before);
+        self.history.record(before);
+    }
+
+    /// Rebuild only the track or the props if an edit changed them, they create GL objects
+    fn changed(&mut self, before: &TrackFile) {
+        self.rebuild_track |= before.segments != self.track_file.segments;
+        self.rebuild_props |= before.props != self.track_file.props;
+    }
+
+    /// Undo the last edit, or redo the last undone one
+    fn step_history(&mut self, redo: bool) {
+        let before = self.track_file.clone();
+        let changed = if redo {
+            self.history.redo(&mut self.track_file)
+        } else {
+            self.history.undo(&mut self.track_file)
+        };
+
+        if changed {
+            self.selection = None;
+            self.changed(&before);
+        }
+    }
+
+    fn place(&mut self, game: &Game, key: Keycode) {
+        let Some(point) = self.ground_point(game, self.mouse_position.0, self.mouse_position.1) else {
+            return;
+        };
+        let position = (point.x, 0.0, point.z);
+
+        let segment = match key {
+            Keycode::Num1 => Some(SegmentDescription::Straight {
+                position,
+                rotation: 0.0,
+                slope: 0.0,
+                banking: 0.0,
+                length: NEW_STRAIGHT_LENGTH,
+            }),
+            Keycode::Num2 => Some(SegmentDescription::RightCorner { position, rotation: 0.0 }),
+            Keycode::Num3 => Some(SegmentDescription::UCorner { position, rotation: 0.0 }),
+            Keycode::Num4 => Some(SegmentDescription::Spline {
+                points: [-40.0, 0.0, 40.0]
+                    .iter()
+                    .map(|z| SplinePoint {
+                        position: (point.x, 0.0, point.z + z),
+                        width: TRACK_WIDTH,
+                        banking: 0.0,
+                    })
+                    .collect(),
+                closed: false,
+            }),
+            _ => None,
+        };
+
+        match segment {
+            Some(segment) => {
+                self.edit(|t| t.segments.push(segment));
+                self.selection = Some(Selection::Segment(self.track_file.segments.len() - 1));
+            }
+            None => {
+                let prop = Prop::Cactus { position, rotation: 0.0, kind: CactusType::Small };
+                self.edit(|t| t.props.push(prop));
+                self.selection = Some(Selection::Prop(self.track_file.props.len() - 1));
+            }
+        }
+    }
+
+    fn save(&self, game: &Game) {
+        match self.track_file.save(&game.track_path) {
+            Ok(()) => log::info!("Saved track to {}", game.track_path.display()),
+            Err(e) => log::error!("{e:#}"),
+        }
+    }
+
+    fn display_selection(&self, game: &Game) {
+        let Some(selection) = self.selection else {
+            return;
+        };
+
+        let mut model_matrix = game.model_matrix.borrow_mut();
+
+        let color = Color::new(1.0, 0.9, 0.0);
+        game.shader.set_material_ambient(&color);
+        game.shader.set_material_diffuse(&color);
+        game.shader.set_material_specular(&Color::new(0.0, 0.0, 0.0));
+
+        for handle in handles(&self.track_file, selection) {
+            let p = track_position((handle.x, handle.y, handle.z));
+            model_matrix.push_stack();
+            model_matrix.add_translate(p.x, p.y + 3.0, p.z);
+            model_matrix.add_scale(2.0, 2.0, 2.0);
+            game.shader.set_model_matrix(model_matrix.matrix.as_slice());
+            game.cube.draw(&game.shader);
+            model_matrix.pop_stack();
+        }
+    }
+}
+
+impl<'a> GameObject<'a> for TrackEditor<'a> {
+    fn on_event(&mut self, game: &Game, event: &Event) {
+        match *event {
+            Event::MouseButtonDown { mouse_btn: MouseButton::Left, x, y, .. } => {
+                let point = self.ground_point(game, x, y);
+                self.selection = point.and_then(|p| pick(&self.track_file, &p));
+                self.drag = match (point, self.selection) {
+                    (Some(last), Some(_)) => Some(Drag { last, before: self.track_file.clone(), moved: false }),
+                    _ => None,
+                };
+            }
+            Event::MouseMotion { x, y, .. } => {
+                self.mouse_position = (x, y);
+
+                // The geometry is only rebuilt when the item is dropped, until then the handles show where it goes
+                if let Some(selection) = self.selection {
+                    let point = self.ground_point(game, x, y);
+                    if let (Some(drag), Some(point)) = (&mut self.drag, point) {
+                        translate(&mut self.track_file, selection, &(point - drag.last));
+                        drag.last = point;
+                        drag.moved = true;
+                    }
+                }
+            }
+            Event::MouseButtonUp { mouse_btn: MouseButton::Left, .. } => {
+                if let Some(drag) = self.drag.take() {
+                    if drag.moved {
+                        self.changed(&drag.before);
+                        self.history.record(drag.before);
+                    }
+                }
+            }
+            Event::KeyDown { keycode: Some(key), keymod, .. } => {
+                let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+                let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+
+                match (key, self.selection) {
+                    (Keycode::S, _) if ctrl => self.save(game),
+                    (Keycode::Z, _) if ctrl => self.step_history(shift),
+                    (Keycode::Y, _) if ctrl => self.step_history(true),
+                    (Keycode::Q, Some(selection)) => self.edit(|t| rotate(t, selection, ROTATION_STEP)),
+                    (Keycode::E, Some(selection)) => self.edit(|t| rotate(t, selection, -ROTATION_STEP)),
+                    (Keycode::Delete | Keycode::Backspace, Some(selection)) => {
+                        self.edit(|t| delete(t, selection));
+                        self.selection = None;
+                    }
+                    (Keycode::Num1 | Keycode::Num2 | Keycode::Num3 | Keycode::Num4 | Keycode::Num5, _) => {
+                        self.place(game, key)
+                    }
+                    _ => (),
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn update(&mut self, game: &Game, gl: &'a Context) {
+        if self.rebuild_track {
+            self.track = Track::new(&self.track_file, gl, game);
+            self.rebuild_track = false;
+        }
+        if self.rebuild_props {
+            self.props = TrackEditor::create_props(&self.track_file, &self.cactus_models);
+            self.rebuild_props = false;
+        }
+    }
+
+    fn display(&self, game: &Game, gl: &'a Context) {
+        self.track.display(game, gl);
+        for prop in &self.props {
+            prop.display(game, gl);
+        }
+        self.display_selection(game);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game_objects::track::track_file::bundled_track;
+
+    use super::*;
+
+    fn track() -> TrackFile {
+        bundled_track("default.ron")
+    }
+
+    #[test]
+    fn undo_and_redo_restore_the_track() {
+        let mut track_file = track();
+        let mut history = EditHistory::new();
+        let segments = track_file.segments.len();
+
+        history.record(track_file.clone());
+        delete(&mut track_file, Selection::Segment(0));
+        assert_eq!(track_file.segments.len(), segments - 1);
+
+        assert!(history.undo(&mut track_file));
+        assert_eq!(track_file.segments.len(), segments);
+        assert!(!history.undo(&mut track_file));
+
+        assert!(history.redo(&mut track_file));
+        assert_eq!(track_file.segments.len(), segments - 1);
+        assert!(!history.redo(&mut track_file));
+    }
+
+    #[test]
+    fn picked_segment_moves_with_the_mouse() {
+        let mut track_file = track();
+        let entry = track_file.segments[0].entry().position;
+
+        // The middle of the start straight
+        let selection = pick(&track_file, &Vector3::new(0.0, 0.0, 66.0)).unwrap();
+        assert_eq!(selection, Selection::Segment(0));
+
+        let offset = Vector3::new(5.0, 0.0, -3.0);
+        translate(&mut track_file, selection, &offset);
+        assert!((track_file.segments[0].entry().position - (entry + offset)).norm() < 0.01);
+
+        rotate(&mut track_file, selection, 90.0);
+        rotate(&mut track_file, selection, -90.0);
+        assert!((track_file.segments[0].entry().position - (entry + offset)).norm() < 0.01);
+    }
+}
@@ -1,8 +1,12 @@
+use std::rc::Rc;
+
 use glow::Context;
 use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
 
 use crate::{core::{game_object::GameObject, obj_loader::load_obj_file, constants::MODEL_LOCATION, game::Game}, objects::mesh_model::MeshModel};
 
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum CactusType {
     Small,
     Large,
@@ -11,24 +15,35 @@ pub enum CactusType {
 pub struct Cactus<'a> {
     position: Vector3<f32>,
     rotation: f32,
-    model: MeshModel<'a>,
+    model: Rc<MeshModel<'a>>,
 }
 
 impl<'a> Cactus<'a> {
-    pub fn new(position: Vector3<f32>, rotation: f32, cactus_type: CactusType, gl: &'a Context, game: &Game) -> Cactus<'a> {
+    pub fn new(position: Vector3<f32>, rotation: f32, cactus_type: CactusType, models: &CactusModels<'a>) -> Cactus<'a> {
         use CactusType::*;
-        let model_file = match cactus_type {
-            Small => "cactus-small.obj",
-            Large => "cactus-large.obj"
+        let model = match cactus_type {
+            Small => models.small.clone(),
+            Large => models.large.clone(),
         };
 
-        let model = load_obj_file(MODEL_LOCATION, model_file, gl, game)
-            .expect("Failed to load model");
-
         Cactus { position, rotation, model }
     }
 }
 
+/// The models of both kinds of cactus, loaded once and shared by all cacti
+pub struct CactusModels<'a> {
+    small: Rc<MeshModel<'a>>,
+    large: Rc<MeshModel<'a>>,
+}
+
+impl<'a> CactusModels<'a> {
+    pub fn load(gl: &'a Context, game: &Game) -> CactusModels<'a> {
+        let load = |model_file| Rc::new(load_obj_file(MODEL_LOCATION, model_file, gl, game).expect("Failed to load model"));
+
+        CactusModels { small: load("cactus-small.obj"), large: load("cactus-large.obj") }
+    }
+}
+
 impl<'a> GameObject<'a> for Cactus<'a> {
     fn on_event(&mut self, _game: &crate::core::game::Game, _event: &sdl2::event::Event) {}
 
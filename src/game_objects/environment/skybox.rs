@@ -9,16 +9,11 @@ pub struct Skybox<'a> {
 }
 
 impl<'a> Skybox<'a> {
-    pub fn new(gl: &'a Context, game: &Game) -> Skybox<'a> {
-        let skybox_faces = vec![
-            "./models/textures/mars_skybox/front.png",
-            "./models/textures/mars_skybox/back.png",
-            "./models/textures/mars_skybox/down.png",
-            "./models/textures/mars_skybox/up.png",
-            "./models/textures/mars_skybox/left.png",
-            "./models/textures/mars_skybox/right.png",
-        ];
-        let skybox_texture = skybox_faces.iter().map(|t| game.load_texture(*t, false)).collect();
+    /// Load the skybox from a folder with a png image for each face of the cube
+    pub fn new(folder: &str, gl: &'a Context, game: &Game) -> Skybox<'a> {
+        let skybox_faces = ["front", "back", "down", "up", "left", "right"]
+            .map(|face| format!("{folder}/{face}.png"));
+        let skybox_texture = skybox_faces.iter().map(|t| game.load_texture(t, false)).collect();
         let skybox_cubemap = SkyboxCubemap::new(gl);
 
         Skybox { skybox_texture, skybox_cubemap }
@@ -1,4 +1,6 @@
 pub mod cars;
 pub mod environment;
 pub mod development;
+pub mod race;
+pub mod replay;
 pub mod track;
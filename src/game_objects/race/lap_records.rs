@@ -0,0 +1,230 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::{core::constants::USER_DATA_FOLDER, game_objects::track::track_file::TrackFile};
+
+// Laps kept for each track and car profile
+const LEADERBOARD_SIZE: usize = 10;
+
+/// A valid lap driven on a track
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LapRecord {
+    pub track: String,
+    // The car the lap was driven with, arcade cars have a boost so they are timed separately
+    pub profile: String,
+    pub lap_time: f32,
+    pub sectors: Vec<f32>,
+    // Seconds since 1970
+    #[serde(default)]
+    pub recorded_at: u64,
+}
+
+impl LapRecord {
+    pub fn new(track: &str, profile: &str, lap_time: f32, sectors: &[f32]) -> LapRecord {
+        LapRecord {
+            track: track.to_string(),
+            profile: profile.to_string(),
+            lap_time,
+            sectors: sectors.to_vec(),
+            recorded_at: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+        }
+    }
+
+    /// Time from the start of the lap to the end of each sector
+    pub fn splits(&self) -> Vec<f32> {
+        self.sectors
+            .iter()
+            .scan(0.0, |time, sector| {
+                *time += sector;
+                Some(*time)
+            })
+            .collect()
+    }
+}
+
+/// The fastest laps on every track, saved in the user data folder
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LapRecords {
+    records: Vec<LapRecord>,
+}
+
+impl LapRecords {
+    /// No file yet means no laps have been driven
+    pub fn load(path: &Path) -> anyhow::Result<LapRecords> {
+        if !path.exists() {
+            return Ok(LapRecords::default());
+        }
+
+        let text = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read lap records {}. {e}", path.display()))?;
+        ron::from_str(&text).map_err(|e| anyhow!("Failed to parse lap records {}. {e}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(folder) = path.parent() {
+            fs::create_dir_all(folder)
+                .map_err(|e| anyhow!("Failed to create folder {}. {e}", folder.display()))?;
+        }
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new().struct_names(false))
+            .map_err(|e| anyhow!("Failed to serialize lap records. {e}"))?;
+        fs::write(path, text).map_err(|e| anyhow!("Failed to write lap records {}. {e}", path.display()))
+    }
+
+    /// The fastest laps on the track with the car profile, fastest first
+    pub fn leaderboard(&self, track: &str, profile: &str) -> Vec<&LapRecord> {
+        let mut laps = self
+            .records
+            .iter()
+            .filter(|r| r.track == track && r.profile == profile)
+            .collect::<Vec<_>>();
+        laps.sort_by(|a, b| a.lap_time.total_cmp(&b.lap_time));
+        laps
+    }
+
+    pub fn personal_best(&self, track: &str, profile: &str) -> Option<&LapRecord> {
+        self.leaderboard(track, profile).first().copied()
+    }
+
+    /// The car profiles laps have been driven with on the track
+    pub fn profiles(&self, track: &str) -> Vec<&str> {
+        let mut profiles = self
+            .records
+            .iter()
+            .filter(|r| r.track == track)
+            .map(|r| r.profile.as_str())
+            .collect::<Vec<_>>();
+        profiles.sort();
+        profiles.dedup();
+        profiles
+    }
+
+    /// Add the lap if it is one of the fastest, returns its place on the leaderboard counting from 1
+    pub fn add(&mut self, record: LapRecord) -> Option<usize> {
+        let (track, profile) = (record.track.clone(), record.profile.clone());
+        self.records.push(record);
+
+        let leaderboard = self.leaderboard(&track, &profile);
+        let added = self.records.last().map(|r| r as *const LapRecord);
+        let place = leaderboard.iter().position(|r| Some(*r as *const LapRecord) == added);
+        // Only the fastest laps of the track and profile are kept, one lap is added at a time
+        if leaderboard.len() > LEADERBOARD_SIZE {
+            let slowest = leaderboard[leaderboard.len() - 1] as *const LapRecord;
+            self.records.retain(|r| !std::ptr::eq(r, slowest));
+        }
+
+        place.filter(|p| *p < LEADERBOARD_SIZE).map(|p| p + 1)
+    }
+}
+
+/// Where the lap records are saved, None when the system has no user data folder
+pub fn lap_records_path() -> Option<PathBuf> {
+    user_data_folder().map(|folder| folder.join("lap_records.ron"))
+}
+
+/// The folder of the game in the user data folder of the system
+pub fn user_data_folder() -> Option<PathBuf> {
+    user_data_dir().map(|dir| dir.join(USER_DATA_FOLDER))
+}
+
+fn user_data_dir() -> Option<PathBuf> {
+    let home = || env::var_os("HOME").map(PathBuf::from);
+    if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home().map(|h| h.join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|p| p.is_absolute())
+            .or_else(|| home().map(|h| h.join(".local/share")))
+    }
+}
+
+/// Name of the car setup laps are timed for
+pub fn car_profile(arcade_mode: bool) -> &'static str {
+    if arcade_mode {
+        "arcade"
+    } else {
+        "standard"
+    }
+}
+
+/// Name of the track laps are timed on, the name of its file and a hash of its geometry.
+/// An edited track starts with no records, and so does a random track of another length
+pub fn track_name(track_path: &Path, track_file: &TrackFile) -> String {
+    let name = track_path.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+    format!("{name}-{:08x}", track_file.geometry_hash())
+}
+
+/// Minutes, seconds and milliseconds, like 1:02.345
+pub fn format_time(seconds: f32) -> String {
+    let minutes = (seconds / 60.0).floor();
+    format!("{}:{:06.3}", minutes as u32, seconds - minutes * 60.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game_objects::track::track_file::bundled_track;
+
+    use super::*;
+
+    #[test]
+    fn leaderboard_keeps_the_fastest_laps() {
+        let mut records = LapRecords::default();
+        for i in 0..15 {
+            records.add(LapRecord::new("default", "standard", 60.0 - i as f32, &[30.0, 30.0 - i as f32]));
+        }
+        records.add(LapRecord::new("default", "arcade", 40.0, &[]));
+        records.add(LapRecord::new("hills", "standard", 90.0, &[]));
+
+        let leaderboard = records.leaderboard("default", "standard");
+        assert_eq!(leaderboard.len(), LEADERBOARD_SIZE);
+        assert_eq!(leaderboard[0].lap_time, 46.0);
+        assert_eq!(records.personal_best("default", "arcade").unwrap().lap_time, 40.0);
+        assert_eq!(records.profiles("default"), ["arcade", "standard"]);
+
+        // Too slow to get on the leaderboard
+        assert_eq!(records.add(LapRecord::new("default", "standard", 70.0, &[])), None);
+        assert_eq!(records.add(LapRecord::new("default", "standard", 47.5, &[])), Some(3));
+        assert_eq!(records.leaderboard("default", "standard").len(), LEADERBOARD_SIZE);
+        assert_eq!(records.personal_best("default", "standard").unwrap().splits(), [30.0, 46.0]);
+    }
+
+    #[test]
+    fn records_survive_saving() {
+        let mut records = LapRecords::default();
+        records.add(LapRecord::new("default", "standard", 61.25, &[20.5, 40.75]));
+
+        let path = std::env::temp_dir().join("lap_records_survive_saving.ron");
+        records.save(&path).unwrap();
+        assert_eq!(LapRecords::load(&path).unwrap(), records);
+        assert_eq!(LapRecords::load(&path.with_extension("missing")).unwrap(), LapRecords::default());
+    }
+
+    #[test]
+    fn edited_tracks_have_their_own_records() {
+        let path = Path::new("./tracks/default.ron");
+        let mut track = bundled_track("default.ron");
+        let name = track_name(path, &track);
+        assert!(name.starts_with("default-"), "{name}");
+
+        track.skybox.clear();
+        assert_eq!(track_name(path, &track), name);
+        track.start_grid.clear();
+        assert_eq!(track_name(path, &track), name);
+        track.checkpoints.pop();
+        assert_ne!(track_name(path, &track), name);
+    }
+
+    #[test]
+    fn times_are_formatted_with_minutes() {
+        assert_eq!(format_time(62.345), "1:02.345");
+        assert_eq!(format_time(9.5), "0:09.500");
+    }
+}
@@ -0,0 +1,119 @@
+use super::lap_records::{format_time, LapRecord, LapRecords};
+
+/// Compares the laps of the player with their personal best on the track and adds them to the lap records
+pub struct LapTimer {
+    track: String,
+    profile: String,
+    last_lap: Option<f32>,
+    // How much slower than the personal best the player was at the last checkpoint
+    split: Option<f32>,
+}
+
+impl LapTimer {
+    pub fn new(track: &str, profile: &str) -> LapTimer {
+        LapTimer {
+            track: track.to_string(),
+            profile: profile.to_string(),
+            last_lap: None,
+            split: None,
+        }
+    }
+
+    pub fn personal_best<'r>(&self, records: &'r LapRecords) -> Option<&'r LapRecord> {
+        records.personal_best(&self.track, &self.profile)
+    }
+
+    pub fn lap_started(&mut self) {
+        self.split = None;
+    }
+
+    /// The player finished a sector, returns the difference to the personal best at the same checkpoint
+    pub fn sector_finished(&mut self, records: &LapRecords, sectors: &[f32]) -> Option<f32> {
+        let time = sectors.iter().sum::<f32>();
+        self.split = self
+            .personal_best(records)
+            .and_then(|best| best.splits().get(sectors.len().checked_sub(1)?).copied())
+            .map(|best_time| time - best_time);
+        self.split
+    }
+
+    /// Adds the lap to the records, returns its place on the leaderboard if it made it
+    pub fn lap_finished(&mut self, records: &mut LapRecords, lap_time: f32, sectors: &[f32]) -> Option<usize> {
+        let best = self.personal_best(records).map(|r| r.lap_time);
+        self.last_lap = Some(lap_time);
+        self.split = None;
+
+        let place = records.add(LapRecord::new(&self.track, &self.profile, lap_time, sectors));
+        match place {
+            Some(1) => match best {
+                Some(best) => log::info!("New personal best {}, {:.3} faster", format_time(lap_time), best - lap_time),
+                None => log::info!("New personal best {}", format_time(lap_time)),
+            },
+            Some(place) => log::info!("{} is number {place} on the leaderboard", format_time(lap_time)),
+            None => (),
+        }
+        place
+    }
+
+    /// The time of the current lap and how it compares, to be shown while driving
+    pub fn status(&self, records: &LapRecords, lap_time: Option<f32>) -> String {
+        let mut parts = vec![lap_time.map_or_else(|| String::from("-:--.---"), format_time)];
+        if let Some(split) = self.split {
+            parts.push(format!("Split {split:+.3}"));
+        }
+        if let Some(last_lap) = self.last_lap {
+            parts.push(format!("Last {}", format_time(last_lap)));
+        }
+        if let Some(best) = self.personal_best(records) {
+            parts.push(format!("Best {}", format_time(best.lap_time)));
+        }
+        parts.join(" | ")
+    }
+
+    pub fn log_leaderboard(&self, records: &LapRecords) {
+        log_leaderboard(records, &self.track, &self.profile);
+    }
+}
+
+pub fn log_leaderboard(records: &LapRecords, track: &str, profile: &str) {
+    let leaderboard = records.leaderboard(track, profile);
+    if leaderboard.is_empty() {
+        log::info!("No {profile} laps on {track} yet");
+        return;
+    }
+
+    log::info!("Fastest {profile} laps on {track}:");
+    for (place, record) in leaderboard.iter().enumerate() {
+        let sectors = record.sectors.iter().map(|s| format!("{s:.3}")).collect::<Vec<_>>();
+        log::info!("{:2}. {} ({})", place + 1, format_time(record.lap_time), sectors.join(", "));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_compare_with_the_personal_best() {
+        let mut records = LapRecords::default();
+        let mut timer = LapTimer::new("default", "standard");
+        assert_eq!(timer.sector_finished(&records, &[20.0]), None);
+        assert_eq!(timer.lap_finished(&mut records, 60.0, &[20.0, 25.0, 15.0]), Some(1));
+        assert_eq!(timer.personal_best(&records).unwrap().lap_time, 60.0);
+
+        timer.lap_started();
+        assert_eq!(timer.sector_finished(&records, &[19.5]), Some(-0.5));
+        assert_eq!(timer.sector_finished(&records, &[19.5, 26.5]), Some(1.0));
+        let status = timer.status(&records, Some(50.0));
+        assert!(status.contains("Split +1.000"), "{status}");
+
+        // A slower lap doesn't change the personal best
+        assert_eq!(timer.lap_finished(&mut records, 62.0, &[19.5, 26.5, 16.0]), Some(2));
+        assert_eq!(timer.personal_best(&records).unwrap().lap_time, 60.0);
+        assert_eq!(timer.status(&records, None), "-:--.--- | Last 1:02.000 | Best 1:00.000");
+
+        // Another player on split screen sees the laps of the first
+        let other = LapTimer::new("default", "standard");
+        assert_eq!(other.personal_best(&records).unwrap().lap_time, 60.0);
+    }
+}
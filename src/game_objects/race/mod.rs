@@ -0,0 +1,3 @@
+pub mod lap_records;
+pub mod lap_timer;
+pub mod race_controller;
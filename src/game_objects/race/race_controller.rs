@@ -0,0 +1,348 @@
+// Seconds on the grid before the countdown starts, and the length of the countdown
+const GRID_TIME: f32 = 2.0;
+const COUNTDOWN_TIME: f32 = 3.0;
+// After the players have finished the others get this long to finish before the results are shown
+const FINISH_WAIT_TIME: f32 = 15.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RaceState {
+    // The cars are on the grid and can't drive yet
+    Grid,
+    Countdown,
+    Racing,
+    // The players have driven all laps, the others are still finishing
+    Finished,
+    Results,
+}
+
+/// A car taking part in the race
+#[derive(Clone, Debug, PartialEq)]
+pub struct RaceEntry {
+    pub name: String,
+    pub laps: u32,
+    pub best_lap: Option<f32>,
+    // Race time when the last lap was done
+    pub finish_time: Option<f32>,
+}
+
+/// Takes the race from the grid through the countdown and the laps to the results. The cars report
+/// their laps to it and drive to the grid when it is restarted, the same in single player and multiplayer.
+/// In multiplayer the server starts the race, so the cars wait on the grid until it does
+pub struct RaceController {
+    state: RaceState,
+    // Seconds since the state started
+    state_time: f32,
+    race_time: f32,
+    // None races forever, like in time trial
+    lap_count: Option<u32>,
+    // The players at this computer, the race is over for them when they have all finished
+    players: Vec<String>,
+    entries: Vec<RaceEntry>,
+    // Goes up every time the race is restarted, so the cars know to go back to the grid
+    start_number: u32,
+    // The race is started by the server instead of after the grid time
+    waits_for_start: bool,
+    // On the grid until the server starts the race
+    waiting: bool,
+}
+
+impl RaceController {
+    pub fn new(lap_count: Option<u32>, waits_for_start: bool) -> RaceController {
+        RaceController {
+            state: RaceState::Grid,
+            state_time: 0.0,
+            race_time: 0.0,
+            lap_count,
+            players: Vec::new(),
+            entries: Vec::new(),
+            start_number: 0,
+            waits_for_start,
+            waiting: waits_for_start,
+        }
+    }
+
+    pub fn state(&self) -> RaceState {
+        self.state
+    }
+
+    pub fn lap_count(&self) -> Option<u32> {
+        self.lap_count
+    }
+
+    pub fn start_number(&self) -> u32 {
+        self.start_number
+    }
+
+    /// Seconds since the start, 0 before it
+    pub fn race_time(&self) -> f32 {
+        self.race_time
+    }
+
+    /// The cars are on the grid until the server starts the race
+    pub fn waiting_for_start(&self) -> bool {
+        self.waiting
+    }
+
+    /// Whole seconds left of the countdown, counting down to 1
+    pub fn countdown(&self) -> Option<u32> {
+        (self.state == RaceState::Countdown).then(|| (COUNTDOWN_TIME - self.state_time).ceil().max(1.0) as u32)
+    }
+
+    /// Laps the car has finished in this race
+    pub fn laps(&self, name: &str) -> u32 {
+        self.entries.iter().find(|e| e.name == name).map_or(0, |e| e.laps)
+    }
+
+    /// Nobody may drive before the lights go out
+    pub fn inputs_frozen(&self) -> bool {
+        matches!(self.state, RaceState::Grid | RaceState::Countdown)
+    }
+
+    /// Add a car to the race, the finish of the players ends the race
+    pub fn add_car(&mut self, name: &str, is_player: bool) {
+        if is_player && !self.players.iter().any(|p| p == name) {
+            self.players.push(name.to_string());
+        }
+        if !self.entries.iter().any(|e| e.name == name) {
+            self.entries.push(RaceEntry { name: name.to_string(), laps: 0, best_lap: None, finish_time: None });
+        }
+    }
+
+    pub fn remove_car(&mut self, name: &str) {
+        self.entries.retain(|e| e.name != name);
+        self.players.retain(|p| p != name);
+    }
+
+    /// Back to the grid with no laps driven, in multiplayer the cars wait there for the server to start
+    pub fn restart(&mut self) {
+        self.set_state(RaceState::Grid);
+        self.race_time = 0.0;
+        self.start_number += 1;
+        self.waiting = self.waits_for_start;
+        for entry in &mut self.entries {
+            *entry = RaceEntry { name: entry.name.clone(), laps: 0, best_lap: None, finish_time: None };
+        }
+    }
+
+    /// The server sent everyone to the grid, the countdown follows the grid time from now
+    pub fn start(&mut self) {
+        self.restart();
+        self.waiting = false;
+    }
+
+    /// Count a lap of the car. Laps only count while racing, and cars that have finished don't drive more laps
+    pub fn lap_completed(&mut self, name: &str, lap_time: f32) {
+        if !matches!(self.state, RaceState::Racing | RaceState::Finished) {
+            return;
+        }
+        let (race_time, lap_count) = (self.race_time, self.lap_count);
+        let entry = match self.entries.iter_mut().find(|e| e.name == name) {
+            Some(e) if e.finish_time.is_none() => e,
+            _ => return,
+        };
+
+        entry.laps += 1;
+        entry.best_lap = Some(entry.best_lap.map_or(lap_time, |best| best.min(lap_time)));
+        match lap_count {
+            Some(count) if entry.laps >= count => {
+                entry.finish_time = Some(race_time);
+                log::info!("{name} finished in {race_time:.3}");
+                if self.state == RaceState::Racing && self.players_finished() {
+                    self.set_state(RaceState::Finished);
+                }
+            }
+            Some(count) if self.players.len() == 1 && self.players[0] == name => {
+                log::info!("Lap {} of {count}", entry.laps + 1)
+            }
+            Some(count) if self.players.iter().any(|p| p == name) => {
+                log::info!("{name}: lap {} of {count}", entry.laps + 1)
+            }
+            _ => (),
+        }
+    }
+
+    pub fn update(&mut self, delta_time: f32) {
+        let previous_time = self.state_time;
+        self.state_time += delta_time;
+        if matches!(self.state, RaceState::Racing | RaceState::Finished) {
+            self.race_time += delta_time;
+        }
+
+        match self.state {
+            RaceState::Grid if !self.waiting && self.state_time >= GRID_TIME => self.set_state(RaceState::Countdown),
+            RaceState::Countdown => {
+                // Call out each second of the countdown once
+                let left = (COUNTDOWN_TIME - self.state_time).ceil();
+                if left > 0.0 && left < (COUNTDOWN_TIME - previous_time).ceil() {
+                    log::info!("{left}");
+                }
+                if self.state_time >= COUNTDOWN_TIME {
+                    log::info!("Go!");
+                    self.set_state(RaceState::Racing);
+                }
+            }
+            RaceState::Finished => {
+                let everyone_finished = self.entries.iter().all(|e| e.finish_time.is_some());
+                if everyone_finished || self.state_time >= FINISH_WAIT_TIME {
+                    self.set_state(RaceState::Results);
+                    self.log_results();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// The cars in the order they finished, then the ones still driving by the laps they have done
+    pub fn results(&self) -> Vec<&RaceEntry> {
+        let mut results = self.entries.iter().collect::<Vec<_>>();
+        results.sort_by(|a, b| match (a.finish_time, b.finish_time) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => b.laps.cmp(&a.laps),
+        });
+        results
+    }
+
+    fn log_results(&self) {
+        log::info!("Results:");
+        for (position, entry) in self.results().iter().enumerate() {
+            let time = entry.finish_time.map_or(format!("{} laps", entry.laps), |t| format!("{t:.3}"));
+            let best_lap = entry.best_lap.map_or(String::new(), |t| format!(", best lap {t:.3}"));
+            log::info!("{}. {} {time}{best_lap}", position + 1, entry.name);
+        }
+    }
+
+    fn players_finished(&self) -> bool {
+        self.entries
+            .iter()
+            .filter(|e| self.players.contains(&e.name))
+            .all(|e| e.finish_time.is_some())
+    }
+
+    fn set_state(&mut self, state: RaceState) {
+        self.state = state;
+        self.state_time = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(race: &mut RaceController, seconds: f32) {
+        for _ in 0..(seconds * 60.0).round() as usize {
+            race.update(1.0 / 60.0);
+        }
+    }
+
+    #[test]
+    fn race_goes_from_the_grid_to_the_results() {
+        let mut race = RaceController::new(Some(2), false);
+        race.add_car("Player", true);
+        race.add_car("Bot 1", false);
+
+        assert_eq!(race.state(), RaceState::Grid);
+        run(&mut race, GRID_TIME + 0.1);
+        assert_eq!(race.state(), RaceState::Countdown);
+        assert!(race.inputs_frozen());
+        // Laps driven before the start don't count
+        race.lap_completed("Player", 1.0);
+
+        run(&mut race, COUNTDOWN_TIME);
+        assert_eq!(race.state(), RaceState::Racing);
+        assert!(!race.inputs_frozen());
+
+        run(&mut race, 30.0);
+        race.lap_completed("Bot 1", 29.0);
+        race.lap_completed("Player", 30.0);
+        run(&mut race, 28.0);
+        race.lap_completed("Player", 28.0);
+        assert_eq!(race.state(), RaceState::Finished);
+
+        run(&mut race, 3.0);
+        race.lap_completed("Bot 1", 32.0);
+        run(&mut race, 0.1);
+        assert_eq!(race.state(), RaceState::Results);
+
+        let results = race.results();
+        assert_eq!(results[0].name, "Player");
+        assert_eq!(results[0].laps, 2);
+        assert_eq!(results[0].best_lap, Some(28.0));
+        assert!(results[1].finish_time.unwrap() > results[0].finish_time.unwrap());
+    }
+
+    #[test]
+    fn results_are_shown_when_the_others_take_too_long() {
+        let mut race = RaceController::new(Some(1), false);
+        race.add_car("Player", true);
+        race.add_car("Bot 1", false);
+        run(&mut race, GRID_TIME + COUNTDOWN_TIME + 1.0);
+
+        race.lap_completed("Player", 40.0);
+        run(&mut race, FINISH_WAIT_TIME + 0.1);
+        assert_eq!(race.state(), RaceState::Results);
+        assert_eq!(race.results()[1].finish_time, None);
+
+        let start_number = race.start_number();
+        race.restart();
+        assert_eq!(race.state(), RaceState::Grid);
+        assert_ne!(race.start_number(), start_number);
+        assert!(race.results().iter().all(|e| e.laps == 0));
+    }
+
+    #[test]
+    fn split_screen_race_waits_for_every_player() {
+        let mut race = RaceController::new(Some(1), false);
+        race.add_car("Player 1", true);
+        race.add_car("Player 2", true);
+        run(&mut race, GRID_TIME + COUNTDOWN_TIME + 1.0);
+
+        race.lap_completed("Player 2", 40.0);
+        assert_eq!(race.state(), RaceState::Racing);
+        run(&mut race, 2.0);
+        race.lap_completed("Player 1", 42.0);
+        assert_eq!(race.state(), RaceState::Finished);
+        run(&mut race, 0.1);
+        assert_eq!(race.state(), RaceState::Results);
+        assert_eq!(race.results()[0].name, "Player 2");
+    }
+
+    #[test]
+    fn multiplayer_race_starts_when_the_server_says_so() {
+        let mut race = RaceController::new(Some(1), true);
+        race.add_car("Player", true);
+        race.add_car("Player 3", false);
+        run(&mut race, GRID_TIME * 2.0);
+        assert_eq!(race.state(), RaceState::Grid);
+        assert!(race.waiting_for_start());
+
+        let start_number = race.start_number();
+        race.start();
+        assert_ne!(race.start_number(), start_number);
+        run(&mut race, GRID_TIME + COUNTDOWN_TIME + 1.0);
+        assert_eq!(race.state(), RaceState::Racing);
+
+        race.lap_completed("Player 3", 38.0);
+        run(&mut race, 2.0);
+        race.lap_completed("Player", 40.0);
+        run(&mut race, 0.1);
+        assert_eq!(race.state(), RaceState::Results);
+        assert_eq!(race.results()[0].name, "Player 3");
+
+        race.restart();
+        run(&mut race, GRID_TIME * 2.0);
+        assert!(race.waiting_for_start());
+    }
+
+    #[test]
+    fn race_without_lap_count_never_ends() {
+        let mut race = RaceController::new(None, false);
+        race.add_car("Player", true);
+        run(&mut race, GRID_TIME + COUNTDOWN_TIME + 1.0);
+        for _ in 0..10 {
+            race.lap_completed("Player", 30.0);
+        }
+        assert_eq!(race.state(), RaceState::Racing);
+    }
+}
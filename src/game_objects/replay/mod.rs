@@ -0,0 +1,2 @@
+pub mod replay_file;
+pub mod replay_player;
@@ -0,0 +1,438 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::anyhow;
+use nalgebra::Vector3;
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    combinator::{map, value},
+    multi::{length_count, length_data},
+    number::complete::le_u8,
+    sequence::{preceded, tuple},
+    IResult,
+};
+
+use crate::network::parser::{parse_float, parse_vector3};
+
+// Seconds between the recorded frames, the cars are interpolated in between
+const SAMPLE_INTERVAL: f32 = 0.05;
+const MAGIC: &[u8; 4] = b"CGRP";
+// Version 2 added the track to the header
+const VERSION: u8 = 2;
+
+// What each record in the file starts with
+const FRAME_RECORD: u8 = 0;
+const EVENT_RECORD: u8 = 1;
+const CAR_RECORD: u8 = 2;
+// Cars are counted with a byte in each frame
+const MAX_CARS: usize = u8::MAX as usize;
+
+/// Something that happened to a car during the race
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReplayEvent {
+    Joined,
+    Left,
+    Sector { checkpoint: u8, time: f32 },
+    Lap { time: f32 },
+    InvalidLap,
+    Respawn,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReplayCarState {
+    // Index into the cars of the replay
+    pub car: u8,
+    pub position: Vector3<f32>,
+    pub angle: f32,
+    pub steering_angle: f32,
+}
+
+/// The cars that were on the track at a time since the start of the recording
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplayFrame {
+    pub time: f32,
+    pub cars: Vec<ReplayCarState>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReplayEventRecord {
+    pub time: f32,
+    pub car: u8,
+    pub event: ReplayEvent,
+}
+
+/// A whole recorded race. After the name of the track the file is a list of records: the name of a car
+/// the first time it shows up, a frame with the positions of all cars, or an event
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Replay {
+    // The name of the track with the hash of its geometry, as lap_records::track_name makes it
+    pub track: String,
+    pub cars: Vec<String>,
+    pub frames: Vec<ReplayFrame>,
+    pub events: Vec<ReplayEventRecord>,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> anyhow::Result<Replay> {
+        let bytes = fs::read(path).map_err(|e| anyhow!("Failed to read replay file {}. {e}", path.display()))?;
+        Replay::parse(&bytes).map_err(|e| anyhow!("Failed to parse replay file {}. {e}", path.display()))
+    }
+
+    pub fn parse(bytes: &[u8]) -> anyhow::Result<Replay> {
+        let (input, version) = parse_version(bytes).map_err(|_| anyhow!("Not a replay file"))?;
+        if version != VERSION {
+            return Err(anyhow!("Unknown replay version {version}"));
+        }
+        let (mut input, track) = parse_name(input).map_err(|_| anyhow!("The file ends in the header"))?;
+
+        let mut replay = Replay { track, ..Replay::default() };
+        while !input.is_empty() {
+            let (rest, record) = parse_record(input)
+                .map_err(|_| anyhow!("Unknown or cut off record at byte {}", bytes.len() - input.len()))?;
+            match record {
+                Record::Frame(frame) => replay.frames.push(frame),
+                Record::Event(event) => replay.events.push(event),
+                Record::Car(name) => replay.cars.push(name),
+            }
+            input = rest;
+        }
+
+        Ok(replay)
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.frames.last().map_or(0.0, |f| f.time)
+    }
+
+    /// Where the cars were at the time, interpolated between the frames around it.
+    /// Cars that joined or left between the frames are where they were in the frame before
+    pub fn cars_at(&self, time: f32) -> Vec<ReplayCarState> {
+        let next = self.frames.partition_point(|f| f.time < time);
+        let (before, after) = match (next.checked_sub(1).map(|i| &self.frames[i]), self.frames.get(next)) {
+            (Some(before), Some(after)) => (before, after),
+            (None, Some(frame)) | (Some(frame), None) => return frame.cars.clone(),
+            (None, None) => return Vec::new(),
+        };
+
+        let t = (time - before.time) / (after.time - before.time).max(f32::EPSILON);
+        before
+            .cars
+            .iter()
+            .map(|a| match after.cars.iter().find(|b| b.car == a.car) {
+                Some(b) => ReplayCarState {
+                    car: a.car,
+                    position: a.position.lerp(&b.position, t),
+                    angle: a.angle + angle_difference(a.angle, b.angle) * t,
+                    steering_angle: a.steering_angle + (b.steering_angle - a.steering_angle) * t,
+                },
+                None => *a,
+            })
+            .collect()
+    }
+
+    /// The events after from up to and including to
+    pub fn events_between(&self, from: f32, to: f32) -> impl Iterator<Item = &ReplayEventRecord> {
+        self.events.iter().filter(move |e| e.time > from && e.time <= to)
+    }
+}
+
+/// How far to turn from one angle to the other, the short way around
+fn angle_difference(from: f32, to: f32) -> f32 {
+    use std::f32::consts::PI;
+    (to - from + PI).rem_euclid(2.0 * PI) - PI
+}
+
+fn parse_version(input: &[u8]) -> IResult<&[u8], u8> {
+    preceded(tag(MAGIC), le_u8)(input)
+}
+
+fn parse_name(input: &[u8]) -> IResult<&[u8], String> {
+    map(length_data(le_u8), |name| String::from_utf8_lossy(name).into_owned())(input)
+}
+
+enum Record {
+    Frame(ReplayFrame),
+    Event(ReplayEventRecord),
+    Car(String),
+}
+
+fn parse_record(input: &[u8]) -> IResult<&[u8], Record> {
+    alt((
+        map(preceded(tag(&[FRAME_RECORD]), parse_frame), Record::Frame),
+        map(preceded(tag(&[EVENT_RECORD]), parse_event_record), Record::Event),
+        map(preceded(tag(&[CAR_RECORD]), parse_name), Record::Car),
+    ))(input)
+}
+
+fn parse_frame(input: &[u8]) -> IResult<&[u8], ReplayFrame> {
+    map(tuple((parse_float, length_count(le_u8, parse_car_state))), |(time, cars)| {
+        ReplayFrame { time, cars }
+    })(input)
+}
+
+fn parse_car_state(input: &[u8]) -> IResult<&[u8], ReplayCarState> {
+    map(
+        tuple((le_u8, parse_vector3, parse_float, parse_float)),
+        |(car, position, angle, steering_angle)| ReplayCarState {
+            car,
+            position: Vector3::new(position.x, position.y, position.z),
+            angle,
+            steering_angle,
+        },
+    )(input)
+}
+
+fn parse_event_record(input: &[u8]) -> IResult<&[u8], ReplayEventRecord> {
+    map(tuple((parse_float, le_u8, parse_event)), |(time, car, event)| {
+        ReplayEventRecord { time, car, event }
+    })(input)
+}
+
+fn parse_event(input: &[u8]) -> IResult<&[u8], ReplayEvent> {
+    alt((
+        value(ReplayEvent::Joined, tag(&[0u8])),
+        value(ReplayEvent::Left, tag(&[1u8])),
+        map(preceded(tag(&[2u8]), tuple((le_u8, parse_float))), |(checkpoint, time)| {
+            ReplayEvent::Sector { checkpoint, time }
+        }),
+        map(preceded(tag(&[3u8]), parse_float), |time| ReplayEvent::Lap { time }),
+        value(ReplayEvent::InvalidLap, tag(&[4u8])),
+        value(ReplayEvent::Respawn, tag(&[5u8])),
+    ))(input)
+}
+
+/// Writes the cars and events to a replay file while the race goes on. The cars record themselves
+/// every update, only one update every SAMPLE_INTERVAL is written
+pub struct ReplayRecorder<W: Write = BufWriter<File>> {
+    writer: W,
+    // The writer failed, nothing more is written
+    failed: bool,
+    time: f32,
+    next_sample_time: f32,
+    car_ids: HashMap<*const usize, u8>,
+    // Cars that left keep their index, so this is the index of the next new car
+    car_count: usize,
+    // Every car index is used, new cars are not recorded
+    full: bool,
+    frame: Vec<ReplayCarState>,
+}
+
+impl ReplayRecorder {
+    pub fn create(path: &Path, track: &str) -> anyhow::Result<ReplayRecorder> {
+        let file = File::create(path).map_err(|e| anyhow!("Failed to create replay file {}. {e}", path.display()))?;
+        ReplayRecorder::new(BufWriter::new(file), track)
+    }
+}
+
+impl<W: Write> ReplayRecorder<W> {
+    /// The track is the name lap_records::track_name gives it, so the replay is only played on the same track
+    pub fn new(mut writer: W, track: &str) -> anyhow::Result<ReplayRecorder<W>> {
+        writer
+            .write_all(MAGIC)
+            .and_then(|_| writer.write_all(&[VERSION]))
+            .and_then(|_| writer.write_all(&name_bytes(track)))
+            .map_err(|e| anyhow!("Failed to write replay. {e}"))?;
+
+        Ok(ReplayRecorder {
+            writer,
+            failed: false,
+            time: 0.0,
+            next_sample_time: 0.0,
+            car_ids: HashMap::new(),
+            car_count: 0,
+            full: false,
+            frame: Vec::new(),
+        })
+    }
+
+    /// Writes the frame the cars recorded in the last update, must be called before the cars are updated
+    pub fn start_frame(&mut self, delta_time: f32) {
+        if !self.frame.is_empty() {
+            let mut bytes = vec![FRAME_RECORD];
+            bytes.extend(self.time.to_le_bytes());
+            bytes.push(self.frame.len() as u8);
+            for state in self.frame.drain(..) {
+                bytes.push(state.car);
+                for value in [state.position.x, state.position.y, state.position.z, state.angle, state.steering_angle] {
+                    bytes.extend(value.to_le_bytes());
+                }
+            }
+            self.write(&bytes);
+            self.next_sample_time = self.time + SAMPLE_INTERVAL;
+        }
+
+        self.time += delta_time;
+    }
+
+    pub fn record_car(&mut self, id: *const usize, name: &str, position: &Vector3<f32>, angle: f32, steering_angle: f32) {
+        if self.time < self.next_sample_time {
+            return;
+        }
+
+        let Some(car) = self.car_index(id, name) else {
+            return;
+        };
+        self.frame.push(ReplayCarState { car, position: *position, angle, steering_angle });
+    }
+
+    pub fn record_event(&mut self, id: *const usize, event: ReplayEvent) {
+        if let Some(&car) = self.car_ids.get(&id) {
+            self.write_event(car, event);
+        }
+    }
+
+    /// The car was removed from the game, a new car with the same id is another car
+    pub fn remove_car(&mut self, id: *const usize) {
+        if let Some(car) = self.car_ids.remove(&id) {
+            self.write_event(car, ReplayEvent::Left);
+        }
+    }
+
+    /// None when the replay already has as many cars as the file can tell apart
+    fn car_index(&mut self, id: *const usize, name: &str) -> Option<u8> {
+        if let Some(&car) = self.car_ids.get(&id) {
+            return Some(car);
+        }
+
+        if self.car_count >= MAX_CARS {
+            if !self.full {
+                log::warn!("The replay has {} cars, cars joining later are not recorded", self.car_count);
+                self.full = true;
+            }
+            return None;
+        }
+        let car = self.car_count as u8;
+        self.car_count += 1;
+        let mut bytes = vec![CAR_RECORD];
+        bytes.extend(name_bytes(name));
+        self.write(&bytes);
+
+        self.car_ids.insert(id, car);
+        self.write_event(car, ReplayEvent::Joined);
+        Some(car)
+    }
+
+    fn write_event(&mut self, car: u8, event: ReplayEvent) {
+        let mut bytes = vec![EVENT_RECORD];
+        bytes.extend(self.time.to_le_bytes());
+        bytes.push(car);
+        match event {
+            ReplayEvent::Joined => bytes.push(0),
+            ReplayEvent::Left => bytes.push(1),
+            ReplayEvent::Sector { checkpoint, time } => {
+                bytes.extend([2, checkpoint]);
+                bytes.extend(time.to_le_bytes());
+            }
+            ReplayEvent::Lap { time } => {
+                bytes.push(3);
+                bytes.extend(time.to_le_bytes());
+            }
+            ReplayEvent::InvalidLap => bytes.push(4),
+            ReplayEvent::Respawn => bytes.push(5),
+        }
+        self.write(&bytes);
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        if self.failed {
+            return;
+        }
+        if let Err(e) = self.writer.write_all(bytes) {
+            log::error!("Failed to write replay, recording stopped. {e}");
+            self.failed = true;
+        }
+    }
+
+    pub fn into_writer(mut self) -> W {
+        self.start_frame(0.0);
+        self.writer
+    }
+}
+
+/// A name with its length in front, cut to the 255 bytes a length byte can count
+fn name_bytes(name: &str) -> Vec<u8> {
+    let mut end = name.len().min(u8::MAX as usize);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    let mut bytes = vec![end as u8];
+    bytes.extend(&name.as_bytes()[..end]);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorded_race_plays_back() {
+        let ids = [0usize; 2];
+        let (player, other) = (&ids[0] as *const usize, &ids[1] as *const usize);
+        let mut recorder = ReplayRecorder::new(Vec::new(), "default-0123abcd").unwrap();
+
+        for tick in 0..60 {
+            recorder.start_frame(1.0 / 60.0);
+            let z = tick as f32;
+            recorder.record_car(player, "Player", &Vector3::new(0.0, 30.0, z), 0.0, 0.1);
+            if tick < 30 {
+                recorder.record_car(other, "Player 2", &Vector3::new(5.0, 30.0, -z), 3.0, 0.0);
+            }
+            if tick == 30 {
+                recorder.remove_car(other);
+                recorder.record_event(player, ReplayEvent::Lap { time: 61.5 });
+            }
+        }
+
+        let bytes = recorder.into_writer();
+        let replay = Replay::parse(&bytes).unwrap();
+        assert_eq!(replay.track, "default-0123abcd");
+        assert_eq!(replay.cars, ["Player", "Player 2"]);
+        // Only every third tick is sampled
+        assert!(replay.frames.len() < 30, "{} frames", replay.frames.len());
+        assert!(replay.events.iter().any(|e| e.car == 1 && e.event == ReplayEvent::Left));
+        assert_eq!(replay.events_between(0.4, 0.6).map(|e| e.event).collect::<Vec<_>>(), [ReplayEvent::Left, ReplayEvent::Lap { time: 61.5 }]);
+
+        // The player moves 60 units a second, half way between two frames is interpolated
+        let frames = &replay.frames;
+        let time = (frames[2].time + frames[3].time) / 2.0;
+        let cars = replay.cars_at(time);
+        assert_eq!(cars.len(), 2);
+        assert!((cars[0].position.z - (time - 1.0 / 60.0) * 60.0).abs() < 0.01, "z {} at {time}", cars[0].position.z);
+        assert_eq!(replay.cars_at(replay.duration()).len(), 1);
+    }
+
+    #[test]
+    fn cut_off_file_is_an_error() {
+        let mut recorder = ReplayRecorder::new(Vec::new(), "default-0123abcd").unwrap();
+        recorder.start_frame(0.1);
+        recorder.record_car(&0usize as *const usize, "Player", &Vector3::zeros(), 0.0, 0.0);
+        let bytes = recorder.into_writer();
+
+        assert!(Replay::parse(&bytes).is_ok());
+        assert!(Replay::parse(&bytes[..bytes.len() - 2]).is_err());
+        assert!(Replay::parse(b"not a replay").is_err());
+    }
+
+    #[test]
+    fn cars_past_the_last_index_are_not_recorded() {
+        let ids = [0usize; 300];
+        let mut recorder = ReplayRecorder::new(Vec::new(), "default-0123abcd").unwrap();
+        recorder.start_frame(0.1);
+        for (i, id) in ids.iter().enumerate() {
+            recorder.record_car(id, &format!("Bot {i}"), &Vector3::zeros(), 0.0, 0.0);
+        }
+        let bytes = recorder.into_writer();
+
+        let replay = Replay::parse(&bytes).unwrap();
+        assert_eq!(replay.cars.len(), 255);
+        assert_eq!(replay.cars.last().unwrap(), "Bot 254");
+        let cars = &replay.frames[0].cars;
+        assert_eq!(cars.len(), 255);
+        assert!(cars.iter().enumerate().all(|(i, state)| state.car as usize == i));
+    }
+}
@@ -0,0 +1,257 @@
+use std::rc::Rc;
+
+use glow::Context;
+use nalgebra::Vector3;
+use sdl2::{event::Event, keyboard::Keycode};
+
+use crate::{
+    core::{game::Game, game_object::GameObject},
+    game_objects::{
+        cars::car::Car,
+        track::{track::RespawnPoint, track_segment::TRACK_WIDTH},
+    },
+    objects::mesh_model::MeshModel,
+};
+
+use super::replay_file::{Replay, ReplayEvent};
+
+// Seconds the arrow keys jump backwards and forwards
+const SCRUB_STEP: f32 = 5.0;
+const SPEEDS: [f32; 6] = [0.125, 0.25, 0.5, 1.0, 2.0, 4.0];
+const NORMAL_SPEED: usize = 3;
+// Distance along the track between the trackside cameras, and how far they are from the centre line
+const TV_CAMERA_SPACING: f32 = 120.0;
+const TV_CAMERA_SIDE_OFFSET: f32 = TRACK_WIDTH;
+const TV_CAMERA_HEIGHT: f32 = 8.0;
+// Only cut to another camera when it is this much closer to the car than the current one,
+// so the cameras don't flicker between two that are about as close
+const TV_CAMERA_CUT_RATIO: f32 = 0.7;
+
+#[derive(Clone, Copy, PartialEq)]
+enum ReplayCamera {
+    Chase,
+    Trackside,
+}
+
+/// Plays a recorded race back. Space pauses, the left and right arrows jump back and forward,
+/// up and down change the speed, Tab follows the next car, C switches between the chase camera
+/// and the trackside cameras and Home starts over
+pub struct ReplayPlayer<'a> {
+    replay: Replay,
+    cars: Vec<Car<'a>>,
+    // Cars that are in the race at the current time
+    visible: Vec<bool>,
+    time: f32,
+    paused: bool,
+    speed: usize,
+    followed_car: usize,
+    camera: ReplayCamera,
+    tv_cameras: Vec<Vector3<f32>>,
+    tv_camera: Option<usize>,
+}
+
+impl<'a> ReplayPlayer<'a> {
+    pub fn new(
+        replay: Replay,
+        car_model: Rc<MeshModel<'a>>,
+        wheel_model: Rc<MeshModel<'a>>,
+        gl: &'a Context,
+        game: &Game,
+    ) -> ReplayPlayer<'a> {
+        let cars = replay
+            .cars
+            .iter()
+            .map(|_| Car::new(false, car_model.clone(), wheel_model.clone(), gl, game))
+            .collect::<Vec<_>>();
+
+        log::info!(
+            "Playing a replay of {:.0} seconds with {} cars. Space pauses, the arrows scrub and change the speed, \
+Tab follows the next car and C switches to the trackside cameras",
+            replay.duration(),
+            cars.len()
+        );
+
+        ReplayPlayer {
+            visible: vec![false; cars.len()],
+            cars,
+            replay,
+            time: 0.0,
+            paused: false,
+            speed: NORMAL_SPEED,
+            followed_car: 0,
+            camera: ReplayCamera::Chase,
+            tv_cameras: tv_camera_positions(&game.respawn_points.borrow()),
+            tv_camera: None,
+        }
+    }
+
+    fn scrub(&mut self, seconds: f32) {
+        self.time = (self.time + seconds).clamp(0.0, self.replay.duration());
+    }
+
+    fn follow_next_car(&mut self) {
+        let count = self.cars.len();
+        if let Some(next) = (1..=count).map(|i| (self.followed_car + i) % count).find(|i| self.visible[*i]) {
+            self.followed_car = next;
+            self.tv_camera = None;
+            log::info!("Following {}", self.replay.cars[next]);
+        }
+    }
+
+    fn log_event(&self, car: usize, event: &ReplayEvent) {
+        let name = self.replay.cars.get(car).map_or("?", String::as_str);
+        match event {
+            ReplayEvent::Joined => log::info!("{name} joined"),
+            ReplayEvent::Left => log::info!("{name} left"),
+            ReplayEvent::Sector { checkpoint, time } => log::info!("{name}: sector {checkpoint} {time:.3}"),
+            ReplayEvent::Lap { time } => log::info!("{name}: lap {time:.3}"),
+            ReplayEvent::InvalidLap => log::info!("{name}: lap not counted"),
+            ReplayEvent::Respawn => log::info!("{name} was put back on the track"),
+        }
+    }
+
+    fn update_camera(&mut self, game: &Game) {
+        let car = match self.cars.get(self.followed_car) {
+            Some(c) if self.visible[self.followed_car] => c,
+            _ => return,
+        };
+        let target = *car.position();
+
+        let (eye, center) = match self.camera {
+            ReplayCamera::Chase => {
+                let (ang_sin, ang_cos) = car.angle().sin_cos();
+                let eye = target + Vector3::new(ang_sin * -20.0, 6.0, ang_cos * -20.0);
+                (eye, target + Vector3::new(0.0, 2.0, 0.0))
+            }
+            ReplayCamera::Trackside => {
+                self.tv_camera = pick_tv_camera(&self.tv_cameras, &target, self.tv_camera);
+                match self.tv_camera {
+                    Some(camera) => (self.tv_cameras[camera], target),
+                    None => return,
+                }
+            }
+        };
+
+        game.view_matrix.borrow_mut().look(eye, center, Vector3::new(0.0, 1.0, 0.0));
+    }
+}
+
+impl<'a> GameObject<'a> for ReplayPlayer<'a> {
+    fn on_event(&mut self, _game: &Game, event: &Event) {
+        let key = match event {
+            Event::KeyDown { keycode: Some(key), .. } => *key,
+            _ => return,
+        };
+
+        match key {
+            Keycode::Space => self.paused = !self.paused,
+            Keycode::Left => self.scrub(-SCRUB_STEP),
+            Keycode::Right => self.scrub(SCRUB_STEP),
+            Keycode::Up => self.speed = (self.speed + 1).min(SPEEDS.len() - 1),
+            Keycode::Down => self.speed = self.speed.saturating_sub(1),
+            Keycode::Tab => self.follow_next_car(),
+            Keycode::Home => self.time = 0.0,
+            Keycode::C => {
+                self.camera = match self.camera {
+                    ReplayCamera::Chase => ReplayCamera::Trackside,
+                    ReplayCamera::Trackside => ReplayCamera::Chase,
+                };
+                self.tv_camera = None;
+            }
+            _ => return,
+        }
+
+        if matches!(key, Keycode::Up | Keycode::Down) {
+            log::info!("Replay speed {}x", SPEEDS[self.speed]);
+        }
+    }
+
+    fn update(&mut self, game: &Game, _gl: &'a Context) {
+        let previous_time = self.time;
+        if !self.paused {
+            self.scrub(game.delta_time * SPEEDS[self.speed]);
+            for record in self.replay.events_between(previous_time, self.time) {
+                self.log_event(record.car as usize, &record.event);
+            }
+        }
+
+        self.visible.fill(false);
+        for state in self.replay.cars_at(self.time) {
+            let index = state.car as usize;
+            if let Some(car) = self.cars.get_mut(index) {
+                car.set_position(state.position);
+                car.set_angle(state.angle);
+                car.set_steering_angle(state.steering_angle);
+                self.visible[index] = true;
+            }
+        }
+
+        if !self.visible.get(self.followed_car).copied().unwrap_or(false) {
+            self.follow_next_car();
+        }
+        self.update_camera(game);
+    }
+
+    fn display(&self, game: &Game, gl: &'a Context) {
+        for (car, visible) in self.cars.iter().zip(&self.visible) {
+            if *visible {
+                car.display(game, gl);
+            }
+        }
+    }
+}
+
+/// Cameras beside the track every TV_CAMERA_SPACING along it, on alternating sides
+pub fn tv_camera_positions(respawn_points: &[RespawnPoint]) -> Vec<Vector3<f32>> {
+    let mut cameras = Vec::new();
+    let mut distance = TV_CAMERA_SPACING;
+    for (i, point) in respawn_points.iter().enumerate() {
+        if i > 0 {
+            distance += (point.position - respawn_points[i - 1].position).norm();
+        }
+        if distance < TV_CAMERA_SPACING {
+            continue;
+        }
+        distance = 0.0;
+
+        let left = Vector3::new(point.angle.cos(), 0.0, -point.angle.sin());
+        let side = if cameras.len() % 2 == 0 { 1.0 } else { -1.0 };
+        cameras.push(point.position + left * side * TV_CAMERA_SIDE_OFFSET + Vector3::new(0.0, TV_CAMERA_HEIGHT, 0.0));
+    }
+    cameras
+}
+
+/// The camera closest to the target, keeping the current one until another is clearly closer
+pub fn pick_tv_camera(cameras: &[Vector3<f32>], target: &Vector3<f32>, current: Option<usize>) -> Option<usize> {
+    let distance = |i: usize| (cameras[i] - target).norm();
+    let closest = (0..cameras.len()).min_by(|a, b| distance(*a).total_cmp(&distance(*b)))?;
+
+    match current {
+        Some(current) if current < cameras.len() && distance(closest) > distance(current) * TV_CAMERA_CUT_RATIO => {
+            Some(current)
+        }
+        _ => Some(closest),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tv_cameras_cut_when_the_car_passes() {
+        let straight = (0..=100)
+            .map(|i| RespawnPoint { position: Vector3::new(0.0, 30.0, 5.0 * i as f32), angle: 0.0 })
+            .collect::<Vec<_>>();
+        let cameras = tv_camera_positions(&straight);
+        assert_eq!(cameras.len(), 5);
+        // Alternating sides of the road
+        assert!(cameras[0].x > 0.0 && cameras[1].x < 0.0);
+
+        let camera = pick_tv_camera(&cameras, &Vector3::new(0.0, 30.0, 10.0), None);
+        assert_eq!(camera, Some(0));
+        // Half way to the next camera the current one is kept
+        assert_eq!(pick_tv_camera(&cameras, &Vector3::new(0.0, 30.0, 60.0), camera), Some(0));
+        assert_eq!(pick_tv_camera(&cameras, &Vector3::new(0.0, 30.0, 110.0), camera), Some(1));
+    }
+}
@@ -0,0 +1,473 @@
+use nalgebra::{Vector2, Vector3};
+
+use crate::utils::segments_intersect;
+
+use super::{
+    track::RespawnPoint,
+    track_file::{track_position, Checkpoint},
+    track_segment::TRACK_ELEVATION,
+};
+
+// The car has to drive against the track for this long before it counts as going the wrong way
+const WRONG_WAY_TIME: f32 = 1.5;
+// Slower than this the direction of the car doesn't matter, it may be turning around
+const WRONG_WAY_MIN_SPEED: f32 = 3.0;
+// A car crosses a line checkpoint this far above or below the height of the line, enough for a car in the air
+const LINE_HEIGHT_RANGE: f32 = 8.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Crossing {
+    Forward,
+    Backward,
+}
+
+impl Checkpoint {
+    /// Middle of the checkpoint in world coordinates
+    pub fn position(&self) -> Vector3<f32> {
+        match *self {
+            Checkpoint::Line { start, end, height } => {
+                track_position(((start.0 + end.0) / 2.0, height, (start.1 + end.1) / 2.0))
+            }
+            Checkpoint::Volume { centre, .. } => track_position(centre),
+        }
+    }
+
+    /// If the car moved through the checkpoint between two ticks, and in which direction
+    pub fn crossing(&self, from: &Vector3<f32>, to: &Vector3<f32>) -> Option<Crossing> {
+        self.crossing_at(from, to).map(|(crossing, _)| crossing)
+    }
+
+    /// The crossing and how far along the move from one tick to the next it happened, from 0 to 1
+    pub fn crossing_at(&self, from: &Vector3<f32>, to: &Vector3<f32>) -> Option<(Crossing, f32)> {
+        match *self {
+            Checkpoint::Line { start, end, height } => {
+                let (start, end) = (Vector2::new(start.0, start.1), Vector2::new(end.0, end.1));
+                if !segments_intersect(&from.xz(), &to.xz(), &start, &end) {
+                    return None;
+                }
+
+                // How high the car was where it crossed the line
+                let line = end - start;
+                let side = |p: &Vector3<f32>| line.perp(&(p.xz() - start));
+                let t = side(from) / (side(from) - side(to));
+                let y = from.y + (to.y - from.y) * t;
+                if (y - (height + TRACK_ELEVATION)).abs() > LINE_HEIGHT_RANGE {
+                    return None;
+                }
+
+                // Moving from the right of the line to the left of it, seen from start to end
+                let forward = Vector2::new(-line.y, line.x);
+                let crossing = if (to.xz() - from.xz()).dot(&forward) >= 0.0 {
+                    Crossing::Forward
+                } else {
+                    Crossing::Backward
+                };
+                Some((crossing, t))
+            }
+            Checkpoint::Volume { centre, size, rotation } => {
+                let (from, to) = (
+                    volume_local(&track_position(centre), rotation, from),
+                    volume_local(&track_position(centre), rotation, to),
+                );
+                let half_size = Vector3::new(size.0, size.1, size.2) / 2.0;
+                let inside = |p: &Vector3<f32>| (0..3).all(|i| p[i].abs() <= half_size[i]);
+
+                // Only entering the box counts, so a car standing in it doesn't cross it every tick
+                if inside(&from) {
+                    return None;
+                }
+                let t = segment_hits_box(&from, &to, &half_size)?;
+
+                Some((if to.z >= from.z { Crossing::Forward } else { Crossing::Backward }, t))
+            }
+        }
+    }
+}
+
+/// Something that happened at a checkpoint, times are in seconds
+#[derive(Clone, Debug, PartialEq)]
+pub enum CheckpointEvent {
+    // The car crossed the finish line for the first time, the first lap starts
+    LapStarted,
+    // The sector before the checkpoint is done
+    Sector { checkpoint: usize, time: f32 },
+    Lap { time: f32, sectors: Vec<f32> },
+    // The car crossed the finish line without driving through all checkpoints
+    InvalidLap,
+    // The car drove through a later checkpoint than the next one, the checkpoints in between were skipped
+    Missed { checkpoint: usize },
+    // The car drove backwards through a checkpoint
+    WrongWay { checkpoint: usize },
+}
+
+/// Keeps track of the checkpoints a car has driven through, and the lap and sector times
+pub struct LapTracker {
+    // Index of the checkpoint the car has to cross next, the finish line is checkpoint 0
+    next_checkpoint: usize,
+    timing: bool,
+    lap_time: f32,
+    sector_start: f32,
+    sectors: Vec<f32>,
+    // A checkpoint was missed, the lap won't count
+    lap_invalid: bool,
+    wrong_way_time: f32,
+}
+
+impl Default for LapTracker {
+    fn default() -> LapTracker {
+        LapTracker::new()
+    }
+}
+
+impl LapTracker {
+    pub fn new() -> LapTracker {
+        LapTracker {
+            // The cars start behind the finish line
+            next_checkpoint: 1,
+            timing: false,
+            lap_time: 0.0,
+            sector_start: 0.0,
+            sectors: Vec::new(),
+            lap_invalid: false,
+            wrong_way_time: 0.0,
+        }
+    }
+
+    /// Start over from the start grid
+    pub fn reset(&mut self) {
+        *self = LapTracker::new();
+    }
+
+    pub fn next_checkpoint(&self) -> usize {
+        self.next_checkpoint
+    }
+
+    /// The checkpoint the car drove through last, None before it crossed the finish line the first time
+    pub fn last_checkpoint(&self, count: usize) -> Option<usize> {
+        (self.timing && count > 0).then(|| (self.next_checkpoint + count - 1) % count)
+    }
+
+    /// Time of the current lap, None before the car has crossed the finish line the first time
+    pub fn lap_time(&self) -> Option<f32> {
+        self.timing.then_some(self.lap_time)
+    }
+
+    /// Times of the sectors driven so far this lap
+    pub fn sectors(&self) -> &[f32] {
+        &self.sectors
+    }
+
+    pub fn is_lap_invalid(&self) -> bool {
+        self.lap_invalid
+    }
+
+    /// Check the checkpoints against the movement of the car from one tick to the next. The times are
+    /// taken where along the move the car crossed, so they don't depend on the frame rate
+    pub fn update(
+        &mut self,
+        checkpoints: &[Checkpoint],
+        from: &Vector3<f32>,
+        to: &Vector3<f32>,
+        delta_time: f32,
+    ) -> Vec<CheckpointEvent> {
+        let mut events = Vec::new();
+        let count = checkpoints.len();
+        if count > 0 {
+            self.next_checkpoint %= count;
+        }
+
+        let mut crossings = checkpoints
+            .iter()
+            .enumerate()
+            .filter_map(|(index, checkpoint)| checkpoint.crossing_at(from, to).map(|(c, t)| (index, c, t)))
+            .collect::<Vec<_>>();
+        crossings.sort_by(|a, b| a.2.total_cmp(&b.2));
+        for (index, crossing, t) in crossings {
+            match crossing {
+                Crossing::Forward => self.checkpoint_crossed(index, count, t * delta_time, &mut events),
+                Crossing::Backward => events.push(CheckpointEvent::WrongWay { checkpoint: index }),
+            }
+        }
+
+        if self.timing {
+            self.lap_time += delta_time;
+        }
+        events
+    }
+
+    /// The checkpoint was crossed the offset in seconds into the tick
+    fn checkpoint_crossed(&mut self, index: usize, count: usize, offset: f32, events: &mut Vec<CheckpointEvent>) {
+        let last_checkpoint = (self.next_checkpoint + count - 1) % count;
+        let now = self.lap_time + offset;
+
+        if index == 0 && !self.timing {
+            self.timing = true;
+            self.start_lap(offset);
+            events.push(CheckpointEvent::LapStarted);
+            if self.next_checkpoint == 0 {
+                self.next_checkpoint = 1 % count;
+            }
+            return;
+        }
+
+        // Driving through the same checkpoint again after turning around twice
+        if index == last_checkpoint && index != self.next_checkpoint {
+            return;
+        }
+
+        if index != self.next_checkpoint {
+            events.push(CheckpointEvent::Missed { checkpoint: self.next_checkpoint });
+            self.lap_invalid = true;
+        }
+
+        if self.timing && !self.lap_invalid {
+            let time = now - self.sector_start;
+            self.sectors.push(time);
+            self.sector_start = now;
+            events.push(CheckpointEvent::Sector { checkpoint: index, time });
+        }
+
+        if index == 0 && self.timing {
+            if self.lap_invalid {
+                events.push(CheckpointEvent::InvalidLap);
+            } else {
+                events.push(CheckpointEvent::Lap { time: now, sectors: self.sectors.clone() });
+            }
+            self.start_lap(offset);
+        }
+
+        self.next_checkpoint = (index + 1) % count;
+    }
+
+    /// The lap started the offset in seconds into the tick, the rest of the tick is part of it
+    fn start_lap(&mut self, offset: f32) {
+        self.lap_time = -offset;
+        self.sector_start = 0.0;
+        self.sectors.clear();
+        self.lap_invalid = false;
+    }
+
+    /// Compare the direction the car is moving in with the direction of the track around it.
+    /// Returns true once, when the car has been going the wrong way for a while
+    pub fn check_wrong_way(
+        &mut self,
+        respawn_points: &[RespawnPoint],
+        position: &Vector3<f32>,
+        velocity: &Vector3<f32>,
+        delta_time: f32,
+    ) -> bool {
+        let closest = respawn_points.iter().min_by(|a, b| {
+            let a_dist = (position - a.position).norm();
+            let b_dist = (position - b.position).norm();
+            a_dist.total_cmp(&b_dist)
+        });
+        let wrong_way = match closest {
+            Some(point) if velocity.xz().norm() > WRONG_WAY_MIN_SPEED => {
+                let track_direction = Vector2::new(point.angle.sin(), point.angle.cos());
+                velocity.xz().dot(&track_direction) < 0.0
+            }
+            _ => false,
+        };
+
+        let was_wrong_way = self.wrong_way_time >= WRONG_WAY_TIME;
+        self.wrong_way_time = if wrong_way { self.wrong_way_time + delta_time } else { 0.0 };
+        !was_wrong_way && self.wrong_way_time >= WRONG_WAY_TIME
+    }
+}
+
+/// A point in the coordinates of a volume checkpoint
+fn volume_local(centre: &Vector3<f32>, rotation: f32, point: &Vector3<f32>) -> Vector3<f32> {
+    let (rot_sin, rot_cos) = rotation.to_radians().sin_cos();
+    let p = point - centre;
+    Vector3::new(p.x * rot_cos - p.z * rot_sin, p.y, p.x * rot_sin + p.z * rot_cos)
+}
+
+/// Slab test of a line segment against a box centred on the origin, returns how far along the segment it enters
+fn segment_hits_box(from: &Vector3<f32>, to: &Vector3<f32>, half_size: &Vector3<f32>) -> Option<f32> {
+    let direction = to - from;
+    let (mut t_min, mut t_max) = (0.0f32, 1.0f32);
+
+    for i in 0..3 {
+        if direction[i].abs() < f32::EPSILON {
+            if from[i].abs() > half_size[i] {
+                return None;
+            }
+            continue;
+        }
+
+        let t1 = (-half_size[i] - from[i]) / direction[i];
+        let t2 = (half_size[i] - from[i]) / direction[i];
+        t_min = t_min.max(t1.min(t2));
+        t_max = t_max.min(t1.max(t2));
+        if t_min > t_max {
+            return None;
+        }
+    }
+
+    Some(t_min)
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use super::*;
+
+    const DELTA_TIME: f32 = 0.5;
+
+    // Gates along the z axis, the car drives back to the start beside them at x = 50
+    fn checkpoints() -> Vec<Checkpoint> {
+        vec![
+            Checkpoint::Line { start: (-10.0, 0.0), end: (10.0, 0.0), height: 0.0 },
+            Checkpoint::Line { start: (-10.0, 100.0), end: (10.0, 100.0), height: 0.0 },
+            Checkpoint::Volume { centre: (0.0, 0.0, 200.0), size: (20.0, 10.0, 4.0), rotation: 0.0 },
+        ]
+    }
+
+    fn at(z: f32) -> Vector3<f32> {
+        Vector3::new(0.0, TRACK_ELEVATION + 1.0, z)
+    }
+
+    fn beside(z: f32) -> Vector3<f32> {
+        Vector3::new(50.0, TRACK_ELEVATION + 1.0, z)
+    }
+
+    /// Move the car along a path, one point per tick
+    fn drive(tracker: &mut LapTracker, path: &[Vector3<f32>]) -> Vec<CheckpointEvent> {
+        path.windows(2)
+            .flat_map(|p| tracker.update(&checkpoints(), &p[0], &p[1], DELTA_TIME))
+            .collect()
+    }
+
+    #[test]
+    fn line_and_volume_crossings() {
+        let line = Checkpoint::Line { start: (-10.0, 0.0), end: (10.0, 0.0), height: 0.0 };
+        assert_eq!(line.crossing(&at(-1.0), &at(1.0)), Some(Crossing::Forward));
+        assert_eq!(line.crossing(&at(1.0), &at(-1.0)), Some(Crossing::Backward));
+        assert_eq!(line.crossing(&at(1.0), &at(2.0)), None);
+        // On a road crossing over the line
+        let bridge = Vector3::new(0.0, 15.0, 0.0);
+        assert_eq!(line.crossing(&(at(-1.0) + bridge), &(at(1.0) + bridge)), None);
+        let raised = Checkpoint::Line { start: (-10.0, 0.0), end: (10.0, 0.0), height: 15.0 };
+        assert_eq!(raised.crossing(&(at(-1.0) + bridge), &(at(1.0) + bridge)), Some(Crossing::Forward));
+        assert_eq!(raised.crossing(&at(-1.0), &at(1.0)), None);
+
+        let volume = checkpoints()[2];
+        // Fast enough to jump over the box in one tick, the swept position still hits it
+        assert_eq!(volume.crossing(&at(190.0), &at(210.0)), Some(Crossing::Forward));
+        assert_eq!(volume.crossing(&at(210.0), &at(199.0)), Some(Crossing::Backward));
+        assert_eq!(volume.crossing(&at(199.0), &at(201.0)), None);
+        let above = Vector3::new(0.0, TRACK_ELEVATION + 20.0, 0.0);
+        assert_eq!(volume.crossing(&(above + at(190.0)), &(above + at(210.0))), None);
+    }
+
+    #[test]
+    fn sector_and_lap_times() {
+        let mut tracker = LapTracker::new();
+        // Every gate is crossed half way through a tick
+        let path = [at(-5.0), at(5.0), at(195.0), at(201.0), beside(201.0), beside(-5.0), at(-5.0), at(5.0)];
+        let events = drive(&mut tracker, &path);
+
+        assert_eq!(
+            events,
+            vec![
+                CheckpointEvent::LapStarted,
+                CheckpointEvent::Sector { checkpoint: 1, time: 0.5 },
+                CheckpointEvent::Sector { checkpoint: 2, time: 0.5 },
+                CheckpointEvent::Sector { checkpoint: 0, time: 2.0 },
+                CheckpointEvent::Lap { time: 3.0, sectors: vec![0.5, 0.5, 2.0] },
+            ]
+        );
+        // The new lap started half way through the last tick
+        assert_eq!(tracker.lap_time(), Some(0.25));
+    }
+
+    #[test]
+    fn lap_times_dont_depend_on_the_frame_rate() {
+        // The car drives 30 units a second from z = -5 around to the finish line 50 units later
+        let lap = |ticks_per_second: f32| {
+            let mut tracker = LapTracker::new();
+            let checkpoints = [checkpoints()[0]];
+            let delta_time = 1.0 / ticks_per_second;
+            let position = |tick: usize| {
+                let distance = tick as f32 * delta_time * 30.0 - 5.0;
+                if distance < 20.0 { at(distance) } else { at(distance - 50.0) }
+            };
+            (1..).find_map(|tick| {
+                tracker.update(&checkpoints, &position(tick - 1), &position(tick), delta_time).into_iter().find_map(|e| match e {
+                    CheckpointEvent::Lap { time, .. } => Some(time),
+                    _ => None,
+                })
+            })
+            .unwrap()
+        };
+
+        for ticks_per_second in [30.0, 60.0, 144.0] {
+            let time = lap(ticks_per_second);
+            assert!((time - 50.0 / 30.0).abs() < 0.001, "{time} at {ticks_per_second} ticks a second");
+        }
+    }
+
+    #[test]
+    fn missed_checkpoint_invalidates_the_lap() {
+        let mut tracker = LapTracker::new();
+        // Drive around checkpoint 1
+        let path = [
+            at(-5.0),
+            at(5.0),
+            at(50.0),
+            beside(50.0),
+            beside(150.0),
+            at(150.0),
+            at(250.0),
+            beside(250.0),
+            beside(-5.0),
+            at(-5.0),
+            at(5.0),
+        ];
+        let events = drive(&mut tracker, &path);
+
+        assert_eq!(
+            events,
+            vec![
+                CheckpointEvent::LapStarted,
+                CheckpointEvent::Missed { checkpoint: 1 },
+                CheckpointEvent::InvalidLap,
+            ]
+        );
+        assert!(!tracker.is_lap_invalid());
+        assert_eq!(tracker.next_checkpoint(), 1);
+    }
+
+    #[test]
+    fn driving_backwards_through_a_checkpoint() {
+        let mut tracker = LapTracker::new();
+        let events = drive(&mut tracker, &[at(-5.0), at(5.0), at(195.0), at(50.0), at(195.0)]);
+
+        assert_eq!(
+            events,
+            vec![
+                CheckpointEvent::LapStarted,
+                CheckpointEvent::Sector { checkpoint: 1, time: 0.5 },
+                CheckpointEvent::WrongWay { checkpoint: 1 },
+            ]
+        );
+        assert_eq!(tracker.next_checkpoint(), 2);
+    }
+
+    #[test]
+    fn wrong_way_against_the_track() {
+        let points = [
+            RespawnPoint { position: at(0.0), angle: 0.0 },
+            RespawnPoint { position: at(50.0), angle: 0.0 },
+        ];
+        let mut tracker = LapTracker::new();
+        let backwards = Vector3::new(0.0, 0.0, -10.0);
+
+        assert!(!tracker.check_wrong_way(&points, &at(10.0), &backwards, 1.0));
+        assert!(tracker.check_wrong_way(&points, &at(10.0), &backwards, 1.0));
+        // Only reported once
+        assert!(!tracker.check_wrong_way(&points, &at(10.0), &backwards, 1.0));
+        assert!(!tracker.check_wrong_way(&points, &at(10.0), &-backwards, 1.0));
+    }
+}
@@ -1,6 +1,16 @@
+pub mod checkpoints;
+pub mod racing_line;
 pub mod track;
+pub mod track_builder;
+pub mod track_export;
+pub mod track_file;
+pub mod track_generator;
+pub mod track_kerb;
 pub mod track_right_corner_segment;
 pub mod track_segment;
 pub mod track_side;
+pub mod track_spline;
+pub mod track_spline_segment;
 pub mod track_straight_segment;
-pub mod track_u_corner_segment;
\ No newline at end of file
+pub mod track_u_corner_segment;
+pub mod track_validation;
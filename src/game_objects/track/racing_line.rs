@@ -0,0 +1,211 @@
+use nalgebra::Vector3;
+
+use super::track_segment::TRACK_WIDTH;
+
+// Distance between the points of the racing line
+const POINT_SPACING: f32 = 4.0;
+// The line is smoothed with fewer points first, so the smoothing reaches further along the track
+const SMOOTHING_SPACING: f32 = 12.0;
+// How often the line is made smoother, and how far each pass moves the points towards the smoother line
+const SMOOTHING_PASSES: usize = 4000;
+const SMOOTHING_STEP: f32 = 0.5;
+// How far the line stays away from the edges of the road
+const EDGE_MARGIN: f32 = 3.5;
+// Curvature is measured between points this many points apart, so small bumps in the line don't count
+const CURVATURE_SPAN: usize = 3;
+// Points searched behind and ahead of the last closest point when looking for the closest point
+const SEARCH_BEHIND: usize = 5;
+const SEARCH_AHEAD: usize = 15;
+
+#[derive(Clone, Copy, Debug)]
+pub struct RacingLinePoint {
+    pub position: Vector3<f32>,
+    // One over the radius of the turn at the point
+    pub curvature: f32,
+}
+
+/// A closed line around the track that cuts the corners as much as the road allows
+pub struct RacingLine {
+    points: Vec<RacingLinePoint>,
+}
+
+impl RacingLine {
+    /// The centre line has to go around the track in the direction it is driven, like the respawn points do
+    pub fn new(centre_line: &[Vector3<f32>]) -> RacingLine {
+        let centre_line = resample(centre_line, SMOOTHING_SPACING);
+        let max_offset = TRACK_WIDTH / 2.0 - EDGE_MARGIN;
+        let count = centre_line.len() as isize;
+
+        // Move every point towards where it bends the line the least while keeping it on the road,
+        // which makes the corners as wide as the road allows
+        let mut line = centre_line.clone();
+        for _ in 0..SMOOTHING_PASSES {
+            line = (0..count)
+                .map(|i| {
+                    let p = |offset: isize| line[(i + offset).rem_euclid(count) as usize];
+                    let smoothest = (4.0 * (p(-1) + p(1)) - p(-2) - p(2)) / 6.0;
+                    let moved = p(0) + (smoothest - p(0)) * SMOOTHING_STEP;
+                    let centre = centre_line[i as usize];
+
+                    let offset = (moved - centre).xz();
+                    let offset = if offset.norm() > max_offset { offset.normalize() * max_offset } else { offset };
+                    Vector3::new(centre.x + offset.x, centre.y, centre.z + offset.y)
+                })
+                .collect();
+        }
+
+        let line = resample(&line, POINT_SPACING);
+        let count = line.len();
+
+        let span = CURVATURE_SPAN.min(count / 3).max(1);
+        let points = (0..count)
+            .map(|i| RacingLinePoint {
+                position: line[i],
+                curvature: curvature(&line[(i + count - span) % count], &line[i], &line[(i + span) % count]),
+            })
+            .collect();
+
+        RacingLine { points }
+    }
+
+    pub fn points(&self) -> &[RacingLinePoint] {
+        &self.points
+    }
+
+    /// Index of the point closest to the position. With a previous index only the points around it are searched,
+    /// so the line can cross itself without a car jumping to the other part
+    pub fn closest_point(&self, position: &Vector3<f32>, previous: Option<usize>) -> usize {
+        let count = self.points.len();
+        let distance = |i: &usize| (self.points[*i].position - position).norm();
+
+        let closest = match previous {
+            Some(previous) => (0..count.min(SEARCH_BEHIND + SEARCH_AHEAD))
+                .map(|offset| (previous + count - SEARCH_BEHIND.min(count) + offset) % count)
+                .min_by(|a, b| distance(a).total_cmp(&distance(b))),
+            None => (0..count).min_by(|a, b| distance(a).total_cmp(&distance(b))),
+        };
+        closest.unwrap_or(0)
+    }
+
+    /// Index of the point the given distance further along the line
+    pub fn index_ahead(&self, index: usize, distance: f32) -> usize {
+        (index + (distance / POINT_SPACING).round() as usize) % self.points.len().max(1)
+    }
+
+    /// The fastest speed at each point, for a car that can turn with the given sideways acceleration
+    /// and slow down with the given deceleration. Corners are braked for early enough to make them
+    pub fn speed_profile(&self, lateral_acceleration: f32, deceleration: f32, max_speed: f32) -> Vec<f32> {
+        let count = self.points.len();
+        let mut speeds = self
+            .points
+            .iter()
+            .map(|p| (lateral_acceleration / p.curvature.max(f32::EPSILON)).sqrt().min(max_speed))
+            .collect::<Vec<_>>();
+
+        // Going backwards, a point can't be faster than what can be braked down to the next one.
+        // The line is closed, so going around twice makes the braking zones reach across the start
+        for i in (0..2 * count).rev() {
+            let (this, next) = (i % count, (i + 1) % count);
+            let braking_speed = (speeds[next].powi(2) + 2.0 * deceleration * POINT_SPACING).sqrt();
+            speeds[this] = speeds[this].min(braking_speed);
+        }
+
+        speeds
+    }
+}
+
+/// Points along the closed line with the same distance between them
+fn resample(line: &[Vector3<f32>], spacing: f32) -> Vec<Vector3<f32>> {
+    if line.len() < 2 {
+        return line.to_vec();
+    }
+
+    let mut points = vec![line[0]];
+    let mut to_next = spacing;
+    for i in 0..line.len() {
+        let (start, end) = (line[i], line[(i + 1) % line.len()]);
+        let length = (end - start).norm();
+
+        let mut travelled = 0.0;
+        while length - travelled >= to_next {
+            travelled += to_next;
+            points.push(start + (end - start) * (travelled / length));
+            to_next = spacing;
+        }
+        to_next -= length - travelled;
+    }
+
+    // The last point may land on the first one
+    if points.len() > 1 && (points[points.len() - 1] - points[0]).norm() < spacing / 2.0 {
+        points.pop();
+    }
+    points
+}
+
+/// One over the radius of the circle through three points, seen from above
+fn curvature(a: &Vector3<f32>, b: &Vector3<f32>, c: &Vector3<f32>) -> f32 {
+    let (a, b, c) = (a.xz(), b.xz(), c.xz());
+    let area = ((b - a).perp(&(c - a)) / 2.0).abs();
+    let sides = (b - a).norm() * (c - b).norm() * (c - a).norm();
+
+    if sides <= f32::EPSILON {
+        0.0
+    } else {
+        4.0 * area / sides
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    /// Centre line of an oval with two straights and two half circles
+    fn oval(straight: f32, radius: f32) -> Vec<Vector3<f32>> {
+        let half_circle = |centre: f32, start: f32| {
+            (0..=20).map(move |i| {
+                let angle = start + PI * i as f32 / 20.0;
+                Vector3::new(radius * angle.cos(), 0.0, centre + radius * angle.sin())
+            })
+        };
+
+        half_circle(straight / 2.0, 0.0).chain(half_circle(-straight / 2.0, PI)).collect()
+    }
+
+    #[test]
+    fn line_stays_on_the_road_and_cuts_corners() {
+        let centre_line = oval(100.0, 40.0);
+        let line = RacingLine::new(&centre_line);
+        let centre_line = resample(&centre_line, POINT_SPACING);
+
+        let mut widest = 0.0;
+        for point in line.points() {
+            let distance = centre_line.iter().map(|c| (c - point.position).norm()).fold(f32::INFINITY, f32::min);
+            assert!(distance <= TRACK_WIDTH / 2.0, "point {} is off the road", point.position);
+            widest = f32::max(widest, distance);
+        }
+
+        // Cutting the corners uses the width of the road and makes the lap shorter than the centre line
+        assert!(widest > TRACK_WIDTH / 4.0, "line stays within {widest} of the centre");
+        let length = |line: &[Vector3<f32>]| (0..line.len()).map(|i| (line[(i + 1) % line.len()] - line[i]).norm()).sum::<f32>();
+        let positions = line.points().iter().map(|p| p.position).collect::<Vec<_>>();
+        assert!(length(&positions) < length(&centre_line));
+    }
+
+    #[test]
+    fn braking_starts_before_the_corner() {
+        let line = RacingLine::new(&oval(300.0, 30.0));
+        let speeds = line.speed_profile(10.0, 8.0, 40.0);
+
+        let slowest = speeds.iter().copied().fold(f32::INFINITY, f32::min);
+        let fastest = speeds.iter().copied().fold(0.0, f32::max);
+        assert!(slowest < 25.0 && fastest > 35.0, "speeds from {slowest} to {fastest}");
+
+        // No point needs more braking than the car can do to reach the next one
+        for i in 0..speeds.len() {
+            let next = speeds[(i + 1) % speeds.len()];
+            assert!(speeds[i].powi(2) - next.powi(2) <= 2.0 * 8.0 * POINT_SPACING + 0.01);
+        }
+    }
+}
@@ -1,97 +1,184 @@
-use glow::Context;
+use glow::{Context, HasContext, NativeTexture};
 use nalgebra::Vector3;
 
 use crate::{
     core::{
         game::Game,
         game_object::{Collider, GameObject},
+        surface::Surface,
     },
     game_objects::track::{
         track_right_corner_segment::TrackRightCornerSegment,
-        track_straight_segment::TrackStraightSegment, track_u_corner_segment::TrackUCornerSegment,
+        track_spline_segment::TrackSplineSegment, track_straight_segment::TrackStraightSegment,
+        track_u_corner_segment::TrackUCornerSegment,
     },
-    utils::FacingDirection,
 };
 
-use super::track_segment::{TrackSegment, TRACK_ELEVATION};
+use super::{
+    track_file::{segment_position, Checkpoint, SegmentDescription, TrackFile},
+    track_segment::{TrackSegment, ROAD_TEXTURE, TRACK_ELEVATION, TRACK_WIDTH},
+};
+
+// Closer points than this are treated as the same point when joining the centre lines of the segments
+const POINT_MERGE_DISTANCE: f32 = 1.0;
+// Roads closer to the car than this, measured from above, are checked to see if it fell off the track
+const FALL_CHECK_DISTANCE: f32 = TRACK_WIDTH;
+// A car this far below the lowest road around it fell off the track
+const FALL_HEIGHT: f32 = 4.0;
+
+#[derive(Clone, Copy, Debug)]
+pub struct RespawnPoint {
+    pub position: Vector3<f32>,
+    // Angle the car should face to drive in the direction of the track
+    pub angle: f32,
+}
 
 pub struct Track<'a> {
     track: Vec<TrackSegment<'a>>,
+    // Shared by all segments, deleted with the track
+    road_texture: NativeTexture,
+    gl: &'a Context,
 }
 
 impl<'a> Track<'a> {
-    pub fn new(gl: &'a Context, game: &Game) -> Track<'a> {
-        let track = Track::create_track(gl, game);
+    pub fn new(track_file: &TrackFile, gl: &'a Context, game: &Game) -> Track<'a> {
+        let road_texture = game.load_texture(ROAD_TEXTURE, true);
+        let track = Track::create_track(track_file, road_texture, gl);
+
+        *game.respawn_points.borrow_mut() = Track::create_respawn_points(&track);
+
+        Track { track, road_texture, gl }
+    }
+
+    /// Join the centre lines of all segments, in the direction the track is driven, into a list of respawn points
+    fn create_respawn_points(track: &[TrackSegment]) -> Vec<RespawnPoint> {
+        let centre_lines = track.iter().map(|s| s.centre_line().as_slice()).collect::<Vec<_>>();
+        let centre_line = Track::join_centre_lines(&centre_lines)
+            .into_iter()
+            .map(|(_, point)| point)
+            .collect::<Vec<_>>();
+
+        (0..centre_line.len())
+            .map(|i| {
+                let position = centre_line[i];
+                let v = centre_line[(i + 1) % centre_line.len()] - position;
+
+                RespawnPoint {
+                    position,
+                    angle: f32::atan2(v.x, v.z),
+                }
+            })
+            .collect()
+    }
+
+    /// Join the centre lines of the segments into one line going around the track in the direction it is driven.
+    /// Every point comes with the index of the segment it is on
+    pub fn join_centre_lines(centre_lines: &[&[Vector3<f32>]]) -> Vec<(usize, Vector3<f32>)> {
+        let mut centre_line: Vec<(usize, Vector3<f32>)> = Vec::new();
+
+        for (i, segment) in centre_lines.iter().enumerate() {
+            let next_segment = centre_lines[(i + 1) % centre_lines.len()];
+            let next_start = next_segment.first();
+            let next_end = next_segment.last();
+
+            let distance_to_next = |point: Option<&Vector3<f32>>| match point {
+                Some(point) => [next_start, next_end]
+                    .iter()
+                    .flatten()
+                    .map(|p| (*p - point).norm())
+                    .fold(f32::INFINITY, f32::min),
+                None => f32::INFINITY,
+            };
+
+            // The segments don't know which way they are driven, so flip the ones that start where the next one begins
+            let mut points = segment.to_vec();
+            if distance_to_next(points.first()) < distance_to_next(points.last()) {
+                points.reverse();
+            }
 
-        Track { track }
+            for point in points {
+                match centre_line.last() {
+                    Some((_, last)) if (last - point).norm() < POINT_MERGE_DISTANCE => (),
+                    _ => centre_line.push((i, point)),
+                }
+            }
+        }
+
+        // A closed spline ends on the point it started on
+        if let [(_, first), .., (_, last)] = centre_line[..] {
+            if (last - first).norm() < POINT_MERGE_DISTANCE {
+                centre_line.pop();
+            }
+        }
+
+        centre_line
     }
 
-    fn create_track<'b>(gl: &'b Context, game: &Game) -> Vec<TrackSegment<'b>> {
-        let mut track = Vec::new();
+    /// The respawn point at the last checkpoint the car drove through. Before the car has crossed the finish
+    /// line there is none, then it is the closest point behind the car
+    pub fn respawn_point(
+        respawn_points: &[RespawnPoint],
+        last_checkpoint: Option<&Checkpoint>,
+        position: &Vector3<f32>,
+    ) -> Option<RespawnPoint> {
+        // Compared in 3D, so a point on a road above or below isn't picked
+        let closest_to = |target: Vector3<f32>| {
+            move |a: &&RespawnPoint, b: &&RespawnPoint| {
+                (target - a.position).norm().total_cmp(&(target - b.position).norm())
+            }
+        };
 
+        match last_checkpoint {
+            Some(checkpoint) => respawn_points.iter().min_by(closest_to(checkpoint.position())),
+            None => respawn_points
+                .iter()
+                .filter(|p| {
+                    let forward = Vector3::new(p.angle.sin(), 0.0, p.angle.cos());
+                    (position - p.position).dot(&forward) >= 0.0
+                })
+                .min_by(closest_to(*position)),
+        }
+        .copied()
+    }
+
+    /// If the position is further below every road around it than a car can be while driving on one
+    pub fn is_below_road(respawn_points: &[RespawnPoint], position: &Vector3<f32>) -> bool {
+        respawn_points
+            .iter()
+            .filter(|p| (position - p.position).xz().norm() < FALL_CHECK_DISTANCE)
+            .map(|p| p.position.y)
+            .min_by(f32::total_cmp)
+            .is_some_and(|road_height| position.y < road_height - FALL_HEIGHT)
+    }
+
+    fn create_track(track_file: &TrackFile, road_texture: NativeTexture, gl: &'a Context) -> Vec<TrackSegment<'a>> {
         use TrackSegment::*;
-        track.push(Straight(TrackStraightSegment::new(
-            Vector3::new(0.0, 0.0, 66.0),
-            FacingDirection::North,
-            240.0,
-            gl,
-            game,
-        )));
-        track.push(RightCorner(TrackRightCornerSegment::new(
-            Vector3::new(-10.0, 0.0, 285.0),
-            gl,
-            game,
-        )));
-        track.push(Straight(TrackStraightSegment::new(
-            Vector3::new(186.0, 0.0, 275.0),
-            FacingDirection::West,
-            200.0,
-            gl,
-            game,
-        )));
-
-        // S
-        track.push(UCorner(TrackUCornerSegment::new(
-            Vector3::new(286.0, 0.0, 235.0),
-            90f32.to_radians(),
-            gl,
-            game,
-        )));
-        track.push(UCorner(TrackUCornerSegment::new(
-            Vector3::new(292.3, 0.0001, 159.0),
-            270f32.to_radians(),
-            gl,
-            game,
-        )));
-        track.push(UCorner(TrackUCornerSegment::new(
-            Vector3::new(292.0, 0.0, 79.0),
-            90f32.to_radians(),
-            gl,
-            game,
-        )));
-
-        // Track end
-        track.push(Straight(TrackStraightSegment::new(
-            Vector3::new(226.0, 0.0, 40.0),
-            FacingDirection::West,
-            140.0,
-            gl,
-            game,
-        )));
-        track.push(RightCorner(TrackRightCornerSegment::new(
-            Vector3::new(67.0, 0.0, 50.0),
-            gl,
-            game,
-        )));
-        track.push(UCorner(TrackUCornerSegment::new(
-            Vector3::new(37.0, 0.0, -49.0),
-            180f32.to_radians(),
-            gl,
-            game,
-        )));
-        // track.push(RoadSurface::new(Vector3::new(105.0, 0.0, 200.0), Vector2::new(200.0, 10.0), gl, game));
-
-        track
+        track_file
+            .segments
+            .iter()
+            .map(|segment| match *segment {
+                SegmentDescription::Straight { position, rotation, slope, banking, length } => Straight(
+                    TrackStraightSegment::new(
+                        segment_position(position),
+                        rotation.to_radians(),
+                        slope.to_radians(),
+                        banking.to_radians(),
+                        length,
+                        road_texture,
+                        gl,
+                    ),
+                ),
+                SegmentDescription::RightCorner { position, rotation } => RightCorner(
+                    TrackRightCornerSegment::new(segment_position(position), rotation.to_radians(), road_texture, gl),
+                ),
+                SegmentDescription::UCorner { position, rotation } => UCorner(
+                    TrackUCornerSegment::new(segment_position(position), rotation.to_radians(), road_texture, gl),
+                ),
+                SegmentDescription::Spline { ref points, closed } => Spline(
+                    TrackSplineSegment::new(points, closed, road_texture, gl),
+                ),
+            })
+            .collect()
     }
 }
 
@@ -101,7 +188,7 @@ impl<'a> GameObject<'a> for Track<'a> {
             self.track
                 .iter()
                 .map(|s| s.collision_info())
-                .chain([Collider::HeightCollider(TRACK_ELEVATION)])
+                .chain([Collider::HeightCollider(TRACK_ELEVATION, Surface::Sand)])
                 .collect(),
         )
     }
@@ -116,3 +203,45 @@ impl<'a> GameObject<'a> for Track<'a> {
         }
     }
 }
+
+impl<'a> Drop for Track<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.delete_texture(self.road_texture);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A road along the z axis that goes over itself on a bridge 10 units higher, both driven towards +z
+    fn respawn_points() -> Vec<RespawnPoint> {
+        let road = |y: f32| (0..10).map(move |i| RespawnPoint { position: Vector3::new(0.0, y, i as f32 * 10.0), angle: 0.0 });
+        road(TRACK_ELEVATION).chain(road(TRACK_ELEVATION + 10.0)).collect()
+    }
+
+    #[test]
+    fn respawns_at_the_last_checkpoint_on_its_road() {
+        let checkpoint = Checkpoint::Line { start: (-10.0, 30.0), end: (10.0, 30.0), height: 10.0 };
+        let car = Vector3::new(0.0, TRACK_ELEVATION + 1.0, 80.0);
+
+        let point = Track::respawn_point(&respawn_points(), Some(&checkpoint), &car).unwrap();
+        assert_eq!(point.position, Vector3::new(0.0, TRACK_ELEVATION + 10.0, 30.0));
+
+        let point = Track::respawn_point(&respawn_points(), None, &car).unwrap();
+        assert_eq!(point.position, Vector3::new(0.0, TRACK_ELEVATION, 80.0));
+    }
+
+    #[test]
+    fn cars_below_every_road_fell_off() {
+        let points = respawn_points()[10..].to_vec();
+        assert!(Track::is_below_road(&points, &Vector3::new(5.0, TRACK_ELEVATION, 40.0)));
+        assert!(!Track::is_below_road(&points, &Vector3::new(5.0, TRACK_ELEVATION + 11.0, 40.0)));
+        // The lower road is still under the bridge
+        assert!(!Track::is_below_road(&respawn_points(), &Vector3::new(5.0, TRACK_ELEVATION + 1.0, 40.0)));
+        // Away from every road the off track timer puts the car back
+        assert!(!Track::is_below_road(&points, &Vector3::new(100.0, TRACK_ELEVATION, 40.0)));
+    }
+}
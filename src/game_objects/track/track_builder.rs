@@ -0,0 +1,330 @@
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+use nalgebra::Vector3;
+
+use super::{
+    track_file::SegmentDescription,
+    track_spline::{SplineGeometry, SplinePoint},
+};
+
+// Ends of the segments in their local coordinates, before the segment is rotated.
+// A right corner segment turns left when driven from its entry to its exit, and right the other way round
+const CORNER_ENTRY: (f32, f32) = (10.0, -100.0);
+const CORNER_EXIT: (f32, f32) = (100.0, -10.0);
+const U_CORNER_ENTRY: (f32, f32) = (-40.0, 0.0);
+const U_CORNER_EXIT: (f32, f32) = (40.0, 0.0);
+
+// How far the heading may be off for the track to count as closed
+const CLOSING_HEADING_TOLERANCE: f32 = 5.0 * PI / 180.0;
+
+/// A point on the track and the direction the track is driven in there.
+/// The heading is in radians, 0 runs along the z axis, the same as the angle of a car
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SegmentTransform {
+    // Relative to the track surface, the same as the positions in a track file
+    pub position: Vector3<f32>,
+    pub heading: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Turn {
+    Left,
+    Right,
+}
+
+impl SegmentTransform {
+    pub fn new(position: Vector3<f32>, heading: f32) -> SegmentTransform {
+        SegmentTransform { position, heading }
+    }
+
+    /// Heading difference to another transform, between 0 and PI
+    pub fn heading_difference(&self, other: &SegmentTransform) -> f32 {
+        let difference = (self.heading - other.heading).rem_euclid(TAU);
+        difference.min(TAU - difference)
+    }
+}
+
+impl SegmentDescription {
+    /// Where the segment is entered, driving it in the direction it is modelled in
+    pub fn entry(&self) -> SegmentTransform {
+        use SegmentDescription::*;
+        match *self {
+            Straight { position, rotation, slope, length, .. } => {
+                let rotation = rotation.to_radians();
+                SegmentTransform::new(straight_end(position, rotation, slope, -length / 2.0), rotation)
+            }
+            RightCorner { position, rotation } => {
+                let rotation = rotation.to_radians();
+                SegmentTransform::new(place(position, rotation, CORNER_ENTRY), rotation)
+            }
+            UCorner { position, rotation } => {
+                let rotation = rotation.to_radians();
+                SegmentTransform::new(place(position, rotation, U_CORNER_ENTRY), rotation)
+            }
+            Spline { ref points, closed } => spline_end(points, closed, true),
+        }
+    }
+
+    /// Where the segment is left, driving it in the direction it is modelled in
+    pub fn exit(&self) -> SegmentTransform {
+        use SegmentDescription::*;
+        match *self {
+            Straight { position, rotation, slope, length, .. } => {
+                let rotation = rotation.to_radians();
+                SegmentTransform::new(straight_end(position, rotation, slope, length / 2.0), rotation)
+            }
+            RightCorner { position, rotation } => {
+                let rotation = rotation.to_radians();
+                SegmentTransform::new(place(position, rotation, CORNER_EXIT), rotation + FRAC_PI_2)
+            }
+            UCorner { position, rotation } => {
+                let rotation = rotation.to_radians();
+                SegmentTransform::new(place(position, rotation, U_CORNER_EXIT), rotation + PI)
+            }
+            Spline { ref points, closed } => spline_end(points, closed, false),
+        }
+    }
+
+    /// The same segment moved by an offset
+    pub fn translated(&self, offset: &Vector3<f32>) -> SegmentDescription {
+        let translate = |p: (f32, f32, f32)| (p.0 + offset.x, p.1 + offset.y, p.2 + offset.z);
+
+        use SegmentDescription::*;
+        match *self {
+            Straight { position, rotation, slope, banking, length } => {
+                Straight { position: translate(position), rotation, slope, banking, length }
+            }
+            RightCorner { position, rotation } => RightCorner { position: translate(position), rotation },
+            UCorner { position, rotation } => UCorner { position: translate(position), rotation },
+            Spline { ref points, closed } => Spline {
+                points: points
+                    .iter()
+                    .map(|p| SplinePoint { position: translate(p.position), ..*p })
+                    .collect(),
+                closed,
+            },
+        }
+    }
+}
+
+/// Builds a track by appending segments, each one starts where the previous one ends
+pub struct TrackBuilder {
+    start: SegmentTransform,
+    exit: SegmentTransform,
+    segments: Vec<SegmentDescription>,
+}
+
+impl TrackBuilder {
+    pub fn new(start: SegmentTransform) -> TrackBuilder {
+        TrackBuilder {
+            start,
+            exit: start,
+            segments: Vec::new(),
+        }
+    }
+
+    pub fn straight(&mut self, length: f32) -> &mut TrackBuilder {
+        self.ramp(length, 0.0)
+    }
+
+    /// A straight going up or down, the slope is in radians and positive goes uphill
+    pub fn ramp(&mut self, length: f32, slope: f32) -> &mut TrackBuilder {
+        let rotation = self.exit.heading;
+        self.append(
+            SegmentDescription::Straight {
+                position: (0.0, 0.0, 0.0),
+                rotation: rotation.to_degrees(),
+                slope: slope.to_degrees(),
+                banking: 0.0,
+                length,
+            },
+            false,
+        )
+    }
+
+    /// A 90 degree corner
+    pub fn corner(&mut self, turn: Turn) -> &mut TrackBuilder {
+        // Turning right drives the corner backwards, so it has to be rotated to face the other way
+        let rotation = match turn {
+            Turn::Left => self.exit.heading,
+            Turn::Right => self.exit.heading + FRAC_PI_2,
+        };
+        self.append(
+            SegmentDescription::RightCorner { position: (0.0, 0.0, 0.0), rotation: rotation.to_degrees() },
+            turn == Turn::Right,
+        )
+    }
+
+    /// A 180 degree corner
+    pub fn u_turn(&mut self, turn: Turn) -> &mut TrackBuilder {
+        let rotation = self.exit.heading;
+        self.append(
+            SegmentDescription::UCorner { position: (0.0, 0.0, 0.0), rotation: rotation.to_degrees() },
+            turn == Turn::Right,
+        )
+    }
+
+    /// A spline road, the points are relative to the current exit with the z axis pointing along the track
+    pub fn spline(&mut self, points: &[SplinePoint]) -> &mut TrackBuilder {
+        let heading = self.exit.heading;
+        let points = points
+            .iter()
+            .map(|p| {
+                let position = place((0.0, p.position.1, 0.0), heading, (p.position.0, p.position.2));
+                SplinePoint { position: (position.x, position.y, position.z), ..*p }
+            })
+            .collect();
+        self.append(SegmentDescription::Spline { points, closed: false }, false)
+    }
+
+    /// Two corners turning opposite ways, the track keeps its heading but moves to the side
+    pub fn s_bend(&mut self, first_turn: Turn) -> &mut TrackBuilder {
+        let second_turn = match first_turn {
+            Turn::Left => Turn::Right,
+            Turn::Right => Turn::Left,
+        };
+        self.corner(first_turn).corner(second_turn)
+    }
+
+    /// Place a segment so the end it is driven from is at the current exit
+    fn append(&mut self, segment: SegmentDescription, reversed: bool) -> &mut TrackBuilder {
+        let (entry, exit) = if reversed {
+            (segment.exit(), segment.entry())
+        } else {
+            (segment.entry(), segment.exit())
+        };
+
+        let offset = self.exit.position - entry.position;
+        let exit_heading = if reversed { exit.heading + PI } else { exit.heading };
+
+        self.segments.push(segment.translated(&offset));
+        self.exit = SegmentTransform::new(exit.position + offset, exit_heading.rem_euclid(TAU));
+        self
+    }
+
+    /// Where the next segment would be placed
+    pub fn exit(&self) -> SegmentTransform {
+        self.exit
+    }
+
+    /// Distance and heading difference between the end of the track and its start
+    pub fn closing_error(&self) -> (f32, f32) {
+        (
+            (self.exit.position - self.start.position).norm(),
+            self.exit.heading_difference(&self.start),
+        )
+    }
+
+    /// If the track ends within tolerance of where it started, facing the same way
+    pub fn is_closed(&self, tolerance: f32) -> bool {
+        let (distance, heading) = self.closing_error();
+        !self.segments.is_empty() && distance <= tolerance && heading <= CLOSING_HEADING_TOLERANCE
+    }
+
+    pub fn segments(&self) -> &[SegmentDescription] {
+        &self.segments
+    }
+
+    pub fn build(self) -> Vec<SegmentDescription> {
+        self.segments
+    }
+}
+
+/// Middle of the start or end of a straight, the distance is measured along the road from its centre
+fn straight_end(position: (f32, f32, f32), rotation: f32, slope: f32, distance: f32) -> Vector3<f32> {
+    let (slope_sin, slope_cos) = slope.to_radians().sin_cos();
+    place(position, rotation, (0.0, distance * slope_cos)) + Vector3::new(0.0, distance * slope_sin, 0.0)
+}
+
+/// First or last point of a spline, a closed spline starts and ends at the same point
+fn spline_end(points: &[SplinePoint], closed: bool, first: bool) -> SegmentTransform {
+    let samples = SplineGeometry::new(points, closed, &Vector3::zeros()).samples;
+    let sample = if first { samples.first() } else { samples.last() };
+
+    match sample {
+        Some(s) => SegmentTransform::new(s.centre, f32::atan2(s.tangent.x, s.tangent.z)),
+        None => SegmentTransform::new(Vector3::zeros(), 0.0),
+    }
+}
+
+/// Rotate a point on the ground around the segment position and move it there
+fn place(position: (f32, f32, f32), rotation: f32, local: (f32, f32)) -> Vector3<f32> {
+    let (rot_sin, rot_cos) = rotation.sin_cos();
+    Vector3::new(
+        position.0 + local.0 * rot_cos + local.1 * rot_sin,
+        position.1,
+        position.2 - local.0 * rot_sin + local.1 * rot_cos,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use super::*;
+
+    const TOLERANCE: f32 = 0.01;
+
+    fn start() -> SegmentTransform {
+        SegmentTransform::new(Vector3::new(0.0, 0.0, 0.0), 0.0)
+    }
+
+    #[test]
+    fn corners_keep_segments_connected() {
+        for turn in [Turn::Left, Turn::Right] {
+            let mut builder = TrackBuilder::new(start());
+            builder.straight(50.0).corner(turn).straight(30.0).u_turn(turn).s_bend(turn);
+
+            let segments = builder.segments().to_vec();
+            let exit = builder.exit();
+            let mut next_entry = start();
+            for (i, segment) in segments.iter().enumerate() {
+                let (entry, segment_exit) = (segment.entry(), segment.exit());
+                let connected = [entry, segment_exit]
+                    .iter()
+                    .any(|end| (end.position - next_entry.position).norm() < TOLERANCE);
+                assert!(connected, "segment {i} is not connected to the previous one");
+
+                next_entry = if (entry.position - next_entry.position).norm() < TOLERANCE {
+                    segment_exit
+                } else {
+                    entry
+                };
+            }
+            assert!((next_entry.position - exit.position).norm() < TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn turns_change_the_heading() {
+        let mut builder = TrackBuilder::new(start());
+        builder.corner(Turn::Left);
+        assert!((builder.exit().heading - FRAC_PI_2).abs() < TOLERANCE);
+
+        let mut builder = TrackBuilder::new(start());
+        builder.corner(Turn::Right);
+        assert!((builder.exit().heading - 3.0 * FRAC_PI_2).abs() < TOLERANCE);
+        assert!(builder.exit().position.x < 0.0);
+
+        let mut builder = TrackBuilder::new(start());
+        builder.s_bend(Turn::Left);
+        assert!(builder.exit().heading_difference(&start()) < TOLERANCE);
+    }
+
+    #[test]
+    fn closed_circuit() {
+        let mut builder = TrackBuilder::new(start());
+        builder
+            .straight(100.0)
+            .corner(Turn::Right)
+            .straight(50.0)
+            .corner(Turn::Right)
+            .straight(100.0)
+            .corner(Turn::Right)
+            .straight(50.0);
+        assert!(!builder.is_closed(1.0));
+
+        builder.corner(Turn::Right);
+        assert!(builder.is_closed(1.0), "closing error {:?}", builder.closing_error());
+    }
+}
@@ -0,0 +1,412 @@
+use std::{fmt::Write, fs, path::Path};
+
+use anyhow::anyhow;
+use itertools::Itertools;
+use nalgebra::{Matrix4, Vector3, Vector4};
+
+use crate::{core::color::Color, objects::track_corner::TrackCornerType};
+
+use super::{
+    track_file::{segment_position, SegmentDescription, TrackFile},
+    track_kerb::TrackKerb,
+    track_right_corner_segment::{right_corner_platform_matrix, CORNER_ROTATION},
+    track_segment::{
+        corner_geometry, TrackMaterial, TrackPiece, BARRIER_MATERIAL, KERB_MATERIAL, PIECE_WHITE, PLATFORM_MATERIAL,
+        ROAD_MATERIAL, ROAD_TEXTURE, TRACK_ELEVATION, TRACK_WIDTH,
+    },
+    track_side::{TrackSegmentSideType, TrackSide},
+    track_spline::SplineGeometry,
+    track_spline_segment::{spline_barrier_pieces, spline_platform_matrices},
+    track_straight_segment::{straight_platform_matrix, straight_road_edges},
+    track_u_corner_segment::u_corner_platform_matrix,
+};
+
+// Name the road texture gets next to the exported files
+const TEXTURE_FILE_NAME: &str = "road.png";
+
+/// How a part of the track looks, the same as the game sets it up when drawing it
+pub struct ExportMaterial {
+    pub name: &'static str,
+    // The object in the exported file the material is used by
+    pub object: &'static str,
+    pub ambient: Color,
+    pub diffuse: Color,
+    pub specular: Color,
+    pub shininess: f32,
+    pub textured: bool,
+}
+
+pub fn export_materials() -> Vec<ExportMaterial> {
+    let material = |name, object, material: TrackMaterial, textured| ExportMaterial {
+        name,
+        object,
+        ambient: material.ambient,
+        diffuse: material.diffuse,
+        specular: material.specular,
+        shininess: material.shininess,
+        textured,
+    };
+    // Barrier and kerb blocks are drawn with their own colour instead of the one of the material
+    let white = |material: TrackMaterial| TrackMaterial { ambient: PIECE_WHITE, diffuse: PIECE_WHITE, ..material };
+
+    vec![
+        material("road", "road", ROAD_MATERIAL, true),
+        material("kerb_red", "kerbs", KERB_MATERIAL, false),
+        material("kerb_white", "kerbs", white(KERB_MATERIAL), false),
+        material("barrier_red", "barriers", BARRIER_MATERIAL, false),
+        material("barrier_white", "barriers", white(BARRIER_MATERIAL), false),
+        material("platform", "platform", PLATFORM_MATERIAL, false),
+    ]
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MeshVertex {
+    pub position: Vector3<f32>,
+    pub normal: Vector3<f32>,
+    pub uv: (f32, f32),
+}
+
+/// Triangles of the track that share a material, positions are relative to the track surface like in the track file
+pub struct TrackMesh {
+    pub object: &'static str,
+    pub material: &'static str,
+    pub vertices: Vec<MeshVertex>,
+    pub triangles: Vec<[usize; 3]>,
+}
+
+impl TrackMesh {
+    fn new(material: &ExportMaterial) -> TrackMesh {
+        TrackMesh {
+            object: material.object,
+            material: material.name,
+            vertices: Vec::new(),
+            triangles: Vec::new(),
+        }
+    }
+
+    /// Road between pairs of points on its two edges in world coordinates. The texture repeats every TRACK_WIDTH
+    /// along the road, like on the spline segments
+    fn add_strip(&mut self, edges: &[(Vector3<f32>, Vector3<f32>)]) {
+        let start = self.vertices.len();
+        let mut distance = 0.0;
+
+        for (i, (a, b)) in edges.iter().enumerate() {
+            let (previous, next) = (edges[i.saturating_sub(1)], edges[(i + 1).min(edges.len() - 1)]);
+            let along = (next.0 + next.1) - (previous.0 + previous.1);
+            let normal = upwards((b - a).cross(&along));
+
+            if i > 0 {
+                distance += (0.5 * (a + b) - 0.5 * (previous.0 + previous.1)).norm();
+            }
+            let v = distance / TRACK_WIDTH;
+
+            self.vertices.push(MeshVertex { position: surface_position(a), normal, uv: (0.0, v) });
+            self.vertices.push(MeshVertex { position: surface_position(b), normal, uv: (1.0, v) });
+        }
+
+        for i in 0..edges.len().saturating_sub(1) {
+            let (a1, b1, a2, b2) = (start + 2 * i, start + 2 * i + 1, start + 2 * i + 2, start + 2 * i + 3);
+            self.add_triangle([a1, b1, a2]);
+            self.add_triangle([b1, b2, a2]);
+        }
+    }
+
+    /// A unit cube placed in world coordinates by the matrix
+    fn add_cube(&mut self, matrix: &Matrix4<f32>) {
+        let normal_matrix = matrix
+            .fixed_slice::<3, 3>(0, 0)
+            .try_inverse()
+            .map(|m| m.transpose())
+            .unwrap_or_else(|| matrix.fixed_slice::<3, 3>(0, 0).into_owned());
+
+        for axis in 0..3 {
+            for side in [-0.5, 0.5] {
+                let mut local_normal = Vector3::zeros();
+                local_normal[axis] = side * 2.0;
+                let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+
+                let start = self.vertices.len();
+                for (u, v) in [(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)] {
+                    let mut corner = Vector4::new(0.0, 0.0, 0.0, 1.0);
+                    corner[axis] = side;
+                    corner[u_axis] = u;
+                    corner[v_axis] = v;
+
+                    self.vertices.push(MeshVertex {
+                        position: surface_position(&(matrix * corner).xyz()),
+                        normal: (normal_matrix * local_normal).normalize(),
+                        uv: (u + 0.5, v + 0.5),
+                    });
+                }
+
+                self.add_triangle([start, start + 1, start + 2]);
+                self.add_triangle([start, start + 2, start + 3]);
+            }
+        }
+    }
+
+    /// Adds the triangle turned so it is counter clockwise seen from the side its vertex normals point to
+    fn add_triangle(&mut self, [a, b, c]: [usize; 3]) {
+        let (pa, pb, pc) = (self.vertices[a].position, self.vertices[b].position, self.vertices[c].position);
+        let normal = self.vertices[a].normal + self.vertices[b].normal + self.vertices[c].normal;
+
+        if (pb - pa).cross(&(pc - pa)).dot(&normal) < 0.0 {
+            self.triangles.push([a, c, b]);
+        } else {
+            self.triangles.push([a, b, c]);
+        }
+    }
+}
+
+/// World position to a position relative to the track surface
+fn surface_position(position: &Vector3<f32>) -> Vector3<f32> {
+    position - Vector3::new(0.0, TRACK_ELEVATION, 0.0)
+}
+
+fn upwards(normal: Vector3<f32>) -> Vector3<f32> {
+    let normal = normal.try_normalize(f32::EPSILON).unwrap_or_else(Vector3::y);
+    if normal.y < 0.0 {
+        -normal
+    } else {
+        normal
+    }
+}
+
+/// The parts of a segment in world coordinates, the same as the built segment draws
+struct SegmentParts {
+    road: Vec<(Vector3<f32>, Vector3<f32>)>,
+    kerbs: Vec<TrackPiece>,
+    barriers: Vec<TrackPiece>,
+    platform: Vec<Matrix4<f32>>,
+}
+
+fn segment_parts(segment: &SegmentDescription) -> SegmentParts {
+    // The sides are placed half a unit above the road
+    let side_position = |position| segment_position(position) + Vector3::new(0.0, TRACK_ELEVATION + 0.5, 0.0);
+
+    use SegmentDescription::*;
+    match *segment {
+        Straight { position, rotation, slope, banking, length } => {
+            let (rotation, slope, banking) = (rotation.to_radians(), slope.to_radians(), banking.to_radians());
+            let centre = segment_position(position);
+
+            SegmentParts {
+                road: straight_road_edges(&centre, rotation, slope, banking, length).to_vec(),
+                kerbs: Vec::new(),
+                barriers: TrackSide::create_pieces(
+                    &side_position(position),
+                    rotation,
+                    (slope, banking),
+                    length,
+                    &TrackSegmentSideType::Straight,
+                ),
+                platform: vec![straight_platform_matrix(&centre, rotation, slope, banking, length)],
+            }
+        }
+        RightCorner { position, rotation } => {
+            let rotation = rotation.to_radians();
+            let geometry =
+                corner_geometry(&TrackCornerType::Right, &segment_position(position), rotation + CORNER_ROTATION);
+
+            SegmentParts {
+                road: geometry.road_edges,
+                kerbs: geometry.kerb.pieces().to_vec(),
+                barriers: TrackSide::create_pieces(
+                    &side_position(position),
+                    rotation,
+                    (0.0, 0.0),
+                    20.0,
+                    &TrackSegmentSideType::RightCorner,
+                ),
+                platform: vec![right_corner_platform_matrix(&segment_position(position), rotation)],
+            }
+        }
+        UCorner { position, rotation } => {
+            let rotation = rotation.to_radians();
+            let geometry = corner_geometry(&TrackCornerType::UTurn, &segment_position(position), rotation);
+
+            SegmentParts {
+                road: geometry.road_edges,
+                kerbs: geometry.kerb.pieces().to_vec(),
+                barriers: TrackSide::create_pieces(
+                    &side_position(position),
+                    rotation,
+                    (0.0, 0.0),
+                    20.0,
+                    &TrackSegmentSideType::UTurn,
+                ),
+                platform: vec![u_corner_platform_matrix(&segment_position(position), rotation)],
+            }
+        }
+        Spline { ref points, closed } => {
+            let geometry = SplineGeometry::new(points, closed, &Vector3::new(0.0, TRACK_ELEVATION + 0.1, 0.0));
+
+            SegmentParts {
+                road: geometry.road_vertices.iter().tuples().map(|(a, b)| (a.position, b.position)).collect(),
+                kerbs: [&geometry.left_kerb, &geometry.right_kerb]
+                    .into_iter()
+                    .flat_map(|kerb| TrackKerb::new(kerb.clone()).pieces().to_vec())
+                    .collect(),
+                barriers: [&geometry.left_barrier, &geometry.right_barrier]
+                    .into_iter()
+                    .flat_map(|barrier| spline_barrier_pieces(barrier))
+                    .collect(),
+                platform: spline_platform_matrices(&geometry),
+            }
+        }
+    }
+}
+
+/// The whole track as one mesh per material, without building it
+pub fn track_meshes(track_file: &TrackFile) -> Vec<TrackMesh> {
+    let mut meshes = export_materials().iter().map(TrackMesh::new).collect::<Vec<_>>();
+    let mesh = |material: &str| {
+        meshes
+            .iter()
+            .position(|m| m.material == material)
+            .expect("Every material has a mesh")
+    };
+    let (road, kerb_red, kerb_white, barrier_red, barrier_white, platform) = (
+        mesh("road"),
+        mesh("kerb_red"),
+        mesh("kerb_white"),
+        mesh("barrier_red"),
+        mesh("barrier_white"),
+        mesh("platform"),
+    );
+
+    for segment in &track_file.segments {
+        let parts = segment_parts(segment);
+
+        meshes[road].add_strip(&parts.road);
+        for piece in &parts.kerbs {
+            let index = if piece.color == PIECE_WHITE { kerb_white } else { kerb_red };
+            meshes[index].add_cube(&piece.matrix);
+        }
+        for piece in &parts.barriers {
+            let index = if piece.color == PIECE_WHITE { barrier_white } else { barrier_red };
+            meshes[index].add_cube(&piece.matrix);
+        }
+        for matrix in &parts.platform {
+            meshes[platform].add_cube(matrix);
+        }
+    }
+
+    meshes
+}
+
+/// Wavefront OBJ text for the meshes, with one object for each part of the track
+pub fn to_obj(meshes: &[TrackMesh], material_library: &str) -> String {
+    let mut text = String::new();
+    writeln!(text, "mtllib {material_library}").unwrap();
+
+    // OBJ indices start at 1 and count every vertex before them in the file
+    let mut offset = 1;
+    let mut object = None;
+    for mesh in meshes.iter().filter(|m| !m.triangles.is_empty()) {
+        if object != Some(mesh.object) {
+            writeln!(text, "o {}", mesh.object).unwrap();
+            object = Some(mesh.object);
+        }
+
+        for v in &mesh.vertices {
+            writeln!(text, "v {:.4} {:.4} {:.4}", v.position.x, v.position.y, v.position.z).unwrap();
+            writeln!(text, "vt {:.4} {:.4}", v.uv.0, v.uv.1).unwrap();
+            writeln!(text, "vn {:.4} {:.4} {:.4}", v.normal.x, v.normal.y, v.normal.z).unwrap();
+        }
+
+        writeln!(text, "usemtl {}", mesh.material).unwrap();
+        for triangle in &mesh.triangles {
+            let [a, b, c] = triangle.map(|i| i + offset);
+            writeln!(text, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}").unwrap();
+        }
+
+        offset += mesh.vertices.len();
+    }
+
+    text
+}
+
+/// Wavefront MTL text for the materials, the road texture is expected next to the file
+pub fn to_mtl(materials: &[ExportMaterial]) -> String {
+    let mut text = String::new();
+    for material in materials {
+        let color = |c: &Color| format!("{:.4} {:.4} {:.4}", c.r, c.g, c.b);
+
+        writeln!(text, "newmtl {}", material.name).unwrap();
+        writeln!(text, "Ka {}", color(&material.ambient)).unwrap();
+        writeln!(text, "Kd {}", color(&material.diffuse)).unwrap();
+        writeln!(text, "Ks {}", color(&material.specular)).unwrap();
+        writeln!(text, "Ns {:.1}", material.shininess).unwrap();
+        writeln!(text, "d {:.1}", material.diffuse.a).unwrap();
+        if material.textured {
+            writeln!(text, "map_Kd {TEXTURE_FILE_NAME}").unwrap();
+        }
+        writeln!(text).unwrap();
+    }
+
+    text
+}
+
+/// Write the track to an OBJ file, with the materials in an MTL file and the road texture next to it
+pub fn export_track(track_file: &TrackFile, path: &Path) -> anyhow::Result<()> {
+    let mtl_path = path.with_extension("mtl");
+    let mtl_name = mtl_path
+        .file_name()
+        .ok_or_else(|| anyhow!("{} is not a file name", path.display()))?
+        .to_string_lossy();
+    let folder = path.parent().unwrap_or(Path::new("."));
+
+    let write = |path: &Path, text: String| {
+        fs::write(path, text).map_err(|e| anyhow!("Failed to write {}. {e}", path.display()))
+    };
+    write(path, to_obj(&track_meshes(track_file), &mtl_name))?;
+    write(&mtl_path, to_mtl(&export_materials()))?;
+
+    let texture_path = folder.join(TEXTURE_FILE_NAME);
+    fs::copy(ROAD_TEXTURE, &texture_path)
+        .map_err(|e| anyhow!("Failed to copy the road texture to {}. {e}", texture_path.display()))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::track_file::bundled_track, *};
+
+    #[test]
+    fn default_track_exports_every_part() {
+        let meshes = track_meshes(&bundled_track("default.ron"));
+
+        for mesh in &meshes {
+            assert!(!mesh.triangles.is_empty(), "{} has no triangles", mesh.material);
+            assert!(mesh.triangles.iter().flatten().all(|&i| i < mesh.vertices.len()));
+        }
+
+        // The road lies on the track surface and faces up
+        let road = meshes.iter().find(|m| m.material == "road").unwrap();
+        assert!(road.vertices.iter().all(|v| v.position.y.abs() < 1.0 && v.normal.y > 0.9));
+        let [a, b, c] = road.triangles[0].map(|i| road.vertices[i].position);
+        assert!((b - a).cross(&(c - a)).y > 0.0);
+    }
+
+    #[test]
+    fn obj_faces_use_written_vertices() {
+        let obj = to_obj(&track_meshes(&bundled_track("default.ron")), "default.mtl");
+
+        let vertex_count = obj.lines().filter(|l| l.starts_with("v ")).count();
+        let indices = obj
+            .lines()
+            .filter_map(|l| l.strip_prefix("f "))
+            .flat_map(|l| l.split(' '))
+            .flat_map(|v| v.split('/'))
+            .map(|i| i.parse::<usize>().unwrap())
+            .collect::<Vec<_>>();
+        assert!(indices.iter().all(|&i| (1..=vertex_count).contains(&i)));
+
+        let mtl = to_mtl(&export_materials());
+        for material in obj.lines().filter_map(|l| l.strip_prefix("usemtl ")) {
+            assert!(mtl.contains(&format!("newmtl {material}\n")));
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use std::{fs, path::Path};
+
+use anyhow::anyhow;
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+use crate::game_objects::environment::cactus::CactusType;
+
+use super::{track_segment::TRACK_ELEVATION, track_spline::SplinePoint};
+
+// All positions in a track file are relative to the track surface, y = 0 is on the road
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TrackFile {
+    // Folder with the six faces of the skybox cube map
+    pub skybox: String,
+    pub segments: Vec<SegmentDescription>,
+    // Where each player starts, in order of the player ids
+    pub start_grid: Vec<GridPosition>,
+    // Gates the car has to drive through in order, the first one is the finish line
+    pub checkpoints: Vec<Checkpoint>,
+    #[serde(default)]
+    pub props: Vec<Prop>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum SegmentDescription {
+    Straight {
+        position: (f32, f32, f32),
+        // In degrees, 0 runs along the z axis
+        #[serde(default)]
+        rotation: f32,
+        // In degrees, positive goes uphill in the direction of the rotation
+        #[serde(default)]
+        slope: f32,
+        // In degrees, positive raises the left side of the road
+        #[serde(default)]
+        banking: f32,
+        length: f32,
+    },
+    RightCorner {
+        position: (f32, f32, f32),
+        // In degrees
+        #[serde(default)]
+        rotation: f32,
+    },
+    UCorner {
+        position: (f32, f32, f32),
+        // In degrees
+        rotation: f32,
+    },
+    // A road through the points, with kerbs on both sides
+    Spline {
+        points: Vec<SplinePoint>,
+        // Connect the last point back to the first one
+        #[serde(default)]
+        closed: bool,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct GridPosition {
+    pub position: (f32, f32, f32),
+    // In degrees, 0 faces along the z axis
+    #[serde(default)]
+    pub angle: f32,
+}
+
+/// A gate on the track, it counts when the car drives through it in the direction the track is driven
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub enum Checkpoint {
+    // A line on the ground between two points, given as x and z.
+    // The start is on the right of the driver and the end on the left
+    Line {
+        start: (f32, f32),
+        end: (f32, f32),
+        // Of the road at the line, relative to the track surface. Cars only cross the line near this height,
+        // so a road going over or under it doesn't count
+        #[serde(default)]
+        height: f32,
+    },
+    // A box the car has to drive into, centred on a position relative to the track surface.
+    // The size is across, up and along the track, the track is driven along the z axis of the box
+    Volume {
+        centre: (f32, f32, f32),
+        size: (f32, f32, f32),
+        // In degrees
+        #[serde(default)]
+        rotation: f32,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Prop {
+    Cactus {
+        position: (f32, f32, f32),
+        // In degrees
+        #[serde(default)]
+        rotation: f32,
+        kind: CactusType,
+    },
+}
+
+impl TrackFile {
+    /// Load a track from a RON file, syntax errors are reported with the line and column
+    pub fn load(path: &Path) -> anyhow::Result<TrackFile> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read track file {}. {e}", path.display()))?;
+
+        TrackFile::parse(&text).map_err(|e| anyhow!("{}:{e}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<TrackFile, ron::error::SpannedError> {
+        ron::from_str(text)
+    }
+
+    /// Write the track to a RON file, comments in an existing file are not kept
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let text = self
+            .to_ron()
+            .map_err(|e| anyhow!("Failed to serialize track {}. {e}", path.display()))?;
+
+        fs::write(path, text).map_err(|e| anyhow!("Failed to write track file {}. {e}", path.display()))
+    }
+
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new().struct_names(false))
+    }
+
+    /// Changes when the road, the checkpoints or the props change, but not with the skybox or comments.
+    /// FNV-1a of the RON text, so it is the same on every build and platform
+    pub fn geometry_hash(&self) -> u32 {
+        let geometry = ron::to_string(&(&self.segments, &self.checkpoints, &self.props)).unwrap_or_default();
+        geometry.bytes().fold(0x811c9dc5u32, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x01000193))
+    }
+
+    /// World position of a spot in the start grid, or None if there are not enough spots for the player
+    pub fn grid_position(&self, index: usize) -> Option<(Vector3<f32>, f32)> {
+        self.start_grid
+            .get(index)
+            .map(|g| (track_position(g.position), g.angle.to_radians()))
+    }
+}
+
+/// Position relative to the track surface, as the segments take it
+pub fn segment_position(position: (f32, f32, f32)) -> Vector3<f32> {
+    Vector3::new(position.0, position.1, position.2)
+}
+
+/// Position relative to the track surface in world coordinates
+pub fn track_position(position: (f32, f32, f32)) -> Vector3<f32> {
+    Vector3::new(position.0, position.1 + TRACK_ELEVATION, position.2)
+}
+
+/// A track from the tracks folder of the repository, for tests
+#[cfg(test)]
+pub fn bundled_track(file_name: &str) -> TrackFile {
+    TrackFile::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tracks").join(file_name)).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::*;
+
+    #[test]
+    fn bundled_tracks_load() {
+        let folder = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tracks");
+        for entry in fs::read_dir(folder).unwrap() {
+            let path = entry.unwrap().path();
+            let track = TrackFile::load(&path).unwrap_or_else(|e| panic!("{e:#}"));
+            assert!(!track.segments.is_empty(), "{} has no segments", path.display());
+            assert!(!track.checkpoints.is_empty(), "{} has no finish line", path.display());
+        }
+    }
+
+    #[test]
+    fn saved_track_loads_again() {
+        let track = bundled_track("hills.ron");
+
+        let reloaded = TrackFile::parse(&track.to_ron().unwrap()).unwrap();
+        assert_eq!(reloaded.segments.len(), track.segments.len());
+        assert_eq!(reloaded.checkpoints.len(), track.checkpoints.len());
+        assert_eq!(reloaded.props.len(), track.props.len());
+    }
+}
@@ -0,0 +1,305 @@
+use std::f32::consts::TAU;
+
+use anyhow::anyhow;
+use nalgebra::Vector3;
+
+use crate::{
+    game_objects::environment::cactus::CactusType,
+    utils::{random::Random, segments_intersect},
+};
+
+use super::{
+    track_file::{Checkpoint, GridPosition, Prop, SegmentDescription, TrackFile},
+    track_segment::TRACK_WIDTH,
+    track_spline::{SplineGeometry, SplinePoint, SplineSample},
+};
+
+const SKYBOX: &str = "./models/textures/mars_skybox";
+// Shortest track that still fits the start grid and the checkpoints
+pub const MIN_TRACK_LENGTH: f32 = 400.0;
+// Closest the centre lines of two parts of the track may come, leaves room for the kerbs and barriers
+const MIN_SEPARATION: f32 = 2.0 * TRACK_WIDTH;
+// Tightest corner the generator makes, a bit wider than the U corner segment
+const MIN_CORNER_RADIUS: f32 = 30.0;
+// Average distance between two control points
+const CONTROL_POINT_SPACING: f32 = 90.0;
+// How far the control points may be moved in and out from the circle, as a fraction of its radius
+const RADIUS_VARIATION: f32 = 0.8;
+// How far the control points may be moved around the circle, as a fraction of the spacing between them
+const ANGLE_VARIATION: f32 = 0.2;
+const SMOOTHING_PASSES: usize = 2;
+// Tracks tried before giving up on a seed
+const MAX_ATTEMPTS: usize = 200;
+// Distance along the track from its start to the finish line, the start grid is in front of it
+const FINISH_LINE_DISTANCE: f32 = 80.0;
+const GRID_SIZE: usize = 7;
+const GRID_SPACING: f32 = 10.0;
+const GRID_OFFSET: f32 = 4.5;
+// Checkpoints after the finish line, spread evenly around the track
+const CHECKPOINT_COUNT: usize = 3;
+// How far the checkpoint lines reach past the edges of the road
+const CHECKPOINT_MARGIN: f32 = 5.0;
+// Track length per cactus
+const CACTUS_SPACING: f32 = 30.0;
+// Closest a cactus may be to the middle of the road
+const CACTUS_CLEARANCE: f32 = TRACK_WIDTH / 2.0 + 20.0;
+
+/// Generate a closed circuit from a single spline, with a start grid, checkpoints and cacti around it.
+///
+/// The same seed and length always give the same track. All numbers in the track are rounded,
+/// so small differences in the trigonometry of different platforms don't change it
+pub fn generate_track(seed: u64, target_length: f32) -> anyhow::Result<TrackFile> {
+    if target_length < MIN_TRACK_LENGTH {
+        return Err(anyhow!("A random track has to be at least {MIN_TRACK_LENGTH} long"));
+    }
+
+    let mut random = Random::new(seed);
+    for _ in 0..MAX_ATTEMPTS {
+        let points = control_points(&mut random, target_length);
+        let samples = SplineGeometry::new(&points, true, &Vector3::zeros()).samples;
+
+        if !is_drivable(&samples) {
+            continue;
+        }
+
+        let props = scatter_cacti(&mut random, &samples, target_length);
+        return Ok(TrackFile {
+            skybox: SKYBOX.to_string(),
+            segments: vec![SegmentDescription::Spline { points, closed: true }],
+            start_grid: start_grid(&samples),
+            checkpoints: checkpoints(&samples),
+            props,
+        });
+    }
+
+    Err(anyhow!("Failed to generate a track for seed {seed} after {MAX_ATTEMPTS} attempts"))
+}
+
+/// Points around a circle, moved randomly in and out, then scaled until the track has the length
+fn control_points(random: &mut Random, target_length: f32) -> Vec<SplinePoint> {
+    let count = ((target_length / CONTROL_POINT_SPACING).round() as usize).clamp(8, 24);
+    let step = TAU / count as f32;
+
+    let angles = (0..count)
+        .map(|i| i as f32 * step + random.range(-ANGLE_VARIATION, ANGLE_VARIATION) * step)
+        .collect::<Vec<_>>();
+    let mut radii = (0..count)
+        .map(|_| 1.0 + random.range(-RADIUS_VARIATION, RADIUS_VARIATION))
+        .collect::<Vec<_>>();
+
+    // Sudden changes of the radius make hairpins, so each radius is averaged with its neighbours
+    for _ in 0..SMOOTHING_PASSES {
+        radii = (0..count)
+            .map(|i| (radii[(i + count - 1) % count] + 2.0 * radii[i] + radii[(i + 1) % count]) / 4.0)
+            .collect();
+    }
+
+    // Increasing angles around the centre keep the control points from crossing each other
+    let mut positions = angles
+        .iter()
+        .zip(&radii)
+        .map(|(angle, radius)| Vector3::new(radius * angle.sin(), 0.0, radius * angle.cos()))
+        .collect::<Vec<_>>();
+
+    let mut scale = target_length / TAU;
+    for _ in 0..4 {
+        let points = spline_points(&positions, scale);
+        let length = track_length(&SplineGeometry::new(&points, true, &Vector3::zeros()).samples);
+        scale *= target_length / length;
+    }
+    positions.iter_mut().for_each(|p| *p *= scale);
+
+    spline_points(&positions, 1.0)
+}
+
+fn spline_points(positions: &[Vector3<f32>], scale: f32) -> Vec<SplinePoint> {
+    positions
+        .iter()
+        .map(|p| SplinePoint {
+            position: (round(p.x * scale), 0.0, round(p.z * scale)),
+            width: TRACK_WIDTH,
+            banking: 0.0,
+        })
+        .collect()
+}
+
+fn track_length(samples: &[SplineSample]) -> f32 {
+    samples.last().map(|s| s.distance).unwrap_or(0.0)
+}
+
+/// If the track doesn't cross or come close to itself and has no corners too tight to drive
+pub fn is_drivable(samples: &[SplineSample]) -> bool {
+    let length = track_length(samples);
+    if samples.len() < 3 || length <= 0.0 {
+        return false;
+    }
+
+    // Turning angle per distance driven is one over the radius of the corner
+    let too_tight = samples.windows(2).any(|s| {
+        let distance = s[1].distance - s[0].distance;
+        let angle = s[0].tangent.xz().angle(&s[1].tangent.xz());
+        distance > 0.0 && angle / distance > 1.0 / MIN_CORNER_RADIUS
+    });
+    if too_tight {
+        return false;
+    }
+
+    // Parts of the track close to each other along the road are allowed to be close in space
+    let neighbour_distance = MIN_SEPARATION * std::f32::consts::FRAC_PI_2;
+    let is_neighbour = |a: &SplineSample, b: &SplineSample| {
+        let along = (a.distance - b.distance).abs();
+        along.min(length - along) < neighbour_distance
+    };
+
+    for (i, a) in samples.iter().enumerate() {
+        for b in &samples[i + 1..] {
+            if !is_neighbour(a, b) && (a.centre - b.centre).xz().norm() < MIN_SEPARATION {
+                return false;
+            }
+        }
+    }
+
+    let lines = samples.windows(2).map(|s| (s[0].centre.xz(), s[1].centre.xz())).collect::<Vec<_>>();
+    for (i, (a1, a2)) in lines.iter().enumerate() {
+        // Lines next to each other share an end, and the last one ends where the first one starts
+        for (b1, b2) in &lines[(i + 2).min(lines.len())..lines.len() - usize::from(i == 0)] {
+            if segments_intersect(a1, a2, b1, b2) {
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+/// First sample at least the distance along the track, wrapping around past the end
+fn sample_at(samples: &[SplineSample], distance: f32) -> &SplineSample {
+    let distance = distance.rem_euclid(track_length(samples));
+    samples
+        .iter()
+        .find(|s| s.distance >= distance)
+        .unwrap_or(&samples[samples.len() - 1])
+}
+
+/// Two columns behind the finish line, the first spot on the right
+fn start_grid(samples: &[SplineSample]) -> Vec<GridPosition> {
+    (0..GRID_SIZE)
+        .map(|i| {
+            let sample = sample_at(samples, FINISH_LINE_DISTANCE - GRID_SPACING * (i + 1) as f32);
+            let side = if i % 2 == 0 { -GRID_OFFSET } else { GRID_OFFSET };
+            let position = sample.centre + sample.lateral * side;
+
+            GridPosition {
+                position: (round(position.x), 0.0, round(position.z)),
+                angle: round(f32::atan2(sample.tangent.x, sample.tangent.z).to_degrees()),
+            }
+        })
+        .collect()
+}
+
+/// The finish line and then checkpoints spread evenly around the track
+fn checkpoints(samples: &[SplineSample]) -> Vec<Checkpoint> {
+    let spacing = track_length(samples) / (CHECKPOINT_COUNT + 1) as f32;
+
+    (0..=CHECKPOINT_COUNT)
+        .map(|i| {
+            let sample = sample_at(samples, FINISH_LINE_DISTANCE + spacing * i as f32);
+            let start = sample.right_edge() - sample.lateral * CHECKPOINT_MARGIN;
+            let end = sample.left_edge() + sample.lateral * CHECKPOINT_MARGIN;
+
+            Checkpoint::Line {
+                start: (round(start.x), round(start.z)),
+                end: (round(end.x), round(end.z)),
+                height: 0.0,
+            }
+        })
+        .collect()
+}
+
+/// Cacti somewhere around the track, but never on the road
+fn scatter_cacti(random: &mut Random, samples: &[SplineSample], target_length: f32) -> Vec<Prop> {
+    let (mut min, mut max) = (samples[0].centre, samples[0].centre);
+    for s in samples {
+        min = min.inf(&s.centre);
+        max = max.sup(&s.centre);
+    }
+    let margin = Vector3::new(60.0, 0.0, 60.0);
+    let (min, max) = (min - margin, max + margin);
+
+    let count = (target_length / CACTUS_SPACING) as usize;
+    let mut props = Vec::with_capacity(count);
+    for _ in 0..count * 20 {
+        if props.len() == count {
+            break;
+        }
+
+        let position = Vector3::new(random.range(min.x, max.x), 0.0, random.range(min.z, max.z));
+        let rotation = random.range(0.0, 360.0);
+        let kind = if random.chance(0.5) { CactusType::Small } else { CactusType::Large };
+
+        let on_road = samples
+            .iter()
+            .any(|s| (s.centre - position).xz().norm() < CACTUS_CLEARANCE);
+        if !on_road {
+            props.push(Prop::Cactus {
+                position: (round(position.x), 0.0, round(position.z)),
+                rotation: round(rotation),
+                kind,
+            });
+        }
+    }
+
+    props
+}
+
+/// Round to two decimals
+fn round(value: f32) -> f32 {
+    (value * 100.0).round() / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_gives_same_track() {
+        let a = generate_track(1234, 1200.0).unwrap();
+        let b = generate_track(1234, 1200.0).unwrap();
+        assert_eq!(a.to_ron().unwrap(), b.to_ron().unwrap());
+
+        let c = generate_track(4321, 1200.0).unwrap();
+        assert_ne!(a.to_ron().unwrap(), c.to_ron().unwrap());
+    }
+
+    #[test]
+    fn generated_tracks_are_drivable() {
+        for seed in 0..20 {
+            let length = 600.0 + 100.0 * seed as f32;
+            let track = generate_track(seed, length).unwrap();
+
+            let SegmentDescription::Spline { points, closed: true } = &track.segments[0] else {
+                panic!("seed {seed} did not make a closed spline");
+            };
+            let samples = SplineGeometry::new(points, true, &Vector3::zeros()).samples;
+            assert!(is_drivable(&samples), "seed {seed} is not drivable");
+            assert!((track_length(&samples) - length).abs() < length * 0.05, "seed {seed} has the wrong length");
+
+            assert_eq!(track.start_grid.len(), GRID_SIZE);
+            assert_eq!(track.checkpoints.len(), CHECKPOINT_COUNT + 1);
+            assert!(!track.props.is_empty());
+        }
+    }
+
+    #[test]
+    fn crossing_track_is_not_drivable() {
+        // A figure eight with wide corners, it only fails by crossing itself
+        let points = (0..16)
+            .map(|i| {
+                let t = i as f32 * TAU / 16.0;
+                SplinePoint { position: (300.0 * t.sin(), 0.0, 300.0 * t.sin() * t.cos()), width: TRACK_WIDTH, banking: 0.0 }
+            })
+            .collect::<Vec<_>>();
+        let samples = SplineGeometry::new(&points, true, &Vector3::zeros()).samples;
+        assert!(!is_drivable(&samples));
+    }
+}
@@ -0,0 +1,87 @@
+use glow::Context;
+use itertools::Itertools;
+use nalgebra::Vector3;
+use sdl2::event::Event;
+
+use crate::core::{
+    game::Game,
+    game_object::{Collider, GameObject},
+    matrices::ModelMatrix,
+    surface::Surface,
+};
+
+use super::{
+    track_segment::{draw_pieces, strip_triangles, TrackPiece, KERB_MATERIAL, KERB_RED, PIECE_WHITE},
+    track_spline::pitch,
+};
+
+pub const KERB_WIDTH: f32 = 2.0;
+const KERB_HEIGHT: f32 = 0.1;
+
+pub struct TrackKerb {
+    pieces: Vec<TrackPiece>,
+    collider: Collider,
+}
+
+impl TrackKerb {
+    /// The edges are pairs of points on the outer and inner edge of the kerb in world coordinates
+    pub fn new(edges: Vec<(Vector3<f32>, Vector3<f32>)>) -> TrackKerb {
+        let collider = Collider::SurfaceCollider(strip_triangles(&edges), Surface::Kerb);
+
+        TrackKerb { pieces: TrackKerb::create_pieces(&edges), collider }
+    }
+
+    pub fn pieces(&self) -> &[TrackPiece] {
+        &self.pieces
+    }
+
+    /// Red and white blocks along the kerb, every colour covers two pairs of edges
+    fn create_pieces(edges: &[(Vector3<f32>, Vector3<f32>)]) -> Vec<TrackPiece> {
+        let mut model_matrix = ModelMatrix::new();
+
+        edges
+            .iter()
+            .tuple_windows()
+            .enumerate()
+            .map(|(i, ((outer1, inner1), (outer2, inner2)))| {
+                let color = if (i / 2) % 2 == 0 {
+                    KERB_RED
+                } else {
+                    PIECE_WHITE
+                };
+
+                let start = 0.5 * outer1 + 0.5 * inner1;
+                let end = 0.5 * outer2 + 0.5 * inner2;
+                let center = 0.5 * start + 0.5 * end;
+                let v = end - start;
+                let rot = f32::atan2(v.x, v.z);
+
+                model_matrix.push_stack();
+                model_matrix.add_translate(center.x, center.y + KERB_HEIGHT / 2.0, center.z);
+                model_matrix.add_rotation(0.0, rot, 0.0);
+                model_matrix.add_rotation(pitch(&v), 0.0, 0.0);
+                model_matrix.add_scale((outer1 - inner1).norm(), KERB_HEIGHT, v.norm());
+                let matrix = model_matrix.matrix;
+                model_matrix.pop_stack();
+
+                TrackPiece { matrix, color }
+            })
+            .collect()
+    }
+}
+
+impl<'a> GameObject<'a> for TrackKerb {
+    fn collision_info(&self) -> Collider {
+        self.collider.clone()
+    }
+
+    fn on_event(&mut self, _game: &Game, _event: &Event) {}
+
+    fn update(&mut self, _game: &Game, _gl: &'a Context) {}
+
+    fn display(&self, game: &Game, _gl: &'a Context) {
+        KERB_MATERIAL.set(game);
+
+        draw_pieces(game, &self.pieces);
+    }
+}
@@ -1,57 +1,96 @@
 use glow::{Context, NativeTexture};
-use nalgebra::Vector3;
+use nalgebra::{Matrix4, Vector3};
 
 use crate::{
     core::{
-        color::Color,
         game::Game,
         game_object::{Collider, GameObject},
+        matrices::ModelMatrix,
     },
     objects::track_corner::{TrackCorner, TrackCornerType},
 };
 
 use super::{
-    track_segment::{TRACK_BOX_HEIGHT, TRACK_ELEVATION, TRACK_WIDTH},
+    track_kerb::TrackKerb,
+    track_segment::{
+        corner_geometry, CornerGeometry, PLATFORM_MATERIAL, ROAD_MATERIAL, TRACK_BOX_HEIGHT, TRACK_ELEVATION, TRACK_WIDTH,
+    },
     track_side::{TrackSide, self},
 };
 
+// The corner mesh is modelled turning the other way
+pub const CORNER_ROTATION: f32 = 3.0 * std::f32::consts::FRAC_PI_2;
+
 pub struct TrackRightCornerSegment<'a> {
     road_texture: NativeTexture,
     segemnt_object: TrackCorner<'a>,
     position: Vector3<f32>,
+    // Yaw in radians, at 0 the corner is entered going along the x axis and left going along the z axis
+    rotation: f32,
     sides: TrackSide,
+    road_collider: Collider,
+    kerb: TrackKerb,
+    centre_line: Vec<Vector3<f32>>,
 }
 
 impl<'a> TrackRightCornerSegment<'a> {
     pub fn new(
         position: Vector3<f32>,
+        rotation: f32,
+        road_texture: NativeTexture,
         gl: &'a Context,
-        game: &Game,
     ) -> TrackRightCornerSegment<'a> {
-        let road_texture = game.load_texture("./models/textures/road.png", true);
         let segemnt_object = TrackCorner::new(gl, TrackCornerType::Right);
 
         let pos = position + Vector3::new(0.0, TRACK_ELEVATION + 0.5, 0.0);
         let sides = TrackSide::new(
             pos,
-            0.0,
+            rotation,
+            (0.0, 0.0),
             20.0,
             track_side::TrackSegmentSideType::RightCorner,
-            game,
         );
+        let CornerGeometry { road_collider, kerb, centre_line, .. } =
+            corner_geometry(&TrackCornerType::Right, &position, rotation + CORNER_ROTATION);
 
         TrackRightCornerSegment {
             road_texture,
             segemnt_object,
             position,
+            rotation,
             sides,
+            road_collider,
+            kerb,
+            centre_line,
         }
     }
+
+    pub fn centre_line(&self) -> &Vec<Vector3<f32>> {
+        &self.centre_line
+    }
+}
+
+/// Places a unit cube as the platform under the corner, the rotation is in radians
+pub fn right_corner_platform_matrix(position: &Vector3<f32>, rotation: f32) -> Matrix4<f32> {
+    let mut model_matrix = ModelMatrix::new();
+    model_matrix.add_translate(
+        position.x,
+        (position.y + TRACK_ELEVATION - (TRACK_BOX_HEIGHT / 2.0)) - 0.02,
+        position.z,
+    );
+    model_matrix.add_rotation(0.0, rotation + CORNER_ROTATION, 0.0);
+    model_matrix.add_translate(-40.0, 0.0, -40.0);
+    model_matrix.add_scale(120.0, TRACK_BOX_HEIGHT, 120.0);
+    model_matrix.matrix
 }
 
 impl<'a> GameObject<'a> for TrackRightCornerSegment<'a> {
     fn collision_info(&self) -> Collider {
-        self.sides.collision_info()
+        Collider::MultiCollider(vec![
+            self.sides.collision_info(),
+            self.road_collider.clone(),
+            self.kerb.collision_info(),
+        ])
     }
 
     fn on_event(&mut self, _game: &Game, _event: &sdl2::event::Event) {}
@@ -60,46 +99,26 @@ impl<'a> GameObject<'a> for TrackRightCornerSegment<'a> {
 
     fn display(&self, game: &Game, gl: &'a Context) {
         self.sides.display(game, gl);
+        self.kerb.display(game, gl);
 
         let mut model_matrix = game.model_matrix.borrow_mut();
 
         // Pavement
-        game.shader
-            .set_material_ambient(&Color::new(0.84 / 1.5, 0.73 / 1.5, 0.67 / 1.5));
-        game.shader
-            .set_material_diffuse(&Color::new(0.84, 0.73, 0.67));
-        game.shader
-            .set_material_specular(&Color::new(0.2, 0.2, 0.2));
-        game.shader.set_shininess(100.0);
+        ROAD_MATERIAL.set(game);
 
         model_matrix.push_stack();
         model_matrix.add_translate(self.position.x, TRACK_ELEVATION + 0.1, self.position.z);
         model_matrix.add_scale(TRACK_WIDTH * 10.0, 1.0, TRACK_WIDTH * 10.0);
-        model_matrix.add_rotation(0.0, 270f32.to_radians(), 0.0);
+        model_matrix.add_rotation(0.0, self.rotation + CORNER_ROTATION, 0.0);
         game.shader.set_model_matrix(model_matrix.matrix.as_slice());
         self.segemnt_object.draw(&game.shader, &self.road_texture);
         model_matrix.pop_stack();
 
         // Platform
-        game.shader
-            .set_material_ambient(&Color::new(0.96 / 1.5, 0.58 / 1.5, 0.38 / 1.5));
-        game.shader
-            .set_material_diffuse(&Color::new(0.96, 0.58, 0.38));
-        game.shader
-            .set_material_specular(&Color::new(0.1, 0.1, 0.1));
-        game.shader.set_shininess(100.0);
+        PLATFORM_MATERIAL.set(game);
 
-        model_matrix.push_stack();
-        model_matrix.add_translate(
-            self.position.x,
-            (self.position.y + TRACK_ELEVATION - (TRACK_BOX_HEIGHT / 2.0)) - 0.02,
-            self.position.z,
-        );
-        model_matrix.add_rotation(0.0, 270f32.to_radians(), 0.0);
-        model_matrix.add_translate(-40.0, 0.0, -40.0);
-        model_matrix.add_scale(120.0, TRACK_BOX_HEIGHT, 120.0);
-        game.shader.set_model_matrix(model_matrix.matrix.as_slice());
+        let platform = right_corner_platform_matrix(&self.position, self.rotation);
+        game.shader.set_model_matrix((model_matrix.matrix * platform).as_slice());
         game.cube.draw(&game.shader);
-        model_matrix.pop_stack();
     }
 }
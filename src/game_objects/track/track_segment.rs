@@ -1,17 +1,54 @@
 use glow::Context;
+use itertools::Itertools;
+use nalgebra::{Matrix4, Vector3, Vector4};
 
 use crate::{
     core::{
+        color::Color,
         game::Game,
         game_object::{Collider, GameObject},
+        matrices::ModelMatrix,
+        surface::Surface,
     },
+    objects::track_corner::{TrackCorner, TrackCornerType},
 };
 
-use super::{track_straight_segment::TrackStraightSegment, track_right_corner_segment::TrackRightCornerSegment, track_u_corner_segment::TrackUCornerSegment};
+use super::{track_straight_segment::TrackStraightSegment, track_right_corner_segment::TrackRightCornerSegment, track_u_corner_segment::TrackUCornerSegment, track_spline_segment::TrackSplineSegment, track_kerb::{TrackKerb, KERB_WIDTH}};
 
 pub const TRACK_ELEVATION: f32 = 30.0;
 pub const TRACK_BOX_HEIGHT: f32 = 5.0;
 pub const TRACK_WIDTH: f32 = 20.0;
+pub const ROAD_TEXTURE: &str = "./models/textures/road.png";
+
+// The blocks of the barriers and kerbs alternate between their red and white
+pub const BARRIER_RED: Color = Color::new(1.0, 0.0, 0.0);
+pub const KERB_RED: Color = Color::new(0.9, 0.1, 0.1);
+pub const PIECE_WHITE: Color = Color::new(1.0, 1.0, 1.0);
+
+pub const ROAD_MATERIAL: TrackMaterial = TrackMaterial {
+    ambient: Color::new(0.84 / 1.5, 0.73 / 1.5, 0.67 / 1.5),
+    diffuse: Color::new(0.84, 0.73, 0.67),
+    specular: Color::new(0.2, 0.2, 0.2),
+    shininess: 100.0,
+};
+pub const PLATFORM_MATERIAL: TrackMaterial = TrackMaterial {
+    ambient: Color::new(0.96 / 1.5, 0.58 / 1.5, 0.38 / 1.5),
+    diffuse: Color::new(0.96, 0.58, 0.38),
+    specular: Color::new(0.1, 0.1, 0.1),
+    shininess: 100.0,
+};
+pub const BARRIER_MATERIAL: TrackMaterial = TrackMaterial {
+    ambient: BARRIER_RED,
+    diffuse: BARRIER_RED,
+    specular: Color::new(1.0, 1.0, 1.0),
+    shininess: 3.0,
+};
+pub const KERB_MATERIAL: TrackMaterial = TrackMaterial {
+    ambient: KERB_RED,
+    diffuse: KERB_RED,
+    specular: Color::new(0.5, 0.5, 0.5),
+    shininess: 10.0,
+};
 
 pub enum TrackSegment<'a> {
     // Position, direction, length
@@ -20,6 +57,21 @@ pub enum TrackSegment<'a> {
     RightCorner(TrackRightCornerSegment<'a>),
     // Position, rotation
     UCorner(TrackUCornerSegment<'a>),
+    // Control points
+    Spline(TrackSplineSegment<'a>),
+}
+
+impl<'a> TrackSegment<'a> {
+    /// Points along the middle of the road in world coordinates
+    pub fn centre_line(&self) -> &Vec<Vector3<f32>> {
+        use TrackSegment::*;
+        match self {
+            Straight (s) => s.centre_line(),
+            RightCorner (s) => s.centre_line(),
+            UCorner (s) => s.centre_line(),
+            Spline (s) => s.centre_line(),
+        }
+    }
 }
 
 impl<'a> GameObject<'a> for TrackSegment<'a> {
@@ -29,6 +81,7 @@ impl<'a> GameObject<'a> for TrackSegment<'a> {
             Straight (s) => s.collision_info(),
             RightCorner (s) => s.collision_info(),
             UCorner (s) => s.collision_info(),
+            Spline (s) => s.collision_info(),
         }
     }
 
@@ -38,6 +91,7 @@ impl<'a> GameObject<'a> for TrackSegment<'a> {
             Straight (s) => s.on_event(game, event),
             RightCorner (s) => s.on_event(game, event),
             UCorner (s) => s.on_event(game, event),
+            Spline (s) => s.on_event(game, event),
         }
     }
 
@@ -47,6 +101,7 @@ impl<'a> GameObject<'a> for TrackSegment<'a> {
             Straight (s) => s.update(game, gl),
             RightCorner (s) => s.update(game, gl),
             UCorner (s) => s.update(game, gl),
+            Spline (s) => s.update(game, gl),
         }
     }
 
@@ -56,6 +111,103 @@ impl<'a> GameObject<'a> for TrackSegment<'a> {
             Straight (s) => s.display(game, gl),
             RightCorner (s) => s.display(game, gl),
             UCorner (s) => s.display(game, gl),
+            Spline (s) => s.display(game, gl),
         }
     }
-}
\ No newline at end of file
+}
+
+/// Triangles covering the strip between two edges, given as pairs of points on each edge
+pub fn strip_triangles(edges: &[(Vector3<f32>, Vector3<f32>)]) -> Vec<[Vector3<f32>; 3]> {
+    edges
+        .iter()
+        .tuple_windows()
+        .flat_map(|((a1, b1), (a2, b2))| [[*a1, *b1, *a2], [*b1, *b2, *a2]])
+        .collect()
+}
+
+/// Tilt a segment that has already been rotated to its heading, positive slope goes uphill along the
+/// local z axis and positive banking raises the left side
+pub fn add_tilt(model_matrix: &mut ModelMatrix, slope: f32, banking: f32) {
+    model_matrix.add_rotation(-slope, 0.0, 0.0);
+    model_matrix.add_rotation(0.0, 0.0, banking);
+}
+
+/// How a part of the track is lit, the track export writes the same materials
+#[derive(Clone, Copy, Debug)]
+pub struct TrackMaterial {
+    pub ambient: Color,
+    pub diffuse: Color,
+    pub specular: Color,
+    pub shininess: f32,
+}
+
+impl TrackMaterial {
+    pub fn set(&self, game: &Game) {
+        game.shader.set_material_ambient(&self.ambient);
+        game.shader.set_material_diffuse(&self.diffuse);
+        game.shader.set_material_specular(&self.specular);
+        game.shader.set_shininess(self.shininess);
+    }
+}
+
+/// A coloured box that is part of a segment, like a barrier or kerb block
+#[derive(Clone, Copy, Debug)]
+pub struct TrackPiece {
+    // Places a unit cube in world coordinates
+    pub matrix: Matrix4<f32>,
+    pub color: Color,
+}
+
+/// Draw the pieces with the material that is already set, only the colour changes between them
+pub fn draw_pieces(game: &Game, pieces: &[TrackPiece]) {
+    let model_matrix = game.model_matrix.borrow();
+
+    for piece in pieces {
+        game.shader.set_material_ambient(&piece.color);
+        game.shader.set_material_diffuse(&piece.color);
+        game.shader.set_model_matrix((model_matrix.matrix * piece.matrix).as_slice());
+        game.cube.draw(&game.shader);
+    }
+}
+
+pub struct CornerGeometry {
+    // Outer edge of the road and the edge of the kerb
+    pub road_edges: Vec<(Vector3<f32>, Vector3<f32>)>,
+    pub road_collider: Collider,
+    pub kerb: TrackKerb,
+    pub centre_line: Vec<Vector3<f32>>,
+}
+
+/// Road collider, kerb on the inside and centre line of a corner, placed the same way as the corner is drawn
+pub fn corner_geometry(
+    corner_type: &TrackCornerType,
+    position: &Vector3<f32>,
+    rotation: f32,
+) -> CornerGeometry {
+    let mut model_matrix = ModelMatrix::new();
+    model_matrix.add_translate(position.x, TRACK_ELEVATION + 0.1, position.z);
+    model_matrix.add_scale(TRACK_WIDTH * 10.0, 1.0, TRACK_WIDTH * 10.0);
+    model_matrix.add_rotation(0.0, rotation, 0.0);
+
+    let edges = TrackCorner::edges(corner_type)
+        .iter()
+        .map(|(outer, inner)| {
+            let outer = (model_matrix.matrix * Vector4::new(outer.x, outer.y, outer.z, 1.0)).xyz();
+            let inner = (model_matrix.matrix * Vector4::new(inner.x, inner.y, inner.z, 1.0)).xyz();
+            let kerb = inner + (outer - inner).normalize() * KERB_WIDTH;
+
+            (outer, kerb, inner)
+        })
+        .collect::<Vec<_>>();
+
+    let road_edges = edges.iter().map(|&(outer, kerb, _)| (outer, kerb)).collect::<Vec<_>>();
+    let kerb_edges = edges.iter().map(|&(_, kerb, inner)| (kerb, inner)).collect();
+    let centre_line = edges.iter().map(|&(outer, _, inner)| 0.5 * outer + 0.5 * inner).collect();
+
+    CornerGeometry {
+        road_collider: Collider::SurfaceCollider(strip_triangles(&road_edges), Surface::Asphalt),
+        road_edges,
+        kerb: TrackKerb::new(kerb_edges),
+        centre_line,
+    }
+}
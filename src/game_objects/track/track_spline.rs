@@ -0,0 +1,324 @@
+use itertools::Itertools;
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+use crate::core::{game_object::Collider, surface::Surface};
+
+use super::{
+    track_kerb::KERB_WIDTH,
+    track_segment::{strip_triangles, TRACK_WIDTH},
+};
+
+// Points sampled between two control points
+const SPLINE_ACCURACY: usize = 12;
+// Distance from the edge of the road to the middle of the barrier
+const BARRIER_OFFSET: f32 = 0.5;
+// Extra width of the platform on each side of the road
+const PLATFORM_MARGIN: f32 = 2.5;
+
+/// A control point of a spline road, the road passes through all of them
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct SplinePoint {
+    pub position: (f32, f32, f32),
+    #[serde(default = "default_width")]
+    pub width: f32,
+    // In degrees, positive raises the left side of the road
+    #[serde(default)]
+    pub banking: f32,
+}
+
+fn default_width() -> f32 {
+    TRACK_WIDTH
+}
+
+/// One sample along the spline, everything in world coordinates
+#[derive(Clone, Copy, Debug)]
+pub struct SplineSample {
+    pub centre: Vector3<f32>,
+    // In the driving direction, follows the slope of the road
+    pub tangent: Vector3<f32>,
+    // Points from the centre to the left edge, tilted by the banking
+    pub lateral: Vector3<f32>,
+    pub width: f32,
+    // Distance along the centre line from the first sample
+    pub distance: f32,
+}
+
+impl SplineSample {
+    pub fn left_edge(&self) -> Vector3<f32> {
+        self.centre + self.lateral * (self.width / 2.0)
+    }
+
+    pub fn right_edge(&self) -> Vector3<f32> {
+        self.centre - self.lateral * (self.width / 2.0)
+    }
+
+    pub fn normal(&self) -> Vector3<f32> {
+        self.tangent.cross(&self.lateral).normalize()
+    }
+}
+
+/// A vertex of the road mesh, the road is drawn as a triangle strip going left, right, left, right...
+#[derive(Clone, Copy, Debug)]
+pub struct RoadVertex {
+    pub position: Vector3<f32>,
+    pub normal: Vector3<f32>,
+    pub uv: (f32, f32),
+}
+
+/// Everything needed to draw and collide with a spline road
+pub struct SplineGeometry {
+    pub samples: Vec<SplineSample>,
+    pub road_vertices: Vec<RoadVertex>,
+    pub road_collider: Collider,
+    // Outer and inner edge of the kerbs on both sides
+    pub left_kerb: Vec<(Vector3<f32>, Vector3<f32>)>,
+    pub right_kerb: Vec<(Vector3<f32>, Vector3<f32>)>,
+    // Lines along the middle of the barriers on both sides
+    pub left_barrier: Vec<Vector3<f32>>,
+    pub right_barrier: Vec<Vector3<f32>>,
+    pub barrier_colliders: Vec<Collider>,
+    pub centre_line: Vec<Vector3<f32>>,
+}
+
+impl SplineGeometry {
+    /// Build the road through the control points, offset is added to all of them.
+    /// A closed spline connects the last point back to the first one
+    pub fn new(points: &[SplinePoint], closed: bool, offset: &Vector3<f32>) -> SplineGeometry {
+        let samples = SplineGeometry::sample(points, closed, offset);
+
+        // The kerbs have their own surface, so they are left out of the road collider
+        let road_edges = samples
+            .iter()
+            .map(|s| (s.left_edge() - s.lateral * KERB_WIDTH, s.right_edge() + s.lateral * KERB_WIDTH))
+            .collect::<Vec<_>>();
+
+        let road_vertices = samples
+            .iter()
+            .flat_map(|s| {
+                // The road texture repeats every TRACK_WIDTH along the road
+                let v = s.distance / TRACK_WIDTH;
+                [
+                    RoadVertex { position: s.left_edge(), normal: s.normal(), uv: (0.0, v) },
+                    RoadVertex { position: s.right_edge(), normal: s.normal(), uv: (1.0, v) },
+                ]
+            })
+            .collect();
+
+        let left_kerb = samples
+            .iter()
+            .map(|s| (s.left_edge(), s.left_edge() - s.lateral * KERB_WIDTH))
+            .collect();
+        let right_kerb = samples
+            .iter()
+            .map(|s| (s.right_edge(), s.right_edge() + s.lateral * KERB_WIDTH))
+            .collect();
+
+        let left_barrier = samples
+            .iter()
+            .map(|s| s.left_edge() + s.lateral * BARRIER_OFFSET)
+            .collect::<Vec<_>>();
+        let right_barrier = samples
+            .iter()
+            .map(|s| s.right_edge() - s.lateral * BARRIER_OFFSET)
+            .collect::<Vec<_>>();
+        let barrier_colliders = [&left_barrier, &right_barrier]
+            .iter()
+            .flat_map(|barrier| {
+                barrier
+                    .iter()
+                    .tuple_windows()
+                    .map(|(p0, p1)| Collider::InfiniteYPlaneCollider(*p0, *p1))
+            })
+            .collect();
+
+        SplineGeometry {
+            road_collider: Collider::SurfaceCollider(strip_triangles(&road_edges), Surface::Asphalt),
+            road_vertices,
+            left_kerb,
+            right_kerb,
+            left_barrier,
+            right_barrier,
+            barrier_colliders,
+            centre_line: samples.iter().map(|s| s.centre).collect(),
+            samples,
+        }
+    }
+
+    /// Boxes under the road, as centre, heading, pitch, width and length
+    pub fn platform(&self) -> Vec<(Vector3<f32>, f32, f32, f32, f32)> {
+        self.samples
+            .iter()
+            .tuple_windows()
+            .map(|(s1, s2)| {
+                let v = s2.centre - s1.centre;
+                let width = s1.width.max(s2.width) + 2.0 * (KERB_WIDTH + PLATFORM_MARGIN);
+                (0.5 * s1.centre + 0.5 * s2.centre, f32::atan2(v.x, v.z), pitch(&v), width, v.norm())
+            })
+            .collect()
+    }
+
+    fn sample(points: &[SplinePoint], closed: bool, offset: &Vector3<f32>) -> Vec<SplineSample> {
+        if points.len() < 2 {
+            return Vec::new();
+        }
+
+        let position = |i: isize| -> Vector3<f32> {
+            let count = points.len() as isize;
+            let p = if closed {
+                points[i.rem_euclid(count) as usize].position
+            } else if i < 0 {
+                // Extend the ends in a straight line
+                let (p0, p1) = (points[0].position, points[1].position);
+                (2.0 * p0.0 - p1.0, 2.0 * p0.1 - p1.1, 2.0 * p0.2 - p1.2)
+            } else if i >= count {
+                let (p0, p1) = (points[count as usize - 1].position, points[count as usize - 2].position);
+                (2.0 * p0.0 - p1.0, 2.0 * p0.1 - p1.1, 2.0 * p0.2 - p1.2)
+            } else {
+                points[i as usize].position
+            };
+            Vector3::new(p.0, p.1, p.2) + offset
+        };
+
+        let spans = if closed { points.len() } else { points.len() - 1 };
+        let mut samples: Vec<SplineSample> = Vec::with_capacity(spans * SPLINE_ACCURACY + 1);
+        for span in 0..spans {
+            let i = span as isize;
+            let (p0, p1, p2, p3) = (position(i - 1), position(i), position(i + 1), position(i + 2));
+            let (start, end) = (&points[span], &points[(span + 1) % points.len()]);
+
+            let last_span = span == spans - 1;
+            let sample_count = if last_span { SPLINE_ACCURACY + 1 } else { SPLINE_ACCURACY };
+            for j in 0..sample_count {
+                let t = j as f32 / SPLINE_ACCURACY as f32;
+                let centre = catmull_rom(&p0, &p1, &p2, &p3, t);
+                let derivative = catmull_rom_derivative(&p0, &p1, &p2, &p3, t);
+
+                let tangent = derivative
+                    .try_normalize(f32::EPSILON)
+                    .unwrap_or_else(|| Vector3::new(0.0, 0.0, 1.0));
+                let banking = (start.banking + (end.banking - start.banking) * t).to_radians();
+                let left = Vector3::new(tangent.z, 0.0, -tangent.x)
+                    .try_normalize(f32::EPSILON)
+                    .unwrap_or_else(|| Vector3::new(1.0, 0.0, 0.0));
+                let lateral = left * banking.cos() + Vector3::new(0.0, banking.sin(), 0.0);
+
+                let distance = samples
+                    .last()
+                    .map(|last| last.distance + (centre - last.centre).norm())
+                    .unwrap_or(0.0);
+
+                samples.push(SplineSample {
+                    centre,
+                    tangent,
+                    lateral,
+                    width: start.width + (end.width - start.width) * t,
+                    distance,
+                });
+            }
+        }
+
+        samples
+    }
+}
+
+/// Rotation around the x axis that tilts the z axis along a direction, after it has been turned to face it
+pub fn pitch(direction: &Vector3<f32>) -> f32 {
+    f32::atan2(-direction.y, direction.xz().norm())
+}
+
+fn catmull_rom(
+    p0: &Vector3<f32>,
+    p1: &Vector3<f32>,
+    p2: &Vector3<f32>,
+    p3: &Vector3<f32>,
+    t: f32,
+) -> Vector3<f32> {
+    0.5 * (2.0 * p1
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t.powi(2)
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t.powi(3))
+}
+
+fn catmull_rom_derivative(
+    p0: &Vector3<f32>,
+    p1: &Vector3<f32>,
+    p2: &Vector3<f32>,
+    p3: &Vector3<f32>,
+    t: f32,
+) -> Vector3<f32> {
+    0.5 * ((p2 - p0)
+        + 2.0 * (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t
+        + 3.0 * (3.0 * p1 - p0 - 3.0 * p2 + p3) * t.powi(2))
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use super::*;
+
+    fn point(x: f32, z: f32, banking: f32) -> SplinePoint {
+        SplinePoint { position: (x, 0.0, z), width: TRACK_WIDTH, banking }
+    }
+
+    #[test]
+    fn straight_spline_follows_the_points() {
+        let points = [point(0.0, 0.0, 0.0), point(0.0, 50.0, 0.0), point(0.0, 100.0, 0.0)];
+        let geometry = SplineGeometry::new(&points, false, &Vector3::zeros());
+
+        assert_eq!(geometry.samples.len(), 2 * SPLINE_ACCURACY + 1);
+        assert!((geometry.centre_line.last().unwrap() - Vector3::new(0.0, 0.0, 100.0)).norm() < 0.01);
+        assert!((geometry.samples.last().unwrap().distance - 100.0).abs() < 0.01);
+        for s in &geometry.samples {
+            assert!(s.centre.x.abs() < 0.01);
+            assert!(((s.left_edge() - s.right_edge()).norm() - TRACK_WIDTH).abs() < 0.01);
+            // Left is +x when driving along +z
+            assert!(s.left_edge().x > s.right_edge().x);
+        }
+    }
+
+    #[test]
+    fn banking_raises_the_left_edge() {
+        let points = [point(0.0, 0.0, 10.0), point(0.0, 100.0, 10.0)];
+        let geometry = SplineGeometry::new(&points, false, &Vector3::zeros());
+
+        let s = geometry.samples[SPLINE_ACCURACY / 2];
+        let height = TRACK_WIDTH / 2.0 * 10f32.to_radians().sin();
+        assert!((s.left_edge().y - height).abs() < 0.01);
+        assert!((s.right_edge().y + height).abs() < 0.01);
+        assert!(s.normal().y > 0.9);
+    }
+
+    #[test]
+    fn slopes_tilt_the_road() {
+        let points = [
+            SplinePoint { position: (0.0, 0.0, 0.0), width: TRACK_WIDTH, banking: 0.0 },
+            SplinePoint { position: (0.0, 10.0, 100.0), width: TRACK_WIDTH, banking: 0.0 },
+        ];
+        let geometry = SplineGeometry::new(&points, false, &Vector3::zeros());
+
+        let s = geometry.samples[SPLINE_ACCURACY / 2];
+        assert!(s.tangent.y > 0.0);
+        // The road goes uphill along z, so its normal leans back
+        assert!(s.normal().z < 0.0);
+        assert!(s.normal().dot(&s.tangent).abs() < 0.01);
+        assert!((s.left_edge().y - s.right_edge().y).abs() < 0.01);
+    }
+
+    #[test]
+    fn closed_spline_ends_at_the_start() {
+        let points = [
+            point(0.0, 0.0, 0.0),
+            point(100.0, 0.0, 0.0),
+            point(100.0, 100.0, 0.0),
+            point(0.0, 100.0, 0.0),
+        ];
+        let geometry = SplineGeometry::new(&points, true, &Vector3::zeros());
+
+        assert_eq!(geometry.samples.len(), 4 * SPLINE_ACCURACY + 1);
+        let (first, last) = (geometry.centre_line.first().unwrap(), geometry.centre_line.last().unwrap());
+        assert!((first - last).norm() < 0.01);
+        assert_eq!(geometry.barrier_colliders.len(), 2 * 4 * SPLINE_ACCURACY);
+    }
+}
@@ -0,0 +1,152 @@
+use glow::{Context, NativeTexture};
+use itertools::Itertools;
+use nalgebra::{Matrix4, Vector3};
+
+use crate::{
+    core::{
+        game::Game,
+        game_object::{Collider, GameObject},
+        matrices::ModelMatrix,
+    },
+    objects::road_strip::RoadStrip,
+};
+
+use super::{
+    track_kerb::TrackKerb,
+    track_segment::{
+        draw_pieces, TrackPiece, BARRIER_MATERIAL, BARRIER_RED, PIECE_WHITE, PLATFORM_MATERIAL, ROAD_MATERIAL,
+        TRACK_BOX_HEIGHT, TRACK_ELEVATION,
+    },
+    track_spline::{pitch, SplineGeometry, SplinePoint},
+};
+
+// Height of the barrier cubes above the road
+const BARRIER_HEIGHT: f32 = 0.4;
+
+/// A road following a Catmull-Rom spline through its control points, with kerbs and barriers on both sides
+pub struct TrackSplineSegment<'a> {
+    road_texture: NativeTexture,
+    segment_object: RoadStrip<'a>,
+    geometry: SplineGeometry,
+    barrier_pieces: Vec<TrackPiece>,
+    left_kerb: TrackKerb,
+    right_kerb: TrackKerb,
+}
+
+impl<'a> TrackSplineSegment<'a> {
+    pub fn new(points: &[SplinePoint], closed: bool, road_texture: NativeTexture, gl: &'a Context) -> TrackSplineSegment<'a> {
+        let geometry = SplineGeometry::new(points, closed, &Vector3::new(0.0, TRACK_ELEVATION + 0.1, 0.0));
+        let segment_object = RoadStrip::new(gl, &geometry.road_vertices);
+        let left_kerb = TrackKerb::new(geometry.left_kerb.clone());
+        let right_kerb = TrackKerb::new(geometry.right_kerb.clone());
+        let barrier_pieces = [&geometry.left_barrier, &geometry.right_barrier]
+            .iter()
+            .flat_map(|barrier| spline_barrier_pieces(barrier))
+            .collect();
+
+        TrackSplineSegment {
+            road_texture,
+            segment_object,
+            geometry,
+            barrier_pieces,
+            left_kerb,
+            right_kerb,
+        }
+    }
+
+    pub fn centre_line(&self) -> &Vec<Vector3<f32>> {
+        &self.geometry.centre_line
+    }
+}
+
+/// Red and white blocks along a barrier line
+pub fn spline_barrier_pieces(barrier: &[Vector3<f32>]) -> Vec<TrackPiece> {
+    let mut model_matrix = ModelMatrix::new();
+
+    barrier
+        .iter()
+        .tuple_windows()
+        .enumerate()
+        .map(|(i, (p1, p2))| {
+            let color = if i % 2 == 0 {
+                BARRIER_RED
+            } else {
+                PIECE_WHITE
+            };
+
+            let p = 0.5 * p1 + 0.5 * p2;
+            let v = p2 - p1;
+
+            model_matrix.push_stack();
+            model_matrix.add_translate(p.x, p.y + BARRIER_HEIGHT, p.z);
+            model_matrix.add_rotation(0.0, f32::atan2(v.x, v.z), 0.0);
+            model_matrix.add_rotation(pitch(&v), 0.0, 0.0);
+            model_matrix.add_scale(1.0, 1.0, v.norm());
+            let matrix = model_matrix.matrix;
+            model_matrix.pop_stack();
+
+            TrackPiece { matrix, color }
+        })
+        .collect()
+}
+
+/// Places unit cubes as the platform under the road
+pub fn spline_platform_matrices(geometry: &SplineGeometry) -> Vec<Matrix4<f32>> {
+    let mut model_matrix = ModelMatrix::new();
+
+    geometry
+        .platform()
+        .into_iter()
+        .map(|(centre, heading, pitch, width, length)| {
+            model_matrix.push_stack();
+            model_matrix.add_translate(centre.x, centre.y - 0.1, centre.z);
+            model_matrix.add_rotation(0.0, heading, 0.0);
+            model_matrix.add_rotation(pitch, 0.0, 0.0);
+            model_matrix.add_translate(0.0, -(TRACK_BOX_HEIGHT / 2.0) - 0.02, 0.0);
+            model_matrix.add_scale(width, TRACK_BOX_HEIGHT, length);
+            let matrix = model_matrix.matrix;
+            model_matrix.pop_stack();
+            matrix
+        })
+        .collect()
+}
+
+impl<'a> GameObject<'a> for TrackSplineSegment<'a> {
+    fn collision_info(&self) -> Collider {
+        Collider::MultiCollider(vec![
+            Collider::MultiCollider(self.geometry.barrier_colliders.clone()),
+            self.geometry.road_collider.clone(),
+            self.left_kerb.collision_info(),
+            self.right_kerb.collision_info(),
+        ])
+    }
+
+    fn on_event(&mut self, _game: &Game, _event: &sdl2::event::Event) {}
+
+    fn update(&mut self, _game: &Game, _gl: &'a Context) {}
+
+    fn display(&self, game: &Game, gl: &'a Context) {
+        BARRIER_MATERIAL.set(game);
+        draw_pieces(game, &self.barrier_pieces);
+
+        self.left_kerb.display(game, gl);
+        self.right_kerb.display(game, gl);
+
+        let model_matrix = game.model_matrix.borrow();
+
+        // Pavement
+        ROAD_MATERIAL.set(game);
+
+        // The road vertices are already in world coordinates
+        game.shader.set_model_matrix(model_matrix.matrix.as_slice());
+        self.segment_object.draw(&game.shader, &self.road_texture);
+
+        // Platform
+        PLATFORM_MATERIAL.set(game);
+
+        for platform in spline_platform_matrices(&self.geometry) {
+            game.shader.set_model_matrix((model_matrix.matrix * platform).as_slice());
+            game.cube.draw(&game.shader);
+        }
+    }
+}
@@ -1,70 +1,151 @@
 use glow::{Context, NativeTexture};
-use nalgebra::Vector3;
+use nalgebra::{Matrix4, Vector3, Vector4};
 
 use crate::{
     core::{
-        color::Color,
         game::Game,
-        game_object::GameObject,
+        game_object::{Collider, GameObject},
+        matrices::ModelMatrix,
+        surface::Surface,
     },
     game_objects::track::{
-        track_segment::{TRACK_BOX_HEIGHT, TRACK_ELEVATION, TRACK_WIDTH},
+        track_segment::{
+            add_tilt, strip_triangles, PLATFORM_MATERIAL, ROAD_MATERIAL, TRACK_BOX_HEIGHT, TRACK_ELEVATION, TRACK_WIDTH,
+        },
         track_side,
     },
     objects::textured_square::TexturedSquare,
-    utils::FacingDirection,
 };
 
 use super::track_side::TrackSide;
 
+const CENTRE_LINE_SPACING: f32 = 10.0;
+
 pub struct TrackStraightSegment<'a> {
     segment_object: TexturedSquare<'a>,
     position: Vector3<f32>,
-    direction: FacingDirection,
+    // Yaw in radians, 0 runs along the z axis
+    rotation: f32,
+    // In radians, positive slope goes uphill along the road and positive banking raises the left side
+    slope: f32,
+    banking: f32,
     length: f32,
     sides: TrackSide,
     road_texture: NativeTexture,
+    road_collider: Collider,
+    centre_line: Vec<Vector3<f32>>,
 }
 
 impl<'a> TrackStraightSegment<'a> {
     pub fn new(
         position: Vector3<f32>,
-        direction: FacingDirection,
+        rotation: f32,
+        slope: f32,
+        banking: f32,
         length: f32,
+        road_texture: NativeTexture,
         gl: &'a Context,
-        game: &Game,
     ) -> TrackStraightSegment<'a> {
-        let road_texture = game.load_texture("./models/textures/road.png", true);
-
-        use FacingDirection::*;
-        let rot = match direction {
-            North => 0.0,
-            West => 90f32.to_radians(),
-        };
 
         let pos = position + Vector3::new(0.0, TRACK_ELEVATION + 0.5, 0.0);
         let sides = TrackSide::new(
             pos,
-            rot,
+            rotation,
+            (slope, banking),
             length,
             track_side::TrackSegmentSideType::Straight,
-            game,
         );
 
+        let edges = straight_road_edges(&position, rotation, slope, banking, length);
+        let road_collider = Collider::SurfaceCollider(strip_triangles(&edges), Surface::Asphalt);
+
+        let centre_line = straight_centre_line(&position, rotation, slope, banking, length);
+
         TrackStraightSegment {
-            segment_object: TexturedSquare::new(gl, TRACK_WIDTH, length, FacingDirection::North),
+            segment_object: TexturedSquare::new(gl, TRACK_WIDTH, length, 0.0),
             position,
-            direction,
+            rotation,
+            slope,
+            banking,
             length,
             sides,
             road_texture,
+            road_collider,
+            centre_line,
         }
     }
+
+    pub fn centre_line(&self) -> &Vec<Vector3<f32>> {
+        &self.centre_line
+    }
+}
+
+/// Places the middle of the road surface of a straight at the origin, going along the z axis
+fn road_matrix(position: &Vector3<f32>, rotation: f32, slope: f32, banking: f32) -> ModelMatrix {
+    let mut model_matrix = ModelMatrix::new();
+    model_matrix.add_translate(position.x, position.y + TRACK_ELEVATION + 0.1, position.z);
+    model_matrix.add_rotation(0.0, rotation, 0.0);
+    add_tilt(&mut model_matrix, slope, banking);
+    model_matrix
+}
+
+/// The left and right edge of the road at both ends of a straight in world coordinates, the angles are in radians
+pub fn straight_road_edges(
+    position: &Vector3<f32>,
+    rotation: f32,
+    slope: f32,
+    banking: f32,
+    length: f32,
+) -> [(Vector3<f32>, Vector3<f32>); 2] {
+    let model_matrix = road_matrix(position, rotation, slope, banking);
+
+    [-length / 2.0, length / 2.0].map(|z| {
+        (
+            (model_matrix.matrix * Vector4::new(TRACK_WIDTH / 2.0, 0.0, z, 1.0)).xyz(),
+            (model_matrix.matrix * Vector4::new(-TRACK_WIDTH / 2.0, 0.0, z, 1.0)).xyz(),
+        )
+    })
+}
+
+/// Places a unit cube as the platform under a straight, the angles are in radians
+pub fn straight_platform_matrix(
+    position: &Vector3<f32>,
+    rotation: f32,
+    slope: f32,
+    banking: f32,
+    length: f32,
+) -> Matrix4<f32> {
+    let mut model_matrix = ModelMatrix::new();
+    model_matrix.add_translate(position.x, position.y + TRACK_ELEVATION, position.z);
+    model_matrix.add_rotation(0.0, rotation, 0.0);
+    add_tilt(&mut model_matrix, slope, banking);
+    model_matrix.add_translate(0.0, -(TRACK_BOX_HEIGHT / 2.0), 0.0);
+    model_matrix.add_scale(TRACK_WIDTH + 5.0, TRACK_BOX_HEIGHT, length);
+    model_matrix.matrix
+}
+
+/// Points along the middle of a straight in world coordinates, the angles are in radians
+pub fn straight_centre_line(
+    position: &Vector3<f32>,
+    rotation: f32,
+    slope: f32,
+    banking: f32,
+    length: f32,
+) -> Vec<Vector3<f32>> {
+    let model_matrix = road_matrix(position, rotation, slope, banking);
+    let point_count = (length / CENTRE_LINE_SPACING).ceil() as i32;
+
+    (0..=point_count)
+        .map(|i| {
+            let z = -length / 2.0 + length * (i as f32 / point_count as f32);
+            (model_matrix.matrix * Vector4::new(0.0, 0.0, z, 1.0)).xyz()
+        })
+        .collect()
 }
 
 impl<'a> GameObject<'a> for TrackStraightSegment<'a> {
-    fn collision_info(&self) -> crate::core::game_object::Collider {
-        self.sides.collision_info()
+    fn collision_info(&self) -> Collider {
+        Collider::MultiCollider(vec![self.sides.collision_info(), self.road_collider.clone()])
     }
 
     fn on_event(&mut self, _game: &Game, _event: &sdl2::event::Event) {}
@@ -77,60 +158,27 @@ impl<'a> GameObject<'a> for TrackStraightSegment<'a> {
         let mut model_matrix = game.model_matrix.borrow_mut();
 
         // Pavement
-        game.shader.set_material_ambient(&Color::new(0.84 / 1.5, 0.73 / 1.5, 0.67 / 1.5));
-        game.shader.set_material_diffuse(&Color::new(0.84, 0.73, 0.67));
-        game.shader
-            .set_material_specular(&Color::new(0.2, 0.2, 0.2));
-        game.shader.set_shininess(100.0);
+        ROAD_MATERIAL.set(game);
 
         model_matrix.push_stack();
 
-        use FacingDirection::*;
-        match self.direction {
-            North => {
-                model_matrix.add_translate(
-                    self.position.x,
-                    self.position.y + TRACK_ELEVATION + 0.1,
-                    self.position.z,
-                );
-                model_matrix.add_scale(1.0, 1.0, 1.0);
-            }
-            West => {
-                model_matrix.add_translate(
-                    self.position.x,
-                    self.position.y + TRACK_ELEVATION + 0.1,
-                    self.position.z,
-                );
-                model_matrix.add_scale(1.0, 1.0, 1.0);
-                model_matrix.add_rotation(0.0, 90f32.to_radians(), 0.0);
-            }
-        }
+        model_matrix.add_translate(
+            self.position.x,
+            self.position.y + TRACK_ELEVATION + 0.1,
+            self.position.z,
+        );
+        model_matrix.add_rotation(0.0, self.rotation, 0.0);
+        add_tilt(&mut model_matrix, self.slope, self.banking);
 
         game.shader.set_model_matrix(model_matrix.matrix.as_slice());
         self.segment_object.draw(&game.shader, &self.road_texture);
         model_matrix.pop_stack();
 
         // Platform
-        game.shader
-            .set_material_ambient(&Color::new(0.96 / 1.5, 0.58 / 1.5, 0.38 / 1.5));
-        game.shader
-            .set_material_diffuse(&Color::new(0.96, 0.58, 0.38));
-        game.shader
-            .set_material_specular(&Color::new(0.1, 0.1, 0.1));
-        game.shader.set_shininess(100.0);
+        PLATFORM_MATERIAL.set(game);
 
-        model_matrix.push_stack();
-        model_matrix.add_translate(
-            self.position.x,
-            self.position.y + TRACK_ELEVATION - (TRACK_BOX_HEIGHT / 2.0),
-            self.position.z,
-        );
-        if matches!(self.direction, FacingDirection::West) {
-            model_matrix.add_rotation(0.0, 90f32.to_radians(), 0.0);
-        }
-        model_matrix.add_scale(TRACK_WIDTH + 5.0, TRACK_BOX_HEIGHT, self.length);
-        game.shader.set_model_matrix(model_matrix.matrix.as_slice());
+        let platform = straight_platform_matrix(&self.position, self.rotation, self.slope, self.banking, self.length);
+        game.shader.set_model_matrix((model_matrix.matrix * platform).as_slice());
         game.cube.draw(&game.shader);
-        model_matrix.pop_stack();
     }
 }
@@ -0,0 +1,422 @@
+use std::{
+    f32::consts::PI,
+    fmt::{self, Display},
+};
+
+use nalgebra::Vector3;
+
+use crate::{core::game_object::Collider, objects::track_corner::TrackCornerType};
+
+use super::{
+    checkpoints::Crossing,
+    track::Track,
+    track_file::{segment_position, track_position, Checkpoint, SegmentDescription, TrackFile},
+    track_right_corner_segment::CORNER_ROTATION,
+    track_segment::{corner_geometry, TRACK_ELEVATION, TRACK_WIDTH},
+    track_side::TrackSide,
+    track_spline::SplineGeometry,
+    track_straight_segment::straight_centre_line,
+};
+
+// Ends of segments closer than this are connected
+const CONTINUITY_TOLERANCE: f32 = 0.5;
+// Largest change of direction where two segments meet
+const KINK_TOLERANCE: f32 = 5.0 * PI / 180.0;
+// Gaps in the barriers narrower than this are too small for a car to get through,
+// the barriers are drawn a unit thicker than their colliders
+const BARRIER_GAP_TOLERANCE: f32 = 3.0;
+// Barrier ends closer than this to a reported gap are part of the same gap
+const BARRIER_GAP_MERGE_DISTANCE: f32 = 10.0;
+// Height of the middle of a car above the road, where the car crosses the checkpoints
+const CAR_HEIGHT: f32 = 0.5;
+// How far above or below the road a spawn point may be
+const SPAWN_HEIGHT_TOLERANCE: f32 = 2.0;
+
+/// Something wrong with a track, all positions are relative to the track surface like in the track file
+#[derive(Clone, Debug, PartialEq)]
+pub enum TrackProblem {
+    // The segment doesn't end where the next one starts
+    Gap { segment: usize, next: usize, position: Vector3<f32>, distance: f32 },
+    // The segments meet, but the road changes direction where they do. The angle is in degrees
+    Kink { segment: usize, next: usize, position: Vector3<f32>, angle: f32 },
+    // The road surfaces of two parts of the track are on top of each other. The barriers are walls of
+    // infinite height, so one part can't go over another either
+    Overlap { segment: usize, other: usize, position: Vector3<f32> },
+    // A barrier ends without meeting another one, the distance is to the closest other barrier
+    BarrierGap { position: Vector3<f32>, distance: f32 },
+    // Driving along the track never goes through the checkpoint
+    UnreachableCheckpoint { checkpoint: usize, position: Vector3<f32> },
+    // The track only goes through the checkpoint against its direction
+    BackwardCheckpoint { checkpoint: usize, position: Vector3<f32> },
+    // A car would start off the road, the distance is from the middle of the road
+    SpawnOffRoad { grid_position: usize, position: Vector3<f32>, distance: f32 },
+}
+
+impl Display for TrackProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use TrackProblem::*;
+        match self {
+            Gap { segment, next, position, distance } => write!(
+                f,
+                "Gap of {distance:.2} between segment {segment} and segment {next} at {}",
+                Coordinates(position)
+            ),
+            Kink { segment, next, position, angle } => write!(
+                f,
+                "Segment {segment} and segment {next} meet at an angle of {angle:.1} degrees at {}",
+                Coordinates(position)
+            ),
+            Overlap { segment, other, position } => write!(
+                f,
+                "Road of segment {segment} overlaps segment {other} at {}",
+                Coordinates(position)
+            ),
+            BarrierGap { position, distance } => write!(
+                f,
+                "Barrier ends at {} with the closest barrier {distance:.2} away",
+                Coordinates(position)
+            ),
+            UnreachableCheckpoint { checkpoint, position } => write!(
+                f,
+                "Checkpoint {checkpoint} at {} is not on the track",
+                Coordinates(position)
+            ),
+            BackwardCheckpoint { checkpoint, position } => write!(
+                f,
+                "Checkpoint {checkpoint} at {} faces against the direction of the track",
+                Coordinates(position)
+            ),
+            SpawnOffRoad { grid_position, position, distance } => write!(
+                f,
+                "Start grid position {grid_position} at {} is {distance:.2} from the middle of the road",
+                Coordinates(position)
+            ),
+        }
+    }
+}
+
+struct Coordinates<'a>(&'a Vector3<f32>);
+
+impl Display for Coordinates<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({:.1}, {:.1}, {:.1})", self.0.x, self.0.y, self.0.z)
+    }
+}
+
+/// Check the geometry of a track, without building it
+pub fn validate_track(track_file: &TrackFile) -> Vec<TrackProblem> {
+    let centre_lines = track_file.segments.iter().map(segment_centre_line).collect::<Vec<_>>();
+    let centre_lines = centre_lines.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    let circuit = Track::join_centre_lines(&centre_lines);
+
+    let mut problems = check_continuity(&track_file.segments);
+    problems.extend(check_overlaps(&circuit));
+    problems.extend(check_barriers(&track_file.segments));
+    problems.extend(check_checkpoints(&track_file.checkpoints, &circuit));
+    problems.extend(check_start_grid(track_file, &circuit));
+    problems
+}
+
+/// Points along the middle of the road of a segment in world coordinates, the same as the built segment has
+pub fn segment_centre_line(segment: &SegmentDescription) -> Vec<Vector3<f32>> {
+    use SegmentDescription::*;
+    match *segment {
+        Straight { position, rotation, slope, banking, length } => straight_centre_line(
+            &segment_position(position),
+            rotation.to_radians(),
+            slope.to_radians(),
+            banking.to_radians(),
+            length,
+        ),
+        RightCorner { position, rotation } => {
+            corner_geometry(&TrackCornerType::Right, &segment_position(position), rotation.to_radians() + CORNER_ROTATION)
+                .centre_line
+        }
+        UCorner { position, rotation } => {
+            corner_geometry(&TrackCornerType::UTurn, &segment_position(position), rotation.to_radians()).centre_line
+        }
+        Spline { ref points, closed } => {
+            SplineGeometry::new(points, closed, &Vector3::new(0.0, TRACK_ELEVATION + 0.1, 0.0)).centre_line
+        }
+    }
+}
+
+/// The barriers of a segment as lines in world coordinates, the same as the colliders of the built segment
+pub fn segment_barriers(segment: &SegmentDescription) -> Vec<(Vector3<f32>, Vector3<f32>)> {
+    // The sides are placed half a unit above the road
+    let side_position = |position| segment_position(position) + Vector3::new(0.0, TRACK_ELEVATION + 0.5, 0.0);
+
+    use SegmentDescription::*;
+    let colliders = match *segment {
+        Straight { position, rotation, slope, banking, length } => TrackSide::create_straight_colliders(
+            &side_position(position),
+            rotation.to_radians(),
+            (slope.to_radians(), banking.to_radians()),
+            length,
+        ),
+        RightCorner { position, rotation } => {
+            TrackSide::create_right_corner_colliders(&side_position(position), rotation.to_radians())
+        }
+        UCorner { position, rotation } => TrackSide::create_u_colliders(&side_position(position), rotation.to_radians()),
+        Spline { ref points, closed } => {
+            SplineGeometry::new(points, closed, &Vector3::new(0.0, TRACK_ELEVATION + 0.1, 0.0)).barrier_colliders
+        }
+    };
+
+    colliders
+        .into_iter()
+        .filter_map(|c| match c {
+            Collider::InfiniteYPlaneCollider(p0, p1) => Some((p0, p1)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Every segment has to end where the next one starts, going the same way
+fn check_continuity(segments: &[SegmentDescription]) -> Vec<TrackProblem> {
+    let mut problems = Vec::new();
+
+    for (i, segment) in segments.iter().enumerate() {
+        let next_index = (i + 1) % segments.len();
+        let next = &segments[next_index];
+
+        // Any end can meet any end, since segments may be driven backwards
+        let ends = |s: &SegmentDescription| [(s.entry(), false), (s.exit(), true)];
+        let joints = if next_index == i {
+            vec![(segment.exit(), true, segment.entry(), false)]
+        } else {
+            ends(segment)
+                .into_iter()
+                .flat_map(|(a, a_exit)| ends(next).into_iter().map(move |(b, b_exit)| (a, a_exit, b, b_exit)))
+                .collect()
+        };
+
+        let Some((a, a_exit, b, b_exit)) = joints.into_iter().min_by(|(a, _, b, _), (c, _, d, _)| {
+            (a.position - b.position).norm().total_cmp(&(c.position - d.position).norm())
+        }) else {
+            continue;
+        };
+
+        let position = 0.5 * a.position + 0.5 * b.position;
+        let distance = (a.position - b.position).norm();
+        if distance > CONTINUITY_TOLERANCE {
+            problems.push(TrackProblem::Gap { segment: i, next: next_index, position, distance });
+            continue;
+        }
+
+        // Two exits or two entries meeting face each other, an exit and an entry go the same way
+        let expected = if a_exit == b_exit { PI } else { 0.0 };
+        let angle = (a.heading_difference(&b) - expected).abs();
+        if angle > KINK_TOLERANCE {
+            problems.push(TrackProblem::Kink { segment: i, next: next_index, position, angle: angle.to_degrees() });
+        }
+    }
+
+    problems
+}
+
+/// Parts of the track that are far apart along the road mustn't be close to each other, at any height
+fn check_overlaps(circuit: &[(usize, Vector3<f32>)]) -> Vec<TrackProblem> {
+    let distances = circuit
+        .iter()
+        .scan((0.0, circuit.first().map(|(_, p)| *p)), |(distance, last), (_, point)| {
+            *distance += last.map(|l| (point - l).norm()).unwrap_or(0.0);
+            *last = Some(*point);
+            Some(*distance)
+        })
+        .collect::<Vec<_>>();
+    let length = distances.last().copied().unwrap_or(0.0)
+        + match (circuit.first(), circuit.last()) {
+            (Some((_, first)), Some((_, last))) => (first - last).norm(),
+            _ => 0.0,
+        };
+
+    // Road next to a point along the track is always close to it
+    let neighbour_distance = TRACK_WIDTH * PI / 2.0;
+
+    let mut problems: Vec<TrackProblem> = Vec::new();
+    for (i, (segment, a)) in circuit.iter().enumerate() {
+        for (j, (other, b)) in circuit.iter().enumerate().skip(i + 1) {
+            let along = distances[j] - distances[i];
+            if along.min(length - along) < neighbour_distance || (a - b).xz().norm() >= TRACK_WIDTH {
+                continue;
+            }
+
+            let (segment, other) = (*segment.min(other), *segment.max(other));
+            let reported = problems
+                .iter()
+                .any(|p| matches!(p, TrackProblem::Overlap { segment: s, other: o, .. } if (*s, *o) == (segment, other)));
+            if !reported {
+                problems.push(TrackProblem::Overlap { segment, other, position: file_position(&(0.5 * a + 0.5 * b)) });
+            }
+        }
+    }
+
+    problems
+}
+
+/// The end of every barrier has to meet another barrier
+fn check_barriers(segments: &[SegmentDescription]) -> Vec<TrackProblem> {
+    let barriers = segments.iter().flat_map(segment_barriers).collect::<Vec<_>>();
+
+    let mut problems: Vec<TrackProblem> = Vec::new();
+    for (i, (p0, p1)) in barriers.iter().enumerate() {
+        for end in [p0, p1] {
+            let distance = barriers
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(_, (b0, b1))| (closest_point(end, b0, b1) - end).xz().norm())
+                .fold(f32::INFINITY, f32::min);
+            if distance <= BARRIER_GAP_TOLERANCE {
+                continue;
+            }
+
+            let position = file_position(end);
+            let reported = problems.iter().any(|p| match p {
+                TrackProblem::BarrierGap { position: p, .. } => (p - position).norm() < BARRIER_GAP_MERGE_DISTANCE,
+                _ => false,
+            });
+            if !reported {
+                problems.push(TrackProblem::BarrierGap { position, distance });
+            }
+        }
+    }
+
+    problems
+}
+
+/// Driving once around the track has to go through every checkpoint forwards
+fn check_checkpoints(checkpoints: &[Checkpoint], circuit: &[(usize, Vector3<f32>)]) -> Vec<TrackProblem> {
+    let car_height = Vector3::new(0.0, CAR_HEIGHT, 0.0);
+    let path = circuit
+        .iter()
+        .chain(circuit.first())
+        .map(|(_, p)| p + car_height)
+        .collect::<Vec<_>>();
+
+    checkpoints
+        .iter()
+        .enumerate()
+        .filter_map(|(checkpoint, c)| {
+            let crossings = path.windows(2).filter_map(|p| c.crossing(&p[0], &p[1])).collect::<Vec<_>>();
+            let position = match *c {
+                Checkpoint::Line { start, end, height } => {
+                    Vector3::new((start.0 + end.0) / 2.0, height, (start.1 + end.1) / 2.0)
+                }
+                Checkpoint::Volume { centre, .. } => segment_position(centre),
+            };
+
+            if crossings.contains(&Crossing::Forward) {
+                None
+            } else if crossings.contains(&Crossing::Backward) {
+                Some(TrackProblem::BackwardCheckpoint { checkpoint, position })
+            } else {
+                Some(TrackProblem::UnreachableCheckpoint { checkpoint, position })
+            }
+        })
+        .collect()
+}
+
+/// Every car has to start on the road
+fn check_start_grid(track_file: &TrackFile, circuit: &[(usize, Vector3<f32>)]) -> Vec<TrackProblem> {
+    let points = circuit.iter().chain(circuit.first()).map(|(_, p)| *p).collect::<Vec<_>>();
+
+    track_file
+        .start_grid
+        .iter()
+        .enumerate()
+        .filter_map(|(grid_position, g)| {
+            let position = track_position(g.position);
+
+            // Closest point on the middle of the road
+            let closest = points
+                .windows(2)
+                .map(|p| closest_point(&position, &p[0], &p[1]))
+                .min_by(|a, b| (a - position).xz().norm().total_cmp(&(b - position).xz().norm()));
+
+            let distance = closest.map(|c| (c - position).xz().norm()).unwrap_or(f32::INFINITY);
+            let height = closest.map(|c| (c.y - position.y).abs()).unwrap_or(0.0);
+            (distance > TRACK_WIDTH / 2.0 || height > SPAWN_HEIGHT_TOLERANCE).then(|| TrackProblem::SpawnOffRoad {
+                grid_position,
+                position: segment_position(g.position),
+                distance,
+            })
+        })
+        .collect()
+}
+
+/// Point on the line from start to end closest to the point, seen from above
+fn closest_point(point: &Vector3<f32>, start: &Vector3<f32>, end: &Vector3<f32>) -> Vector3<f32> {
+    let line = (end - start).xz();
+    let t = if line.norm_squared() > 0.0 {
+        ((point - start).xz().dot(&line) / line.norm_squared()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    start + (end - start) * t
+}
+
+/// World position back to a position relative to the track surface
+fn file_position(position: &Vector3<f32>) -> Vector3<f32> {
+    position - Vector3::new(0.0, TRACK_ELEVATION, 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{f32::consts::TAU, fs, path::PathBuf};
+
+    use super::{super::{track_file::bundled_track, track_spline::SplinePoint}, *};
+
+    #[test]
+    fn bundled_tracks_are_valid() {
+        let folder = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tracks");
+        for entry in fs::read_dir(folder).unwrap() {
+            let path = entry.unwrap().path();
+            let problems = validate_track(&TrackFile::load(&path).unwrap());
+            let report = problems.iter().map(|p| p.to_string()).collect::<Vec<_>>().join("\n");
+            assert!(problems.is_empty(), "{}:\n{report}", path.display());
+        }
+    }
+
+    #[test]
+    fn broken_track_reports_problems() {
+        let mut track = bundled_track("default.ron");
+
+        track.segments[2] = track.segments[2].translated(&Vector3::new(0.0, 0.0, 8.0));
+        if let Checkpoint::Line { start, end, .. } = &mut track.checkpoints[0] {
+            std::mem::swap(start, end);
+        }
+        track.start_grid[0].position.0 += 30.0;
+
+        let problems = validate_track(&track);
+        let has = |matches: fn(&TrackProblem) -> bool| problems.iter().any(matches);
+        assert!(has(|p| matches!(p, TrackProblem::Gap { segment: 1, next: 2, .. })));
+        assert!(has(|p| matches!(p, TrackProblem::Gap { segment: 2, next: 3, .. })));
+        assert!(has(|p| matches!(p, TrackProblem::BarrierGap { .. })));
+        assert!(has(|p| matches!(p, TrackProblem::BackwardCheckpoint { checkpoint: 0, .. })));
+        assert!(has(|p| matches!(p, TrackProblem::SpawnOffRoad { grid_position: 0, .. })));
+    }
+
+    #[test]
+    fn road_going_over_itself_overlaps() {
+        // A figure eight that goes over its crossing 20 units higher than under it, the barriers would block it
+        let points = (0..16)
+            .map(|i| {
+                let t = i as f32 * TAU / 16.0;
+                let position = (300.0 * t.sin(), 10.0 * t.cos(), 300.0 * t.sin() * t.cos());
+                SplinePoint { position, width: TRACK_WIDTH, banking: 0.0 }
+            })
+            .collect::<Vec<_>>();
+        let track = TrackFile {
+            skybox: String::new(),
+            segments: vec![SegmentDescription::Spline { points, closed: true }],
+            start_grid: Vec::new(),
+            checkpoints: Vec::new(),
+            props: Vec::new(),
+        };
+
+        let problems = validate_track(&track);
+        assert!(problems.iter().any(|p| matches!(p, TrackProblem::Overlap { segment: 0, other: 0, .. })));
+    }
+}
@@ -4,10 +4,22 @@ pub mod network;
 pub mod objects;
 pub mod utils;
 
-use clap::Parser;
+use std::path::PathBuf;
+
+use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand};
 use simplelog::TermLogger;
 
-use crate::core::constants::{W_HEIGHT, W_WIDTH};
+use crate::core::constants::{DEFAULT_RANDOM_TRACK_LENGTH, DEFAULT_TRACK, WINDOW_TITLE, W_HEIGHT, W_WIDTH};
+use crate::game_objects::cars::{bot_driver::BotDifficulty, player_input::{check_split_screen, PlayerInput}};
+use crate::game_objects::race::{
+    lap_records::{lap_records_path, track_name, LapRecords},
+    lap_timer::log_leaderboard,
+};
+use crate::game_objects::replay::replay_file::{Replay, ReplayRecorder};
+use crate::game_objects::track::{
+    track_export::export_track, track_file::TrackFile, track_generator::generate_track,
+    track_validation::validate_track,
+};
 
 use crate::core::game;
 
@@ -19,20 +31,194 @@ struct Args {
     /// Skip this argument to play in single player
     #[clap(short, long, default_value = None)]
     server: Option<String>,
+
+    /// Arcade mode, gives the car a boost that recharges over time and when drifting
+    #[clap(short, long)]
+    arcade: bool,
+
+    /// Record telemetry of the players car to this file, as JSON lines if it ends with .json or .jsonl
+    /// and as CSV otherwise. Recording is started and stopped with T
+    #[clap(short, long)]
+    telemetry: Option<PathBuf>,
+
+    /// Track file to race on
+    #[clap(long, default_value = DEFAULT_TRACK, global = true)]
+    track: PathBuf,
+
+    /// Open the track in the editor instead of racing on it.
+    /// Fly with W, S and the arrows, drag segments and props with the mouse,
+    /// turn them with Q and E, delete with Delete and place new ones with 1 to 5.
+    /// Ctrl+Z undoes, Ctrl+Y redoes and Ctrl+S saves to the track file
+    #[clap(short, long)]
+    edit: bool,
+
+    /// Number of computer driven cars to race against in single player
+    #[clap(long, default_value_t = 0)]
+    bots: usize,
+
+    /// How fast the bots drive
+    #[clap(long, value_enum, default_value_t = BotDifficulty::Medium)]
+    bot_difficulty: BotDifficulty,
+
+    /// Time trial, the best lap on the track is saved and driven again by a see through ghost car.
+    /// The ghost turns green when you are ahead of it and red when you are behind
+    #[clap(long)]
+    time_trial: bool,
+
+    /// Record the race to this file, the player, the other players, the bots and the laps they drive
+    #[clap(long, value_name = "PATH")]
+    record_replay: Option<PathBuf>,
+
+    /// Watch a recorded race instead of racing, on the same track it was recorded on.
+    /// A replay doesn't play on another track or on a track that was edited since it was recorded.
+    /// Space pauses, the left and right arrows jump back and forward, up and down change the speed,
+    /// Tab follows the next car and C switches between the chase camera and the trackside cameras
+    #[clap(long, value_name = "PATH")]
+    replay: Option<PathBuf>,
+
+    /// Laps in a race. The cars wait on the grid for the countdown, and the results are shown when
+    /// everyone has finished. Press Enter to race again in single player. Time trial has no lap limit.
+    /// The lap time and the split to your fastest lap are shown in the window title, Tab lists the fastest laps
+    #[clap(long, default_value_t = 3)]
+    laps: u32,
+
+    /// Split the window between two to four players at this computer, each driving with their own input:
+    /// wasd, arrows, ijkl or a joystick by its index like joystick0. For example --split-screen wasd,joystick0.
+    /// With the arrows right ctrl is the handbrake, right shift boosts and backspace resets,
+    /// with ijkl M is the handbrake, U boosts and O resets. Only in single player
+    #[clap(long, value_name = "INPUTS", value_delimiter = ',', num_args = 2..=4)]
+    split_screen: Vec<PlayerInput>,
+
+    /// Race on a random track generated from this seed instead of the track file.
+    /// Everyone in a multiplayer race has to use the same seed and length
+    #[clap(long, value_name = "SEED", global = true)]
+    random_track: Option<u64>,
+
+    /// Length of the random track
+    #[clap(long, default_value_t = DEFAULT_RANDOM_TRACK_LENGTH, global = true)]
+    track_length: f32,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check the track for gaps between segments, overlapping roads, gaps in the barriers,
+    /// checkpoints that can't be reached and start positions off the road, then exit
+    Validate,
+    /// Write the road, kerbs, barriers and platform of the track to an OBJ file, with the materials
+    /// in an MTL file and the road texture next to it, then exit
+    Export {
+        /// Path of the OBJ file
+        output: PathBuf,
+    },
+    /// List the fastest laps driven on the track, for the standard and the arcade car, then exit
+    Leaderboard,
 }
 
 fn main() {
     let args = Args::parse();
+    if let Err(e) = check_split_screen(&args.split_screen) {
+        Args::command().error(ErrorKind::ArgumentConflict, e).exit();
+    }
     init_logger();
 
-    let (gl, window, mut events_loop, _gl_context, joystick) = unsafe {
+    let (track_file, track_path) = match args.random_track {
+        // The editor saves a random track to its own file, so the track file isn't overwritten
+        Some(seed) => (
+            generate_track(seed, args.track_length),
+            PathBuf::from(format!("./tracks/random-{seed}-{:.0}.ron", args.track_length)),
+        ),
+        None => (TrackFile::load(&args.track), args.track),
+    };
+    let track_file = match track_file {
+        Ok(t) => t,
+        Err(e) => {
+            log::error!("{e:#}");
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(Command::Validate) = args.command {
+        let problems = validate_track(&track_file);
+        for problem in &problems {
+            log::warn!("{problem}");
+        }
+
+        if problems.is_empty() {
+            log::info!("No problems found in {}", track_path.display());
+            return;
+        }
+        log::error!("Found {} problems in {}", problems.len(), track_path.display());
+        std::process::exit(1);
+    }
+
+    if let Some(Command::Export { output }) = &args.command {
+        if let Err(e) = export_track(&track_file, output) {
+            log::error!("{e}");
+            std::process::exit(1);
+        }
+        log::info!("Exported {} to {}", track_path.display(), output.display());
+        return;
+    }
+
+    if let Some(Command::Leaderboard) = args.command {
+        let Some(path) = lap_records_path() else {
+            log::error!("Failed to find the user data folder the lap records are saved in");
+            std::process::exit(1);
+        };
+        let records = match LapRecords::load(&path) {
+            Ok(r) => r,
+            Err(e) => {
+                log::error!("{e:#}");
+                std::process::exit(1);
+            }
+        };
+
+        let track = track_name(&track_path, &track_file);
+        let profiles = records.profiles(&track);
+        if profiles.is_empty() {
+            log::info!("No laps on {track} yet");
+        }
+        for profile in profiles {
+            log_leaderboard(&records, &track, profile);
+        }
+        return;
+    }
+
+    let track = track_name(&track_path, &track_file);
+    let replay = match args.replay.as_deref().map(Replay::load).transpose() {
+        Ok(r) => r,
+        Err(e) => {
+            log::error!("{e:#}");
+            std::process::exit(1);
+        }
+    };
+    if let Some(replay) = replay.as_ref().filter(|r| r.track != track) {
+        log::error!(
+            "The replay was recorded on {}, not on {track}. Start it with the track it was recorded on, \
+            or the same --random-track and --track-length",
+            replay.track
+        );
+        std::process::exit(1);
+    }
+    let replay_recorder = match args.record_replay.as_deref().map(|path| ReplayRecorder::create(path, &track)).transpose() {
+        Ok(r) => r,
+        Err(e) => {
+            log::error!("{e:#}");
+            std::process::exit(1);
+        }
+    };
+
+    let (gl, mut window, mut events_loop, _gl_context, joystick) = unsafe {
         let sdl = sdl2::init().unwrap();
         let video = sdl.video().unwrap();
         let gl_attr = video.gl_attr();
         gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
         gl_attr.set_context_version(3, 0);
         let window = video
-            .window("OpenGL", W_WIDTH, W_HEIGHT)
+            .window(WINDOW_TITLE, W_WIDTH, W_HEIGHT)
             .opengl()
             .resizable()
             .build()
@@ -46,13 +232,25 @@ fn main() {
         (gl, window, events_loop, gl_context, joystick)
     };
 
-    let mut game = game::Game::new(
-        &gl,
-        &window,
-        &mut events_loop,
-        &joystick,
-        args.server.as_ref().map(String::as_str),
-    );
+    let options = game::GameOptions {
+        server_address: args.server,
+        arcade_mode: args.arcade,
+        telemetry_path: args.telemetry,
+        track_file,
+        track_path,
+        edit_mode: args.edit,
+        bot_count: args.bots,
+        bot_difficulty: args.bot_difficulty,
+        time_trial: args.time_trial,
+        replay_recorder,
+        replay,
+        lap_count: (!args.time_trial).then_some(args.laps),
+        players: match args.split_screen.is_empty() {
+            true => vec![PlayerInput::single_player()],
+            false => args.split_screen,
+        },
+    };
+    let mut game = game::Game::new(&gl, &mut window, &mut events_loop, &joystick, options);
     game.create_scene();
 
     game.main();
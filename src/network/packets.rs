@@ -53,6 +53,9 @@ pub enum GamePacket {
     Restart,
     DropPlayer { player_id: u8 },
     End { player_id: u8 },
+    // Damage of the front, rear, left and right of a players car
+    DamageUpdate { player_id: u8, areas: [f32; 4] },
+    BoostUpdate { player_id: u8, active: bool },
 }
 
 impl GamePacket {
@@ -66,7 +69,13 @@ impl GamePacket {
             Restart => vec![8],
             StatusUpdate(s) => s.to_binary_data(),
             DropPlayer { player_id } => vec![4, *player_id],
-            End { player_id } => vec![3, *player_id]
+            End { player_id } => vec![3, *player_id],
+            DamageUpdate { player_id, areas } => [
+                vec![9, *player_id],
+                areas.iter().flat_map(|a| a.to_le_bytes()).collect(),
+            ]
+            .concat(),
+            BoostUpdate { player_id, active } => vec![10, *player_id, *active as u8],
         }
     }
 }
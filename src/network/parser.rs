@@ -65,6 +65,29 @@ pub fn parse_restart(input: &[u8]) -> IResult<&[u8], GamePacket> {
     value(GamePacket::Restart, tag(&[8u8]))(input)
 }
 
+pub fn parse_damage_update(input: &[u8]) -> IResult<&[u8], GamePacket> {
+    map(
+        preceded(
+            tag(&[9u8]),
+            tuple((le_u8, parse_float, parse_float, parse_float, parse_float)),
+        ),
+        |(player_id, front, rear, left, right)| GamePacket::DamageUpdate {
+            player_id,
+            areas: [front, rear, left, right],
+        },
+    )(input)
+}
+
+pub fn parse_boost_update(input: &[u8]) -> IResult<&[u8], GamePacket> {
+    map(
+        preceded(tag(&[10u8]), tuple((le_u8, le_u8))),
+        |(player_id, active)| GamePacket::BoostUpdate {
+            player_id,
+            active: active != 0,
+        },
+    )(input)
+}
+
 pub fn parse_packet(packet: &[u8]) -> Result<GamePacket, Box<dyn std::error::Error + '_>> {
     let (_, packet) = alt((
         parse_status_update,
@@ -75,6 +98,8 @@ pub fn parse_packet(packet: &[u8]) -> Result<GamePacket, Box<dyn std::error::Err
         parse_new_player,
         parse_lap_complete,
         parse_restart,
+        parse_damage_update,
+        parse_boost_update,
     ))(packet)?;
 
     Ok(packet)
@@ -13,6 +13,7 @@ enum Connection {
 pub enum NetworkEvent {
     PlayerConnected { player_id: u8 },
     PlayerDisconnected { player_id: u8 },
+    LapCompleted { player_id: u8 },
     MoveToStartPos,
 }
 
@@ -20,6 +21,8 @@ pub struct ServerConnection {
     connection: Connection,
     connected_players: HashSet<u8>,
     last_status: HashMap<u8, packets::StatusUpdate>,
+    last_damage: HashMap<u8, [f32; 4]>,
+    boosting_players: HashSet<u8>,
     player_id: Option<u8>,
     pub game_events: RefCell<VecDeque<NetworkEvent>>,
 }
@@ -30,6 +33,8 @@ impl ServerConnection {
             connection: Connection::NotConnected,
             connected_players: HashSet::new(),
             last_status: HashMap::new(),
+            last_damage: HashMap::new(),
+            boosting_players: HashSet::new(),
             player_id: None,
             game_events: RefCell::new(VecDeque::new()),
         }
@@ -79,6 +84,24 @@ impl ServerConnection {
         self.send_packet(packets::GamePacket::LapComplete { player_id });
     }
 
+    pub fn send_damage_update(&self, areas: [f32; 4]) {
+        let player_id = match self.player_id {
+            Some(player_id) => player_id,
+            None => return
+        };
+
+        self.send_packet(packets::GamePacket::DamageUpdate { player_id, areas });
+    }
+
+    pub fn send_boost_update(&self, active: bool) {
+        let player_id = match self.player_id {
+            Some(player_id) => player_id,
+            None => return
+        };
+
+        self.send_packet(packets::GamePacket::BoostUpdate { player_id, active });
+    }
+
     pub fn end_connection(&mut self) {
         if let Some(player_id) = self.player_id {
             self.send_packet(packets::GamePacket::End { player_id });
@@ -105,6 +128,14 @@ impl ServerConnection {
         self.last_status.get(&player_id)
     }
 
+    pub fn last_damage(&self, player_id: u8) -> Option<[f32; 4]> {
+        self.last_damage.get(&player_id).copied()
+    }
+
+    pub fn is_boosting(&self, player_id: u8) -> bool {
+        self.boosting_players.contains(&player_id)
+    }
+
     pub fn player_id(&self) -> Option<u8> {
         self.player_id
     }
@@ -154,15 +185,34 @@ impl ServerConnection {
                         None => ()
                     };
                 }
+                DamageUpdate { player_id, areas } => {
+                    if self.connected_players.contains(&player_id) {
+                        self.last_damage.insert(player_id, areas);
+                    }
+                }
+                BoostUpdate { player_id, active } => {
+                    if active && self.connected_players.contains(&player_id) {
+                        self.boosting_players.insert(player_id);
+                    } else {
+                        self.boosting_players.remove(&player_id);
+                    }
+                }
                 Restart => {
                     log::debug!("Some player has won, restarting");
                     self.game_events.get_mut().push_back(NetworkEvent::MoveToStartPos);
                 }
                 DropPlayer { player_id } => {
                     self.connected_players.remove(&player_id);
+                    self.last_damage.remove(&player_id);
+                    self.boosting_players.remove(&player_id);
                     self.game_events.get_mut().push_back(NetworkEvent::PlayerDisconnected { player_id });
                 }
-                End { .. } | LapComplete { .. } => (),
+                LapComplete { player_id } => {
+                    if self.connected_players.contains(&player_id) {
+                        self.game_events.get_mut().push_back(NetworkEvent::LapCompleted { player_id });
+                    }
+                }
+                End { .. } => (),
             }
         }
     }
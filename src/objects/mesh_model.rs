@@ -3,7 +3,7 @@ use std::{collections::HashMap, slice};
 use glow::*;
 use nalgebra::{Vector3, Vector2};
 
-use crate::core::{material::Material, shader::Shader3D};
+use crate::core::{color::Color, material::Material, shader::Shader3D};
 
 pub struct MeshModel<'a> {
     vertex_arrays: HashMap<String, Vec<f32>>,
@@ -57,11 +57,28 @@ impl<'a> MeshModel<'a> {
     }
 
     pub fn draw(&self, shader: &Shader3D) {
+        self.draw_with_tint(shader, &Color::zeros(), 0.0);
+    }
+
+    /// Draw the model with the colors of all materials mixed with a tint color, amount is from 0 (no tint) to 1
+    pub fn draw_with_tint(&self, shader: &Shader3D, tint: &Color, amount: f32) {
+        self.draw_translucent(shader, tint, amount, 1.0);
+    }
+
+    /// Draw the model tinted like draw_with_tint, with the alpha of all materials multiplied by alpha
+    pub fn draw_translucent(&self, shader: &Shader3D, tint: &Color, amount: f32, alpha: f32) {
+        let mix = |color: Color| Color {
+            r: color.r + (tint.r - color.r) * amount,
+            g: color.g + (tint.g - color.g) * amount,
+            b: color.b + (tint.b - color.b) * amount,
+            a: color.a * alpha,
+        };
+
         for (mesh_id, mesh_material) in &self.mesh_materials {
             let material = &self.materials[mesh_material];
-            shader.set_material_diffuse(&material.diffuse());
-            shader.set_material_specular(&material.specular);
-            shader.set_material_ambient(&material.ambient());
+            shader.set_material_diffuse(&mix(material.diffuse()));
+            shader.set_material_specular(&mix(material.specular));
+            shader.set_material_ambient(&mix(material.ambient()));
             shader.set_shininess(material.shininess);
             shader.set_attribute_buffers(&self.vertex_buffer_ids[mesh_id]);
             shader.set_diffuse_texture_active(material.diffuse_texture.is_some());
@@ -1,5 +1,6 @@
 pub mod cube;
 pub mod mesh_model;
+pub mod road_strip;
 pub mod textured_square;
 pub mod track_corner;
 pub mod skybox_cubemap;
\ No newline at end of file
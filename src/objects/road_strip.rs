@@ -0,0 +1,72 @@
+use std::slice;
+
+use glow::*;
+
+use crate::{core::shader::Shader3D, game_objects::track::track_spline::RoadVertex};
+
+/// A textured triangle strip, used for roads that follow a spline
+pub struct RoadStrip<'a> {
+    buffer: NativeBuffer,
+    vertex_count: i32,
+    gl: &'a Context,
+}
+
+impl<'a> RoadStrip<'a> {
+    pub fn new(gl: &'a Context, vertices: &[RoadVertex]) -> RoadStrip<'a> {
+        let vertex_array = vertices
+            .iter()
+            .flat_map(|v| {
+                [
+                    v.position.x,
+                    v.position.y,
+                    v.position.z,
+                    v.normal.x,
+                    v.normal.y,
+                    v.normal.z,
+                    v.uv.0,
+                    v.uv.1,
+                ]
+            })
+            .collect::<Vec<f32>>();
+
+        let buffer = unsafe {
+            let buffer = gl.create_buffer().unwrap();
+            gl.bind_buffer(ARRAY_BUFFER, Some(buffer));
+            gl.buffer_data_u8_slice(
+                ARRAY_BUFFER,
+                slice::from_raw_parts(vertex_array.as_ptr() as *const u8, vertex_array.len() * 4),
+                STATIC_DRAW,
+            );
+            gl.bind_buffer(ARRAY_BUFFER, None);
+            buffer
+        };
+
+        RoadStrip {
+            buffer,
+            vertex_count: vertices.len() as i32,
+            gl,
+        }
+    }
+
+    pub fn draw(&self, shader: &Shader3D, texture: &NativeTexture) {
+        shader.set_attribute_buffers(&self.buffer);
+        shader.set_diffuse_texture_active(true);
+        shader.set_specular_texture_active(false);
+
+        unsafe {
+            self.gl.active_texture(TEXTURE0);
+            self.gl.bind_texture(TEXTURE_2D, Some(*texture));
+            shader.set_diffuse_texture(0);
+
+            self.gl.draw_arrays(TRIANGLE_STRIP, 0, self.vertex_count);
+        }
+    }
+}
+
+impl<'a> Drop for RoadStrip<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.delete_buffer(self.buffer);
+        }
+    }
+}
@@ -3,7 +3,7 @@ use std::slice;
 use glow::*;
 use itertools::{izip, Itertools};
 
-use crate::{core::shader::Shader3D, utils::FacingDirection};
+use crate::core::shader::Shader3D;
 
 const MAX_SIZE: f32 = 20.0;
 
@@ -14,7 +14,8 @@ pub struct TexturedSquare<'a> {
 }
 
 impl<'a> TexturedSquare<'a> {
-    pub fn new(gl: &'a Context, width: f32, height: f32, texture_dir: FacingDirection) -> TexturedSquare {
+    /// The texture is turned by texture_rotation radians on each tile
+    pub fn new(gl: &'a Context, width: f32, height: f32, texture_rotation: f32) -> TexturedSquare {
         let width_segment_count = ((width / MAX_SIZE).ceil() as i32).max(1);
         let height_segment_count = ((height / MAX_SIZE).ceil() as i32).max(1);
         let width_remaining_size = width % MAX_SIZE;
@@ -67,31 +68,25 @@ impl<'a> TexturedSquare<'a> {
             .flat_map(|_| [0.0, 1.0, 0.0])
             .collect();
 
-        use FacingDirection::*;
-        let uv_array: Vec<f32> = match texture_dir {
-            North => {
-                (0..position_array.len() / 3)
-                    .flat_map(|i| match i % 4 {
-                        0 => [0.0, 0.0],
-                        1 => [0.0, 1.0],
-                        2 => [1.0, 1.0],
-                        3 => [1.0, 0.0],
-                        _ => [0.0, 0.0],
-                    })
-                    .collect()
-            }
-            West => {
-                (0..position_array.len() / 3)
-                    .flat_map(|i| match i % 4 {
-                        0 => [0.0, 0.0],
-                        1 => [1.0, 0.0],
-                        2 => [1.0, 1.0],
-                        3 => [0.0, 1.0],
-                        _ => [0.0, 0.0],
-                    })
-                    .collect()
-            }
-        };
+        let (rot_sin, rot_cos) = texture_rotation.sin_cos();
+        let uv_array: Vec<f32> = (0..position_array.len() / 3)
+            .flat_map(|i| {
+                let (u, v) = match i % 4 {
+                    0 => (0.0, 0.0),
+                    1 => (0.0, 1.0),
+                    2 => (1.0, 1.0),
+                    3 => (1.0, 0.0),
+                    _ => (0.0, 0.0),
+                };
+
+                // Rotate around the middle of the tile
+                let (u, v) = (u - 0.5, v - 0.5);
+                [
+                    u * rot_cos - v * rot_sin + 0.5,
+                    u * rot_sin + v * rot_cos + 0.5,
+                ]
+            })
+            .collect();
 
         let vertex_array = izip!(
             position_array.iter().tuples(),
@@ -132,3 +127,11 @@ impl<'a> TexturedSquare<'a> {
         }
     }
 }
+
+impl<'a> Drop for TexturedSquare<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.delete_buffer(self.buffer);
+        }
+    }
+}
@@ -7,6 +7,10 @@ use nalgebra::Vector3;
 use crate::core::shader::Shader3D;
 
 const LINE_ACCURACY: i32 = 50;
+// Both ends of the curve are included
+const VERTEX_PAIRS: i32 = LINE_ACCURACY + 1;
+// Width of the road, the corner is scaled up when drawn
+pub const CORNER_TRACK_WIDTH: f32 = 0.1;
 
 pub enum TrackCornerType {
     Right,
@@ -23,34 +27,9 @@ impl<'a> TrackCorner<'a> {
         gl: &'a Context,
         corner_type: TrackCornerType
     ) -> TrackCorner {
-        use TrackCornerType::*;
-        let (enter, control, exit) = match corner_type {
-            UTurn => (
-                Vector3::new(-0.25, -0.0001, 0.0),
-                Vector3::new(0.0, -0.0001, 0.5),
-                Vector3::new(0.25, -0.0001, 0.0),
-            ),
-            Right => (
-                Vector3::new(-0.5, -0.0002, 0.0),
-                Vector3::new(0.0, -0.0002, 0.0),
-                Vector3::new(0.0, -0.0002, -0.5),
-            ),
-        };
-        let track_width = 0.1;
-        let pb = match corner_type {
-            UTurn => 0.5 * enter + 0.5 * exit,
-            Right => Vector3::new(enter.x, 0.0, exit.z),
-        };
-
-        let mut position_array = Vec::with_capacity((LINE_ACCURACY * 6) as usize);
-        let mut normal_array = Vec::with_capacity((LINE_ACCURACY * 6) as usize);
-        for i in 0..LINE_ACCURACY {
-            let t = i as f32 / LINE_ACCURACY as f32;
-            let track_outer = TrackCorner::bezier_curve(&enter, &control, &exit, t);
-
-            let inner_direction = (pb - track_outer).normalize();
-            let track_inner = track_outer + inner_direction * track_width;
-
+        let mut position_array = Vec::with_capacity((VERTEX_PAIRS * 6) as usize);
+        let mut normal_array = Vec::with_capacity((VERTEX_PAIRS * 6) as usize);
+        for (track_outer, track_inner) in TrackCorner::edges(&corner_type) {
             position_array.extend(vec![
                 track_outer.x,
                 track_outer.y,
@@ -62,7 +41,7 @@ impl<'a> TrackCorner<'a> {
             normal_array.extend(vec![0.0, 1.0, 0.0, 0.0, 1.0, 0.0]);
         }
 
-        let uv_array: Vec<f32> = (0..LINE_ACCURACY * 2)
+        let uv_array: Vec<f32> = (0..VERTEX_PAIRS * 2)
             .flat_map(|i| match i % 4 {
                 0 => [1.0, 1.0],
                 1 => [1.0, 0.0],
@@ -95,6 +74,39 @@ impl<'a> TrackCorner<'a> {
         TrackCorner { buffer, gl }
     }
 
+    /// Points along the outer and inner edge of the corner, in the corners local coordinates
+    pub fn edges(corner_type: &TrackCornerType) -> Vec<(Vector3<f32>, Vector3<f32>)> {
+        use TrackCornerType::*;
+        let (enter, control, exit) = match corner_type {
+            UTurn => (
+                Vector3::new(-0.25, -0.0001, 0.0),
+                Vector3::new(0.0, -0.0001, 0.5),
+                Vector3::new(0.25, -0.0001, 0.0),
+            ),
+            Right => (
+                Vector3::new(-0.5, -0.0002, 0.0),
+                Vector3::new(0.0, -0.0002, 0.0),
+                Vector3::new(0.0, -0.0002, -0.5),
+            ),
+        };
+        let pb = match corner_type {
+            UTurn => 0.5 * enter + 0.5 * exit,
+            Right => Vector3::new(enter.x, 0.0, exit.z),
+        };
+
+        (0..VERTEX_PAIRS)
+            .map(|i| {
+                let t = i as f32 / LINE_ACCURACY as f32;
+                let track_outer = TrackCorner::bezier_curve(&enter, &control, &exit, t);
+
+                let inner_direction = (pb - track_outer).normalize();
+                let track_inner = track_outer + inner_direction * CORNER_TRACK_WIDTH;
+
+                (track_outer, track_inner)
+            })
+            .collect()
+    }
+
     pub fn bezier_curve(
         p0: &Vector3<f32>,
         p1: &Vector3<f32>,
@@ -114,7 +126,15 @@ impl<'a> TrackCorner<'a> {
             self.gl.bind_texture(TEXTURE_2D, Some(*texture));
             shader.set_diffuse_texture(0);
 
-            self.gl.draw_arrays(TRIANGLE_STRIP, 0, LINE_ACCURACY * 2);
+            self.gl.draw_arrays(TRIANGLE_STRIP, 0, VERTEX_PAIRS * 2);
+        }
+    }
+}
+
+impl<'a> Drop for TrackCorner<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.delete_buffer(self.buffer);
         }
     }
 }
@@ -1,11 +1,6 @@
-use nalgebra::Vector2;
-
-#[derive(Clone)]
-pub enum FacingDirection {
-    North,
-    West
-}
+pub mod random;
 
+use nalgebra::Vector2;
 pub fn limit(value: f32, from: f32, to: f32) -> f32 {
     value.min(to).max(from)
 }
@@ -33,4 +28,17 @@ pub fn line_contains_point(start: &Vector2<f32>, end: &Vector2<f32>, point: &Vec
     }
 
     true
-}
\ No newline at end of file
+}
+
+/// If the line segments from a1 to a2 and from b1 to b2 cross each other
+pub fn segments_intersect(a1: &Vector2<f32>, a2: &Vector2<f32>, b1: &Vector2<f32>, b2: &Vector2<f32>) -> bool {
+    let cross = |o: &Vector2<f32>, p: &Vector2<f32>, q: &Vector2<f32>| (p - o).perp(&(q - o));
+
+    let d1 = cross(b1, b2, a1);
+    let d2 = cross(b1, b2, a2);
+    let d3 = cross(a1, a2, b1);
+    let d4 = cross(a1, a2, b2);
+
+    ((d1 > 0.0 && d2 <= 0.0) || (d1 < 0.0 && d2 >= 0.0))
+        && ((d3 > 0.0 && d4 <= 0.0) || (d3 < 0.0 && d4 >= 0.0))
+}
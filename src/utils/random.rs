@@ -0,0 +1,60 @@
+/// A small pseudo random number generator (SplitMix64).
+/// It only uses integer arithmetic, so a seed gives the same numbers on every platform
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Random {
+        Random { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Between 0 and 1, including 0 but not 1
+    pub fn next_f32(&mut self) -> f32 {
+        // 24 bits is all the precision an f32 has
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Between from and to, including from but not to
+    pub fn range(&mut self, from: f32, to: f32) -> f32 {
+        from + (to - from) * self.next_f32()
+    }
+
+    /// True with the given probability
+    pub fn chance(&mut self, probability: f32) -> bool {
+        self.next_f32() < probability
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_gives_same_numbers() {
+        let (mut a, mut b) = (Random::new(42), Random::new(42));
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+
+        // The first number of a seed is fixed, a change here would change every generated track
+        assert_eq!(Random::new(0).next_u64(), 0xE220_A839_7B1D_CDAF);
+    }
+
+    #[test]
+    fn numbers_stay_in_range() {
+        let mut random = Random::new(7);
+        for _ in 0..1000 {
+            let value = random.range(-2.0, 3.0);
+            assert!((-2.0..3.0).contains(&value));
+        }
+    }
+}